@@ -5,8 +5,12 @@ use std::fs::File;
 use std::io::BufReader;
 use std::process::ExitCode;
 
+use k0hax_snmpv3::mib::MibCompiler;
 use k0hax_snmpv3::{oids, params};
 
+// Number of hosts queried at the same time.
+const BATCH_CONCURRENCY: usize = 16;
+
 #[allow(dead_code)]
 fn write_json_params(path: &str, data: &Vec<params::Params>) -> Result<()> {
     let mut output = File::create(path)?;
@@ -34,6 +38,22 @@ fn read_oid_json(path: &str) -> Result<oids::OidMap> {
     Ok(o)
 }
 
+fn compile_mibs(dirs: &[String], modules: &[String]) -> Result<oids::OidMap> {
+    let mut compiler = MibCompiler::new();
+    for dir in dirs {
+        compiler.add_search_path(dir);
+    }
+    for module in modules {
+        compiler.load_module(module)?;
+    }
+
+    let mib = compiler.compile();
+    for name in mib.unresolved() {
+        eprintln!("unresolved MIB object: {}", name);
+    }
+    Ok(mib.to_oid_map())
+}
+
 #[allow(dead_code)]
 fn write_oid_json(path: &str, data: &oids::OidMap) -> Result<()> {
     let mut output = File::create(path)?;
@@ -47,8 +67,19 @@ fn get_all(
 ) -> Result<Vec<(String, Vec<params::SnmpResult>)>> {
     let mut retval: Vec<(String, Vec<params::SnmpResult>)> = Vec::new();
 
-    for item in data {
-        retval.push((item.host.clone(), k0hax_snmpv3::run(oid_map.clone(), item)?));
+    for item in k0hax_snmpv3::run_batch(oid_map, data, BATCH_CONCURRENCY) {
+        match item.result {
+            Ok(results) => {
+                eprintln!(
+                    "{}: {} results in {:?}",
+                    item.host,
+                    results.len(),
+                    item.elapsed
+                );
+                retval.push((item.host, results));
+            }
+            Err(error) => eprintln!("{}: failed after {:?}: {}", item.host, item.elapsed, error),
+        }
     }
 
     Ok(retval)
@@ -59,9 +90,11 @@ fn print_vals(data: &Vec<(String, Vec<params::SnmpResult>)>) -> Result<()> {
     for (key, item) in data {
         println!("Host: {}", key);
         for result in item {
-            println!("");
+            println!();
             match &result.result {
-                Some(params::SnmpValue::String(x)) => println!("{}: {}", result.oid, x),
+                Some(params::SnmpValue::OctetString(_)) => {
+                    println!("{}: {}", result.oid, result.result.as_ref().unwrap())
+                }
                 Some(x) => println!("{}: {:?}", result.oid, x),
                 None => return Err(format_err!("No SnmpResult Value found!")),
             };
@@ -97,6 +130,10 @@ struct Cli {
     #[arg(long, requires_all=["username", "oid"], group="config_mode", required=true)]
     hostname: Option<String>,
 
+    /// Local address to send requests from
+    #[arg(long, requires_all=["hostname"])]
+    local_addr: Option<String>,
+
     /// OID to walk
     oid: Option<String>,
 
@@ -118,18 +155,37 @@ struct Cli {
     /// SNMP Privacy Key
     #[arg(requires_all=["privacy_protocol"])]
     priv_key: Option<String>,
+
+    /// Directory to look for MIB modules in
+    #[arg(long, value_name = "DIR")]
+    mib_dir: Vec<String>,
+
+    /// MIB modules naming the OIDs, in place of oids.json
+    #[arg(long, value_name = "MODULE", value_delimiter = ',')]
+    mibs: Vec<String>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
+#[allow(clippy::enum_variant_names)]
 enum AuthTypeArgs {
     Md5Digest,
     Sha1Digest,
+    Sha224Digest,
+    Sha256Digest,
+    Sha384Digest,
+    Sha512Digest,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, ValueEnum)]
 enum PrivTypeArgs {
     Des,
     Aes128,
+    Aes192,
+    Aes256,
+    /// AES-192 with the Reeder key extension used by Cisco
+    Aes192C,
+    /// AES-256 with the Reeder key extension used by Cisco
+    Aes256C,
 }
 
 fn main() -> ExitCode {
@@ -151,32 +207,35 @@ fn main() -> ExitCode {
     } else {
         // Auth and Auth Key
         let real_auth = match auth_type {
-            Some(AuthTypeArgs::Md5Digest) => Some(params::Params::MD5_DIGEST.to_string()),
-            Some(AuthTypeArgs::Sha1Digest) => Some(params::Params::SHA1_DIGEST.to_string()),
+            Some(AuthTypeArgs::Md5Digest) => Some(params::AuthProtocol::Md5),
+            Some(AuthTypeArgs::Sha1Digest) => Some(params::AuthProtocol::Sha1),
+            Some(AuthTypeArgs::Sha224Digest) => Some(params::AuthProtocol::Sha224),
+            Some(AuthTypeArgs::Sha256Digest) => Some(params::AuthProtocol::Sha256),
+            Some(AuthTypeArgs::Sha384Digest) => Some(params::AuthProtocol::Sha384),
+            Some(AuthTypeArgs::Sha512Digest) => Some(params::AuthProtocol::Sha512),
             None => None,
         };
 
-        let auth_key = match cli.auth_key {
-            Some(x) => Some(x.to_string()),
-            None => None,
-        };
+        let auth_key = cli.auth_key.map(|x| x.to_string());
 
         // Priv and Priv Key
         let real_priv = match priv_type {
-            Some(PrivTypeArgs::Des) => Some(params::Params::DES_ENCRYPTION.to_string()),
-            Some(PrivTypeArgs::Aes128) => Some(params::Params::AES128_ENCRYPTION.to_string()),
+            Some(PrivTypeArgs::Des) => Some(params::PrivProtocol::Des),
+            Some(PrivTypeArgs::Aes128) => Some(params::PrivProtocol::Aes128),
+            Some(PrivTypeArgs::Aes192) => Some(params::PrivProtocol::Aes192),
+            Some(PrivTypeArgs::Aes256) => Some(params::PrivProtocol::Aes256),
+            Some(PrivTypeArgs::Aes192C) => Some(params::PrivProtocol::Aes192C),
+            Some(PrivTypeArgs::Aes256C) => Some(params::PrivProtocol::Aes256C),
             None => None,
         };
 
-        let priv_key = match cli.priv_key {
-            Some(x) => Some(x.to_string()),
-            None => None,
-        };
+        let priv_key = cli.priv_key.map(|x| x.to_string());
 
         // Command `Walk`
         let oid_raw: oids::OID = oids::OID {
             oid: oid_arg.clone(),
             name: oid_arg,
+            ..Default::default()
         };
 
         let cmd_param: params::Command = params::Command::Walk { oid: oid_raw };
@@ -192,18 +251,24 @@ fn main() -> ExitCode {
             auth_protocol: real_auth,
             privacy: priv_key,
             privacy_protocol: real_priv,
+            local_addr: cli.local_addr,
+            retry: Default::default(),
             cmd: cmd_param,
         }])
     };
 
-    let oids: oids::OidMap = read_oid_json("oids.json").unwrap();
+    let oids: oids::OidMap = if cli.mibs.is_empty() {
+        read_oid_json("oids.json").unwrap()
+    } else {
+        compile_mibs(&cli.mib_dir, &cli.mibs).unwrap()
+    };
 
-    let data = get_all(oids, my_params).unwrap();
+    let data = get_all(oids.clone(), my_params).unwrap();
 
     for data_row in &data {
         println!("=== {} ===", data_row.0);
-        for row_result in &data_row.1 {
-            println!("{}", row_result);
+        for line in oids.format_results(&data_row.1) {
+            println!("{}", line);
         }
     }
     write_json_vals("output.json", data).unwrap();
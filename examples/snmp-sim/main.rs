@@ -0,0 +1,141 @@
+extern crate k0hax_snmpv3;
+use anyhow::{format_err, Result};
+use clap::Parser;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::thread;
+use std::time::Duration;
+
+use k0hax_snmpv3::{simulator, Agent, UsmUser};
+
+fn read_json_users(path: &str) -> Result<Vec<UsmUser>> {
+    let f = File::open(path)?;
+    let reader = BufReader::new(f);
+
+    // Read the JSON contents of the file as an instance of `Vec<UsmUser>`
+    let u = serde_json::from_reader(reader)?;
+    Ok(u)
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format_err!("odd number of hex digits in {:?}", hex));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(|| format_err!("invalid hex string {:?}", hex))
+        })
+        .collect()
+}
+
+#[derive(Parser, Debug)]
+/// Serves recorded device data as SNMPv3 agents
+///
+/// Every simulated device listens on its own UDP port, counting up from the --listen address, so
+/// hundreds of devices can run on one host.
+#[command(author, version, about)]
+struct Cli {
+    /// Recordings to serve: .snmprec files, get-snmp .json output, or snmpwalk -On output
+    #[arg(required = true)]
+    recordings: Vec<String>,
+
+    /// Address of the first simulated device
+    #[arg(short, long, default_value = "127.0.0.1:16100")]
+    listen: SocketAddr,
+
+    /// JSON file listing the USM users accepted by every device
+    #[arg(short, long, value_name = "FILE")]
+    users: String,
+
+    /// Number of devices started from each recording
+    #[arg(long, default_value_t = 1)]
+    copies: u16,
+
+    /// Engine ID prefix in hex, followed by the device number. Engine IDs are random otherwise
+    #[arg(long)]
+    engine_id: Option<String>,
+
+    /// snmpEngineBoots of every device
+    #[arg(long, default_value_t = 1)]
+    engine_boots: u32,
+
+    /// Delay before each response, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
+
+    /// Probability of ignoring each request, between 0 and 1
+    #[arg(long, default_value_t = 0.0)]
+    loss: f64,
+}
+
+fn start_devices(cli: &Cli) -> Result<Vec<thread::JoinHandle<k0hax_snmpv3::Result<()>>>> {
+    let users = read_json_users(&cli.users)?;
+    let engine_id_prefix = cli.engine_id.as_deref().map(decode_hex).transpose()?;
+
+    let mut recordings = Vec::new();
+    for path in &cli.recordings {
+        recordings.extend(simulator::load(path)?);
+    }
+
+    let mut devices = Vec::new();
+    for recording in recordings {
+        for _ in 0..cli.copies {
+            let device_num = devices.len() as u16;
+            let mut addr = cli.listen;
+            let port = addr
+                .port()
+                .checked_add(device_num)
+                .ok_or_else(|| format_err!("out of ports after {} devices", device_num))?;
+            addr.set_port(port);
+
+            let engine_id = match &engine_id_prefix {
+                Some(prefix) => {
+                    let mut engine_id = prefix.clone();
+                    engine_id.extend_from_slice(&u32::from(device_num).to_be_bytes());
+                    engine_id
+                }
+                None => Agent::random_engine_id(),
+            };
+            let mut agent = Agent::with_engine_id(addr, &engine_id, cli.engine_boots)?;
+            for user in &users {
+                agent.add_user(user)?;
+            }
+            agent
+                .set_latency(Duration::from_millis(cli.latency_ms))
+                .set_loss(cli.loss);
+            recording.clone().register(&mut agent)?;
+
+            let engine_id: String = engine_id.iter().map(|x| format!("{:02x}", x)).collect();
+            println!("{} {} {}", agent.local_addr()?, engine_id, recording.name);
+            devices.push(thread::spawn(move || agent.run()));
+        }
+    }
+
+    Ok(devices)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let devices = match start_devices(&cli) {
+        Ok(devices) => devices,
+        Err(error) => {
+            eprintln!("{:#}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for device in devices {
+        if let Ok(Err(error)) = device.join() {
+            eprintln!("device stopped: {}", error);
+        }
+    }
+
+    ExitCode::FAILURE
+}
@@ -8,11 +8,17 @@
  * for the session.
  *
  * Note: NoAuth is not implemented yet.
+ *
+ * The SHA-2 variants come after NoAuth to keep the existing values stable.
  */
 enum AuthTypeArgs {
   Md5Digest,
   Sha1Digest,
   NoAuth,
+  Sha224Digest,
+  Sha256Digest,
+  Sha384Digest,
+  Sha512Digest,
 };
 typedef uint8_t AuthTypeArgs;
 
@@ -21,11 +27,18 @@ typedef uint8_t AuthTypeArgs;
  * for the session.
  *
  * Note: NoPriv is not implemented yet.
+ *
+ * The `C` variants use the Reeder key extension found on Cisco devices, the others the
+ * Blumenthal one. They come after NoPriv to keep the existing values stable.
  */
 enum PrivTypeArgs {
   Des,
   Aes128,
   NoPriv,
+  Aes192,
+  Aes256,
+  Aes192C,
+  Aes256C,
 };
 typedef uint8_t PrivTypeArgs;
 
@@ -11,10 +11,16 @@ use std::fmt;
 /// for the session.
 ///
 /// Note: NoAuth is not implemented yet.
+///
+/// The SHA-2 variants come after NoAuth to keep the existing values stable.
 pub enum AuthTypeArgs {
     Md5Digest,
     Sha1Digest,
     NoAuth,
+    Sha224Digest,
+    Sha256Digest,
+    Sha384Digest,
+    Sha512Digest,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -23,10 +29,17 @@ pub enum AuthTypeArgs {
 /// for the session.
 ///
 /// Note: NoPriv is not implemented yet.
+///
+/// The `C` variants use the Reeder key extension found on Cisco devices, the others the
+/// Blumenthal one. They come after NoPriv to keep the existing values stable.
 pub enum PrivTypeArgs {
     Des,
     Aes128,
     NoPriv,
+    Aes192,
+    Aes256,
+    Aes192C,
+    Aes256C,
 }
 
 #[derive(Debug, Clone)]
@@ -415,6 +428,7 @@ fn oid_from_strings(oid_str: String, oid_name: String) -> k0hax_snmpv3::oids::OI
     k0hax_snmpv3::oids::OID {
         oid: oid_str,
         name: oid_name,
+        ..Default::default()
     }
 }
 
@@ -437,11 +451,22 @@ fn to_c_snmp_result(item: k0hax_snmpv3::params::SnmpResult) -> SnmpResult {
             let int_ret: *mut i32 = Box::into_raw(Box::new(x));
             int_ret as *mut c_void
         }
-        k0hax_snmpv3::params::SnmpValue::String(x) => {
-            length = x.len();
-            return_type = SnmpType::String;
-            CString::new(x).unwrap().into_raw() as *mut c_void
-        }
+        k0hax_snmpv3::params::SnmpValue::OctetString(x) => match CString::new(x) {
+            Ok(x) => {
+                length = x.as_bytes().len();
+                return_type = SnmpType::String;
+                x.into_raw() as *mut c_void
+            }
+            // Binary strings with NUL octets can't be C strings, so they're handed over like
+            // Opaque values.
+            Err(error) => {
+                return_type = SnmpType::Opaque;
+                let (opaque_value, opaque_len, opaque_cap) = error.into_vec().into_raw_parts();
+                length = opaque_len;
+                capacity = opaque_cap;
+                opaque_value as *mut c_void
+            }
+        },
         k0hax_snmpv3::params::SnmpValue::ObjectId(x) => {
             return_type = SnmpType::ObjectId;
             let (components, oid_length, oid_capacity) = x.components.into_raw_parts();
@@ -677,9 +702,13 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
     let auth_secret = String::from_utf8_lossy(auth_secret_cstr.to_bytes()).to_string();
 
     let auth_protocol = match auth_params.auth_protocol {
-        AuthTypeArgs::Md5Digest => Some(k0hax_snmpv3::params::Params::MD5_DIGEST.to_string()),
-        AuthTypeArgs::Sha1Digest => Some(k0hax_snmpv3::params::Params::SHA1_DIGEST.to_string()),
+        AuthTypeArgs::Md5Digest => Some(k0hax_snmpv3::params::AuthProtocol::Md5),
+        AuthTypeArgs::Sha1Digest => Some(k0hax_snmpv3::params::AuthProtocol::Sha1),
         AuthTypeArgs::NoAuth => None,
+        AuthTypeArgs::Sha224Digest => Some(k0hax_snmpv3::params::AuthProtocol::Sha224),
+        AuthTypeArgs::Sha256Digest => Some(k0hax_snmpv3::params::AuthProtocol::Sha256),
+        AuthTypeArgs::Sha384Digest => Some(k0hax_snmpv3::params::AuthProtocol::Sha384),
+        AuthTypeArgs::Sha512Digest => Some(k0hax_snmpv3::params::AuthProtocol::Sha512),
     };
     /* End Auth */
 
@@ -696,9 +725,13 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
     let priv_secret = String::from_utf8_lossy(priv_secret_cstr.to_bytes()).to_string();
 
     let priv_protocol = match priv_params.priv_protocol {
-        PrivTypeArgs::Des => Some(k0hax_snmpv3::params::Params::DES_ENCRYPTION.to_string()),
-        PrivTypeArgs::Aes128 => Some(k0hax_snmpv3::params::Params::AES128_ENCRYPTION.to_string()),
+        PrivTypeArgs::Des => Some(k0hax_snmpv3::params::PrivProtocol::Des),
+        PrivTypeArgs::Aes128 => Some(k0hax_snmpv3::params::PrivProtocol::Aes128),
         PrivTypeArgs::NoPriv => None,
+        PrivTypeArgs::Aes192 => Some(k0hax_snmpv3::params::PrivProtocol::Aes192),
+        PrivTypeArgs::Aes256 => Some(k0hax_snmpv3::params::PrivProtocol::Aes256),
+        PrivTypeArgs::Aes192C => Some(k0hax_snmpv3::params::PrivProtocol::Aes192C),
+        PrivTypeArgs::Aes256C => Some(k0hax_snmpv3::params::PrivProtocol::Aes256C),
     };
     /* End Priv */
 
@@ -726,6 +759,7 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
             let real_oid: k0hax_snmpv3::oids::OID = k0hax_snmpv3::oids::OID {
                 oid: oid_oid.to_string(),
                 name: oid_name.to_string(),
+                ..Default::default()
             };
             oid_vec.push(real_oid);
             k0hax_snmpv3::params::Command::Get { oids: oid_vec }
@@ -748,6 +782,7 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
             let real_oid: k0hax_snmpv3::oids::OID = k0hax_snmpv3::oids::OID {
                 oid: oid_oid.to_string(),
                 name: oid_name.to_string(),
+                ..Default::default()
             };
             oid_vec.push(real_oid);
             k0hax_snmpv3::params::Command::GetNext { oids: oid_vec }
@@ -770,6 +805,7 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
             let real_oid: k0hax_snmpv3::oids::OID = k0hax_snmpv3::oids::OID {
                 oid: oid_oid.to_string(),
                 name: oid_name.to_string(),
+                ..Default::default()
             };
             k0hax_snmpv3::params::Command::Walk { oid: real_oid }
         }
@@ -778,10 +814,12 @@ pub unsafe extern "C" fn run(oid_map_ptr: *mut c_void, param_ptr: *mut Params) -
     let real_params = k0hax_snmpv3::params::Params {
         user: user,
         host: hostname,
-        auth: Some(auth_secret),
+        auth: auth_protocol.map(|_| auth_secret),
         auth_protocol: auth_protocol,
-        privacy: Some(priv_secret),
+        privacy: priv_protocol.map(|_| priv_secret),
         privacy_protocol: priv_protocol,
+        local_addr: None,
+        retry: Default::default(),
         cmd: real_cmd,
     };
     let retval = k0hax_snmpv3::run(k0hax_oid_map.clone(), real_params);
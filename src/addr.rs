@@ -0,0 +1,187 @@
+use crate::error::{Error, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, ToSocketAddrs};
+
+/**
+ * Default UDP port of an SNMP agent.
+*/
+pub const SNMP_PORT_NUM: u16 = 161;
+
+/**
+ * Resolves a host as written in `Params.host` to a socket address, the first one when the name
+ * has several.
+ *
+ * Accepted forms are `host`, `host:port`, `a.b.c.d`, `a.b.c.d:port`, bare IPv6 literals such as
+ * `2001:db8::1`, and bracketed ones such as `[2001:db8::1]:1161`. Link-local IPv6 addresses can
+ * carry a scope ID, either numeric (`fe80::1%2`) or an interface name (`fe80::1%eth0`).
+ * `default_port` is used when the host doesn't name a port.
+ */
+pub fn resolve(host: &str, default_port: u16) -> Result<SocketAddr> {
+    Ok(resolve_all(host, default_port)?.remove(0))
+}
+
+/**
+ * Resolves `host` like `resolve`, picking the first address of the same family as `local_addr`,
+ * since a socket bound to `local_addr` can't reach the others.
+ */
+pub fn resolve_for(host: &str, default_port: u16, local_addr: &SocketAddr) -> Result<SocketAddr> {
+    resolve_all(host, default_port)?
+        .into_iter()
+        .find(|addr| addr.is_ipv4() == local_addr.is_ipv4())
+        .ok_or_else(|| {
+            Error::config(format!(
+                "no address of the same family as {} found for {:?}",
+                local_addr, host
+            ))
+        })
+}
+
+/**
+ * Resolves `host` like `resolve`, returning every address in the order the resolver gave them.
+ */
+pub fn resolve_all(host: &str, default_port: u16) -> Result<Vec<SocketAddr>> {
+    if let Some(rest) = host.strip_prefix('[') {
+        let end = rest
+            .find(']')
+            .ok_or_else(|| Error::config(format!("missing ']' in address {:?}", host)))?;
+        let port = match &rest[end + 1..] {
+            "" => default_port,
+            port => port
+                .strip_prefix(':')
+                .and_then(|port| port.parse().ok())
+                .ok_or_else(|| Error::config(format!("invalid port in address {:?}", host)))?,
+        };
+
+        return Ok(vec![parse_scoped_ipv6(&rest[..end], port)?]);
+    }
+
+    // More than one colon can only be a bare IPv6 literal, which never carries a port.
+    if host.matches(':').count() > 1 {
+        return Ok(vec![parse_scoped_ipv6(host, default_port)?]);
+    }
+
+    let (name, port) = match host.rsplit_once(':') {
+        Some((name, port)) => {
+            let port = port
+                .parse()
+                .map_err(|_| Error::config(format!("invalid port in address {:?}", host)))?;
+            (name, port)
+        }
+        None => (host, default_port),
+    };
+
+    if let Ok(ip) = name.parse::<Ipv4Addr>() {
+        return Ok(vec![SocketAddr::new(IpAddr::V4(ip), port)]);
+    }
+
+    let addrs: Vec<SocketAddr> = (name, port).to_socket_addrs()?.collect();
+    match addrs.is_empty() {
+        true => Err(Error::config(format!("no address found for {:?}", name))),
+        false => Ok(addrs),
+    }
+}
+
+/**
+ * Returns the unspecified address of the same family as `addr`, with port 0, which is what a
+ * client socket talking to `addr` binds to by default.
+ */
+pub fn unspecified_for(addr: &SocketAddr) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(_) => SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0),
+        SocketAddr::V6(_) => SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), 0),
+    }
+}
+
+fn parse_scoped_ipv6(addr: &str, port: u16) -> Result<SocketAddr> {
+    let (ip, scope_id) = match addr.split_once('%') {
+        Some((ip, scope)) => (ip, scope_id(scope)?),
+        None => (addr, 0),
+    };
+    let ip = ip
+        .parse::<Ipv6Addr>()
+        .map_err(|_| Error::config(format!("invalid IPv6 address {:?}", addr)))?;
+
+    Ok(SocketAddr::V6(SocketAddrV6::new(ip, port, 0, scope_id)))
+}
+
+fn scope_id(scope: &str) -> Result<u32> {
+    if let Ok(index) = scope.parse() {
+        return Ok(index);
+    }
+
+    interface_index(scope)
+        .ok_or_else(|| Error::config(format!("unknown network interface {:?}", scope)))
+}
+
+#[cfg(unix)]
+fn interface_index(name: &str) -> Option<u32> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: `name` is a valid NUL terminated string for the duration of the call.
+    match unsafe { libc::if_nametoindex(name.as_ptr()) } {
+        0 => None,
+        index => Some(index),
+    }
+}
+
+#[cfg(not(unix))]
+fn interface_index(_name: &str) -> Option<u32> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_resolves_ipv4_and_ipv6_literals() {
+        assert_eq!(
+            resolve("192.0.2.1", SNMP_PORT_NUM).unwrap(),
+            "192.0.2.1:161".parse().unwrap()
+        );
+        assert_eq!(
+            resolve("192.0.2.1:1161", SNMP_PORT_NUM).unwrap(),
+            "192.0.2.1:1161".parse().unwrap()
+        );
+        assert_eq!(
+            resolve("2001:db8::1", SNMP_PORT_NUM).unwrap(),
+            "[2001:db8::1]:161".parse().unwrap()
+        );
+        assert_eq!(
+            resolve("[2001:db8::1]:1161", SNMP_PORT_NUM).unwrap(),
+            "[2001:db8::1]:1161".parse().unwrap()
+        );
+        assert!(resolve("[2001:db8::1", SNMP_PORT_NUM).is_err());
+        assert!(resolve("[2001:db8::1]1161", SNMP_PORT_NUM).is_err());
+    }
+
+    #[test]
+    fn it_keeps_ipv6_scope_ids() {
+        let addr = resolve("fe80::1%3", SNMP_PORT_NUM).unwrap();
+        match addr {
+            SocketAddr::V6(addr) => {
+                assert_eq!(addr.scope_id(), 3);
+                assert_eq!(addr.port(), SNMP_PORT_NUM);
+            }
+            SocketAddr::V4(_) => panic!("expected an IPv6 address"),
+        }
+
+        let addr = resolve("[fe80::1%3]:1161", SNMP_PORT_NUM).unwrap();
+        assert_eq!(addr.port(), 1161);
+        assert!(resolve("fe80::1%no-such-interface0", SNMP_PORT_NUM).is_err());
+    }
+
+    #[test]
+    fn it_resolves_to_the_family_of_the_local_address() {
+        let ipv4: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let ipv6: SocketAddr = "[::1]:0".parse().unwrap();
+
+        let addrs = resolve_all("localhost", SNMP_PORT_NUM).unwrap();
+        assert!(addrs.contains(&"127.0.0.1:161".parse().unwrap()));
+        assert_eq!(
+            resolve_for("localhost", SNMP_PORT_NUM, &ipv4).unwrap(),
+            "127.0.0.1:161".parse().unwrap()
+        );
+
+        assert!(resolve_for("192.0.2.1", SNMP_PORT_NUM, &ipv6).is_err());
+        assert!(resolve_for("2001:db8::1", SNMP_PORT_NUM, &ipv6).is_ok());
+    }
+}
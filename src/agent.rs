@@ -0,0 +1,739 @@
+use crate::error::{Error, ReportKind, Result};
+use crate::params::{SnmpValue, UsmUser};
+use crate::usm::{Accepted, Incoming, LocalEngine};
+use snmp_mp::{ObjectIdent, PduErrorStatus, PduType, ScopedPdu, SnmpMsg, VarBind, VarValue};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::ops::Bound;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Room left around the scoped PDU for the message header, the security parameters and the
+// encryption padding, when fitting a GetBulk response in the manager's maximum message size.
+const MSG_OVERHEAD: usize = 256;
+
+/**
+ * Trait implemented by the providers of the objects of a MIB subtree, registered with
+ * `Agent::register`.
+ *
+ * Setting values happens in two phases, like in RFC 3416: `test_set` is called for every binding
+ * of a SetRequest before any `set`, so a request is either applied as a whole or rejected. The
+ * default implementations make the subtree read-only.
+*/
+pub trait MibHandler: Send {
+    /**
+     * Returns the value of the instance `oid`, or `None` if there's no such instance.
+     */
+    fn get(&mut self, oid: &ObjectIdent) -> Option<SnmpValue>;
+
+    /**
+     * Returns the first instance of the subtree that follows `oid` in lexicographic order, and
+     * its value. `oid` may come before the subtree, in which case the first instance of the
+     * subtree is returned.
+     */
+    fn get_next(&mut self, oid: &ObjectIdent) -> Option<(ObjectIdent, SnmpValue)>;
+
+    /**
+     * Checks whether `value` can be assigned to `oid`, returning the error-status to answer with
+     * otherwise.
+     */
+    fn test_set(
+        &mut self,
+        _oid: &ObjectIdent,
+        _value: &SnmpValue,
+    ) -> std::result::Result<(), PduErrorStatus> {
+        Err(PduErrorStatus::NotWritable)
+    }
+
+    /**
+     * Assigns `value` to `oid`, after `test_set` accepted it.
+     */
+    fn set(
+        &mut self,
+        _oid: &ObjectIdent,
+        _value: SnmpValue,
+    ) -> std::result::Result<(), PduErrorStatus> {
+        Err(PduErrorStatus::CommitFailed)
+    }
+}
+
+/**
+ * `MibHandler` serving values kept in memory.
+ *
+ * Only existing instances can be set, with a value of the type they hold, and only if the table
+ * was made writable.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct MibTable {
+    values: BTreeMap<ObjectIdent, SnmpValue>,
+    writable: bool,
+}
+
+impl MibTable {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn writable(mut self, writable: bool) -> Self {
+        self.writable = writable;
+        self
+    }
+
+    pub fn insert(&mut self, oid: ObjectIdent, value: SnmpValue) -> &mut Self {
+        self.values.insert(oid, value);
+        self
+    }
+
+    pub fn remove(&mut self, oid: &ObjectIdent) -> Option<SnmpValue> {
+        self.values.remove(oid)
+    }
+
+    pub fn values(&self) -> &BTreeMap<ObjectIdent, SnmpValue> {
+        &self.values
+    }
+}
+
+impl MibHandler for MibTable {
+    fn get(&mut self, oid: &ObjectIdent) -> Option<SnmpValue> {
+        self.values.get(oid).cloned()
+    }
+
+    fn get_next(&mut self, oid: &ObjectIdent) -> Option<(ObjectIdent, SnmpValue)> {
+        self.values
+            .range((Bound::Excluded(oid), Bound::Unbounded))
+            .next()
+            .map(|(oid, value)| (oid.clone(), value.clone()))
+    }
+
+    fn test_set(
+        &mut self,
+        oid: &ObjectIdent,
+        value: &SnmpValue,
+    ) -> std::result::Result<(), PduErrorStatus> {
+        match self.values.get(oid) {
+            // An instance keeps the type it was inserted with (RFC 3416, section 4.2.5).
+            Some(current) if std::mem::discriminant(current) != std::mem::discriminant(value) => {
+                Err(PduErrorStatus::WrongType)
+            }
+            Some(_) if self.writable => Ok(()),
+            Some(_) => Err(PduErrorStatus::NotWritable),
+            None => Err(PduErrorStatus::NoCreation),
+        }
+    }
+
+    fn set(
+        &mut self,
+        oid: &ObjectIdent,
+        value: SnmpValue,
+    ) -> std::result::Result<(), PduErrorStatus> {
+        self.values.insert(oid.clone(), value);
+        Ok(())
+    }
+}
+
+/**
+ * Embeddable SNMPv3 agent answering Get, GetNext, GetBulk and Set requests.
+ *
+ * The agent is the authoritative engine: it answers discovery with its engine ID, boots and time,
+ * and authenticates and decrypts requests with the keys of the users added with `add_user`.
+ * Requests are dispatched to the `MibHandler` registered for the subtree holding each variable.
+ * There is no view-based access control: every configured user can read and write every
+ * registered subtree.
+*/
+pub struct Agent {
+    socket: UdpSocket,
+    engine: LocalEngine,
+    // Sorted by subtree. Subtrees don't overlap.
+    handlers: Vec<(ObjectIdent, Box<dyn MibHandler>)>,
+    latency: Duration,
+    loss: f64,
+    failed_responses: u32,
+    buf: [u8; SnmpMsg::MAX_UDP_PACKET_SIZE],
+}
+
+impl Agent {
+    /**
+     * Binds an agent to `local_addr`, e.g. `0.0.0.0:161`, with a random engine ID.
+     *
+     * Managers cache engine IDs, so long-lived agents should be given a stable one with
+     * `with_engine_id`.
+     */
+    pub fn bind<A: ToSocketAddrs>(local_addr: A) -> Result<Self> {
+        Self::with_engine_id(local_addr, &Self::random_engine_id(), 1)
+    }
+
+    /**
+     * Binds an agent to `local_addr` with the given engine ID and boots.
+     *
+     * `engine_boots` should be incremented, and persisted, every time the agent restarts, as
+     * managers reject responses from an engine that went back in time.
+     */
+    pub fn with_engine_id<A: ToSocketAddrs>(
+        local_addr: A,
+        engine_id: &[u8],
+        engine_boots: u32,
+    ) -> Result<Self> {
+        let engine = LocalEngine::new(engine_id, engine_boots)?;
+        let socket = UdpSocket::bind(local_addr)?;
+        let buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+
+        Ok(Self {
+            socket,
+            engine,
+            handlers: Vec::new(),
+            latency: Duration::ZERO,
+            loss: 0.0,
+            failed_responses: 0,
+            buf,
+        })
+    }
+
+    /**
+     * Returns a random engine ID in the administratively assigned octets format of RFC 3411, to
+     * be persisted and passed to `with_engine_id`.
+     */
+    pub fn random_engine_id() -> Vec<u8> {
+        LocalEngine::random_engine_id()
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn engine_id(&self) -> &[u8] {
+        self.engine.engine_id()
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.engine.engine_boots()
+    }
+
+    pub fn engine_time(&self) -> u32 {
+        self.engine.engine_time()
+    }
+
+    /**
+     * Accepts requests from `user`, at the user's security level.
+     */
+    pub fn add_user(&mut self, user: &UsmUser) -> Result<&mut Self> {
+        let engine_id = self.engine.engine_id().to_vec();
+        self.engine.add_user(&engine_id, user)?;
+        Ok(self)
+    }
+
+    /**
+     * Registers `handler` for the variables under `subtree`, a dotted OID like
+     * `1.3.6.1.4.1.99999`. Subtrees can't overlap.
+     */
+    pub fn register<H>(&mut self, subtree: &str, handler: H) -> Result<&mut Self>
+    where
+        H: MibHandler + 'static,
+    {
+        let subtree =
+            ObjectIdent::from_str(subtree).map_err(|_| Error::InvalidOid(subtree.to_string()))?;
+        if let Some((registered, _)) = self.handlers.iter().find(|(registered, _)| {
+            contains(registered, &subtree) || contains(&subtree, registered)
+        }) {
+            return Err(Error::config(format!(
+                "subtree {} overlaps the registered subtree {}",
+                subtree, registered
+            )));
+        }
+
+        let index = self
+            .handlers
+            .partition_point(|(registered, _)| *registered < subtree);
+        self.handlers.insert(index, (subtree, Box::new(handler)));
+        Ok(self)
+    }
+
+    /**
+     * Delays every response and report by `latency`, to simulate a distant or busy device.
+     *
+     * Requests are answered one at a time, so the delays of back-to-back requests add up.
+     */
+    pub fn set_latency(&mut self, latency: Duration) -> &mut Self {
+        self.latency = latency;
+        self
+    }
+
+    /**
+     * Ignores each incoming message with probability `loss`, between 0 and 1, to simulate packet
+     * loss.
+     */
+    pub fn set_loss(&mut self, loss: f64) -> &mut Self {
+        self.loss = loss.clamp(0.0, 1.0);
+        self
+    }
+
+    /**
+     * Returns how many requests were dropped for the reason named by the USM statistics counter
+     * `kind`. `ReportKind::Other` counts messages that couldn't be decoded.
+     */
+    pub fn dropped(&self, kind: ReportKind) -> u32 {
+        self.engine.dropped(kind)
+    }
+
+    /**
+     * Returns how many responses and reports couldn't be encoded or sent, e.g. because the
+     * requester was unreachable. The agent keeps serving other requests when that happens.
+     */
+    pub fn failed_responses(&self) -> u32 {
+        self.failed_responses
+    }
+
+    /**
+     * Answers requests until receiving from the socket fails.
+     */
+    pub fn run(&mut self) -> Result<()> {
+        loop {
+            self.recv_until(None)?;
+        }
+    }
+
+    /**
+     * Answers the requests arriving within `timeout`, for agents driven by another loop.
+     */
+    pub fn poll(&mut self, timeout: Duration) -> Result<()> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<()> {
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(());
+                    }
+
+                    Some(remaining)
+                }
+                None => None,
+            };
+
+            self.socket.set_read_timeout(timeout)?;
+            let (len, source) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(error)
+                    if error.kind() == ErrorKind::WouldBlock
+                        || error.kind() == ErrorKind::TimedOut =>
+                {
+                    return Ok(());
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let mut encoded_msg = self.buf[..len].to_vec();
+            self.answer(&mut encoded_msg, source);
+        }
+    }
+
+    // A request that can't be answered must not stop the agent from serving the others.
+    fn answer(&mut self, encoded_msg: &mut [u8], source: SocketAddr) {
+        if self.handle_msg(encoded_msg, source).is_err() {
+            self.failed_responses = self.failed_responses.saturating_add(1);
+        }
+    }
+
+    // Only errors encoding and sending responses and reports are returned, anything wrong with
+    // the request itself gets it dropped.
+    fn handle_msg(&mut self, encoded_msg: &mut [u8], source: SocketAddr) -> Result<()> {
+        if self.loss > 0.0 && rand::random::<f64>() < self.loss {
+            return Ok(());
+        }
+
+        let request = match self.engine.process(encoded_msg)? {
+            Incoming::Accepted(request) => request,
+            Incoming::Dropped { report } => {
+                if let Some(report) = report {
+                    thread::sleep(self.latency);
+                    self.socket.send_to(&report, source)?;
+                }
+                return Ok(());
+            }
+        };
+
+        let scoped_pdu = match request.msg.scoped_pdu_data.plaintext() {
+            Some(scoped_pdu) => scoped_pdu,
+            None => return Ok(()),
+        };
+
+        let max_size = (request.msg.max_size() as usize).min(SnmpMsg::MAX_UDP_PACKET_SIZE);
+        let (var_binds, error_status, error_index) = match scoped_pdu.pdu_type() {
+            PduType::GetRequest => (self.get(scoped_pdu.var_binds()), PduErrorStatus::NoError, 0),
+            PduType::GetNextRequest => (
+                self.get_next(scoped_pdu.var_binds()),
+                PduErrorStatus::NoError,
+                0,
+            ),
+            PduType::GetBulkRequest => (
+                self.get_bulk(scoped_pdu, max_size.saturating_sub(MSG_OVERHEAD)),
+                PduErrorStatus::NoError,
+                0,
+            ),
+            PduType::SetRequest => match self.set(scoped_pdu.var_binds()) {
+                Ok(()) => (scoped_pdu.var_binds().to_vec(), PduErrorStatus::NoError, 0),
+                Err((error_status, error_index)) => {
+                    (scoped_pdu.var_binds().to_vec(), error_status, error_index)
+                }
+            },
+            // Notifications and responses are meant for managers.
+            _ => return Ok(()),
+        };
+
+        let mut encoded_response =
+            self.encode_response(&request, var_binds, error_status, error_index)?;
+        if encoded_response.len() > max_size {
+            encoded_response =
+                self.encode_response(&request, Vec::new(), PduErrorStatus::TooBig, 0)?;
+        }
+
+        thread::sleep(self.latency);
+        self.socket.send_to(&encoded_response, source)?;
+        Ok(())
+    }
+
+    fn encode_response(
+        &mut self,
+        request: &Accepted,
+        var_binds: Vec<VarBind>,
+        error_status: PduErrorStatus,
+        error_index: u32,
+    ) -> Result<Vec<u8>> {
+        let mut response = SnmpMsg::new(request.msg.id());
+        if let (Some(request_pdu), Some(response_pdu)) = (
+            request.msg.scoped_pdu_data.plaintext(),
+            response.scoped_pdu_data.plaintext_mut(),
+        ) {
+            response_pdu
+                .set_pdu_type(PduType::Response)
+                .set_request_id(request_pdu.request_id())
+                .set_engine_id(request_pdu.engine_id())
+                .set_context_name(request_pdu.context_name())
+                .set_error_status(error_status)
+                .set_error_index(error_index)
+                .set_var_binds(var_binds);
+        }
+
+        self.engine.encode_response(request, &mut response)
+    }
+
+    fn get(&mut self, requested: &[VarBind]) -> Vec<VarBind> {
+        requested
+            .iter()
+            .map(|var_bind| {
+                let name = var_bind.name();
+                let value = match self.owner(name) {
+                    Some(handler) => handler
+                        .get(name)
+                        .map(VarValue::from)
+                        .unwrap_or(VarValue::NoSuchInstance),
+                    None => VarValue::NoSuchObject,
+                };
+
+                VarBind::with_value(name.clone(), value)
+            })
+            .collect()
+    }
+
+    fn get_next(&mut self, requested: &[VarBind]) -> Vec<VarBind> {
+        requested
+            .iter()
+            .map(|var_bind| self.next(var_bind.name()))
+            .collect()
+    }
+
+    // Answers with the non-repeaters, then with rows of successors of the repeaters until
+    // max-repetitions rows are sent, the MIB view ends or the response would exceed `max_len`.
+    //
+    // max-repetitions comes from the request, so rows stop being built once they fill `max_len`,
+    // rather than being trimmed afterwards.
+    fn get_bulk(&mut self, scoped_pdu: &ScopedPdu, max_len: usize) -> Vec<VarBind> {
+        let requested = scoped_pdu.var_binds();
+        let non_repeaters = (scoped_pdu.error_status() as usize).min(requested.len());
+        let max_repetitions = scoped_pdu.error_index();
+
+        let mut sizing_pdu = scoped_pdu.clone();
+        sizing_pdu.set_var_binds(Vec::new());
+        let empty_len = sizing_pdu.encode().len();
+
+        let mut var_binds = self.get_next(&requested[..non_repeaters]);
+        let mut len = empty_len + var_binds_len(&mut sizing_pdu, &var_binds, empty_len);
+        let mut row: Vec<VarBind> = requested[non_repeaters..].to_vec();
+        for _ in 0..max_repetitions {
+            if len > max_len
+                || row.is_empty()
+                || row.iter().all(|x| *x.value() == VarValue::EndOfMibView)
+            {
+                break;
+            }
+
+            row = self.get_next(&row);
+            len += var_binds_len(&mut sizing_pdu, &row, empty_len);
+            var_binds.extend_from_slice(&row);
+        }
+
+        fit(var_binds, scoped_pdu, max_len)
+    }
+
+    fn set(&mut self, requested: &[VarBind]) -> std::result::Result<(), (PduErrorStatus, u32)> {
+        for (index, var_bind) in requested.iter().enumerate() {
+            let value = SnmpValue::from(var_bind.value().clone());
+            let tested = match self.owner(var_bind.name()) {
+                Some(handler) => handler.test_set(var_bind.name(), &value),
+                None => Err(PduErrorStatus::NoCreation),
+            };
+            tested.map_err(|error_status| (error_status, index as u32 + 1))?;
+        }
+
+        for (index, var_bind) in requested.iter().enumerate() {
+            let value = SnmpValue::from(var_bind.value().clone());
+            let committed = match self.owner(var_bind.name()) {
+                Some(handler) => handler.set(var_bind.name(), value),
+                None => Err(PduErrorStatus::CommitFailed),
+            };
+            committed.map_err(|error_status| (error_status, index as u32 + 1))?;
+        }
+
+        Ok(())
+    }
+
+    // Returns the variable binding following `oid` in the MIB view, or `endOfMibView`.
+    fn next(&mut self, oid: &ObjectIdent) -> VarBind {
+        for (subtree, handler) in self.handlers.iter_mut() {
+            // Skips the subtrees that come entirely before `oid`.
+            if *subtree < *oid && !contains(subtree, oid) {
+                continue;
+            }
+
+            if let Some((next_oid, value)) = handler.get_next(oid) {
+                if next_oid > *oid && contains(subtree, &next_oid) {
+                    return VarBind::with_value(next_oid, value.into());
+                }
+            }
+        }
+
+        VarBind::with_value(oid.clone(), VarValue::EndOfMibView)
+    }
+
+    fn owner(&mut self, oid: &ObjectIdent) -> Option<&mut Box<dyn MibHandler>> {
+        self.handlers
+            .iter_mut()
+            .find(|(subtree, _)| contains(subtree, oid))
+            .map(|(_, handler)| handler)
+    }
+}
+
+fn contains(subtree: &ObjectIdent, oid: &ObjectIdent) -> bool {
+    oid.components().starts_with(subtree.components())
+}
+
+// Returns the octets `var_binds` add to the encoding of `sizing_pdu`, which has none of its own.
+// Length octets growing with the list are left out, and accounted for by `fit`.
+fn var_binds_len(sizing_pdu: &mut ScopedPdu, var_binds: &[VarBind], empty_len: usize) -> usize {
+    sizing_pdu.set_var_binds(var_binds.iter().cloned());
+    sizing_pdu.encode().len().saturating_sub(empty_len)
+}
+
+// Drops variable bindings from the end until the scoped PDU holding them encodes to at most
+// `max_len` octets.
+fn fit(mut var_binds: Vec<VarBind>, scoped_pdu: &ScopedPdu, max_len: usize) -> Vec<VarBind> {
+    let mut response_pdu = scoped_pdu.clone();
+    loop {
+        response_pdu.set_var_binds(var_binds.iter().cloned());
+        let len = response_pdu.encode().len();
+        if len <= max_len || var_binds.is_empty() {
+            return var_binds;
+        }
+
+        // Shrinks in proportion to the excess, by at least one binding.
+        let keep = var_binds.len() * max_len / len;
+        var_binds.truncate(keep.min(var_binds.len() - 1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oids::{OidMap, OID};
+    use crate::params::{AuthProtocol, PrivProtocol, SnmpValue};
+    use crate::session::Session;
+    use crate::{client, msg_factory, Params, SnmpSession};
+    use snmp_usm::{DesPrivKey, Md5};
+
+    fn user() -> UsmUser {
+        UsmUser {
+            user: "poller".to_string(),
+            auth: Some("maplesyrup".to_string()),
+            auth_protocol: Some(AuthProtocol::Sha512),
+            privacy: Some("pancakes1".to_string()),
+            privacy_protocol: Some(PrivProtocol::Aes192),
+        }
+    }
+
+    fn oid(oid: &str) -> OID {
+        OID {
+            oid: oid.to_string(),
+            name: oid.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_answers_requests_from_registered_handlers() {
+        let mut system = MibTable::new().writable(true);
+        system
+            .insert(
+                ObjectIdent::from_str("1.3.6.1.2.1.1.1.0").unwrap(),
+                SnmpValue::OctetString(b"test agent".to_vec()),
+            )
+            .insert(
+                ObjectIdent::from_str("1.3.6.1.2.1.1.4.0").unwrap(),
+                SnmpValue::OctetString(b"noc@example.com".to_vec()),
+            );
+        let mut interfaces = MibTable::new();
+        for index in 1..=3 {
+            interfaces.insert(
+                ObjectIdent::from_str(&format!("1.3.6.1.2.1.2.2.1.1.{}", index)).unwrap(),
+                SnmpValue::Int(index),
+            );
+        }
+
+        let mut agent = Agent::bind("127.0.0.1:0").unwrap();
+        agent
+            .add_user(&user())
+            .unwrap()
+            .register("1.3.6.1.2.1.1", system)
+            .unwrap()
+            .register("1.3.6.1.2.1.2", interfaces)
+            .unwrap();
+        assert!(agent.register("1.3.6.1.2.1.1.9", MibTable::new()).is_err());
+        let host = agent.local_addr().unwrap().to_string();
+        thread::spawn(move || agent.run());
+
+        let user = user();
+        let params = Params {
+            user: user.user,
+            host,
+            auth: user.auth,
+            auth_protocol: user.auth_protocol,
+            privacy: user.privacy,
+            privacy_protocol: user.privacy_protocol,
+            local_addr: None,
+            retry: Default::default(),
+            cmd: crate::Command::Get { oids: Vec::new() },
+        };
+        let mut session = SnmpSession::new(&params).unwrap();
+
+        let results = session
+            .get(vec![oid("1.3.6.1.2.1.1.1.0"), oid("1.3.6.1.2.1.1.2.0")])
+            .unwrap();
+        assert_eq!(results[0].value().unwrap().to_string(), "test agent");
+        assert!(results[1].outcome.is_exception());
+
+        let oid_map = OidMap { oids: Vec::new() };
+        let walked = session.walk(&oid_map, oid("1.3.6.1.2.1")).unwrap();
+        assert_eq!(walked.len(), 5);
+        let bulk_walked = session
+            .bulk_walk(&oid_map, oid("1.3.6.1.2.1.2"), 2)
+            .unwrap();
+        assert_eq!(bulk_walked.len(), 3);
+
+        session
+            .set(vec![(
+                oid("1.3.6.1.2.1.1.4.0"),
+                SnmpValue::OctetString(b"ops@example.com".to_vec()),
+            )])
+            .unwrap();
+        let contact = session.get(vec![oid("1.3.6.1.2.1.1.4.0")]).unwrap();
+        assert_eq!(contact[0].value().unwrap().to_string(), "ops@example.com");
+
+        let wrong_type = session.set(vec![(oid("1.3.6.1.2.1.1.4.0"), SnmpValue::Int(9))]);
+        match wrong_type {
+            Err(Error::ErrorStatus(error)) => {
+                assert_eq!(error.status, PduErrorStatus::WrongType)
+            }
+            other => panic!("expected wrongType, got {:?}", other),
+        }
+        let contact = session.get(vec![oid("1.3.6.1.2.1.1.4.0")]).unwrap();
+        assert_eq!(contact[0].value().unwrap().to_string(), "ops@example.com");
+
+        let read_only = session.set(vec![(oid("1.3.6.1.2.1.2.2.1.1.1"), SnmpValue::Int(9))]);
+        assert!(matches!(read_only, Err(Error::ErrorStatus(_))));
+    }
+
+    #[test]
+    fn it_keeps_serving_when_a_response_cant_be_sent() {
+        let mut agent = Agent::bind("127.0.0.1:0").unwrap();
+        agent.add_user(&user()).unwrap();
+
+        let mut session = Session::<Md5, DesPrivKey<Md5>, u32>::undiscovered();
+        let mut discovery = msg_factory::create_reportable_msg(&mut session);
+        let mut encoded_msg = client::encode_msg(&mut discovery, &mut session).unwrap();
+
+        // Sending to the broadcast address fails without SO_BROADCAST.
+        agent.answer(&mut encoded_msg, "255.255.255.255:161".parse().unwrap());
+        assert_eq!(agent.failed_responses(), 1);
+    }
+
+    // Serves an endless table, counting the instances asked for.
+    struct Counter {
+        calls: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl MibHandler for Counter {
+        fn get(&mut self, _oid: &ObjectIdent) -> Option<SnmpValue> {
+            None
+        }
+
+        fn get_next(&mut self, oid: &ObjectIdent) -> Option<(ObjectIdent, SnmpValue)> {
+            let count = self
+                .calls
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let next = ObjectIdent::from_slice(&[1, 3, 6, 1, 4, 1, 99999, count as u64 + 1]);
+            (next > *oid).then_some((next, SnmpValue::Int(1)))
+        }
+    }
+
+    #[test]
+    fn it_stops_bulk_rows_at_the_response_size() {
+        let calls = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut agent = Agent::bind("127.0.0.1:0").unwrap();
+        agent
+            .register(
+                "1.3.6.1.4.1.99999",
+                Counter {
+                    calls: calls.clone(),
+                },
+            )
+            .unwrap();
+
+        let mut scoped_pdu = ScopedPdu::new(1);
+        scoped_pdu
+            .set_pdu_type(PduType::GetBulkRequest)
+            .set_error_index(u32::MAX)
+            .set_var_binds(vec![VarBind::new(ObjectIdent::from_slice(&[
+                1, 3, 6, 1, 4, 1, 99999,
+            ]))]);
+        let var_binds = agent.get_bulk(&scoped_pdu, 1_000);
+
+        assert!(!var_binds.is_empty());
+        // Only the row crossing the limit is built in excess, then trimmed.
+        let calls = calls.load(std::sync::atomic::Ordering::Relaxed);
+        assert!(calls > var_binds.len() && calls <= var_binds.len() + 5);
+    }
+
+    #[test]
+    fn it_trims_bulk_responses_to_fit() {
+        let var_binds =
+            vec![VarBind::with_value(ObjectIdent::from_slice(&[1, 3, 6]), VarValue::Int(1)); 100];
+        let fitted = fit(var_binds, &ScopedPdu::new(1), 200);
+        assert!(!fitted.is_empty());
+        let mut scoped_pdu = ScopedPdu::new(1);
+        scoped_pdu.set_var_binds(fitted);
+        assert!(scoped_pdu.encode().len() <= 200);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::addr;
+use crate::auth::Digest;
+use crate::client::{decode_msg, encode_msg, needs_resync, report_error};
+use crate::error::TimeoutError;
+use crate::error::{Error, Result};
+use crate::retry::RetryPolicy;
+use crate::session::{Session, Step};
+use snmp_mp::SnmpMsg;
+use snmp_usm::PrivKey;
+use std::net::SocketAddr;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::time::{self, Instant};
+
+/**
+ * Asynchronous counterpart of `Client`, built on tokio.
+ *
+ * Messages are built and checked exactly like with `Client`; only the socket operations are
+ * awaited, so a single runtime can keep many requests to different agents in flight.
+*/
+pub struct AsyncClient {
+    pub socket: UdpSocket,
+    retry_policy: RetryPolicy,
+    buf: Vec<u8>,
+}
+
+impl AsyncClient {
+    /**
+     * Constructs a new `AsyncClient` and connects it to the remote address using UDP.
+     *
+     * The socket is bound to the unspecified address of the remote's family.
+     */
+    pub async fn new<A: ToSocketAddrs>(remote_addr: A) -> Result<AsyncClient> {
+        Self::with_local_addr(remote_addr, None).await
+    }
+
+    /**
+     * Constructs a new `AsyncClient` bound to `local_addr`, or to the unspecified address of the
+     * remote's family if `None`, and connects it to the remote address.
+     */
+    pub async fn with_local_addr<A: ToSocketAddrs>(
+        remote_addr: A,
+        local_addr: Option<SocketAddr>,
+    ) -> Result<AsyncClient> {
+        let remote_addr = tokio::net::lookup_host(remote_addr)
+            .await?
+            .next()
+            .ok_or_else(|| Error::config("no remote address to connect to"))?;
+        let local_addr = local_addr.unwrap_or_else(|| addr::unspecified_for(&remote_addr));
+        if local_addr.is_ipv4() != remote_addr.is_ipv4() {
+            return Err(Error::config(format!(
+                "local address {} and remote address {} are of different families",
+                local_addr, remote_addr
+            )));
+        }
+
+        let socket = UdpSocket::bind(local_addr).await?;
+        socket.connect(remote_addr).await?;
+
+        Ok(Self {
+            socket,
+            retry_policy: RetryPolicy::default(),
+            buf: vec![0; SnmpMsg::MAX_UDP_PACKET_SIZE],
+        })
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<&mut Self> {
+        retry_policy.validate()?;
+        self.retry_policy = retry_policy;
+        Ok(self)
+    }
+
+    /**
+     * Sends a request and returns the response on success.
+     *
+     * Reports are handled like in `Client::send_request`.
+     */
+    pub async fn send_request<D, P, S>(
+        &mut self,
+        msg: &mut SnmpMsg,
+        session: &mut Session<'_, D, P, S>,
+    ) -> Result<SnmpMsg>
+    where
+        D: Digest,
+        P: PrivKey<Salt = S>,
+        S: Step + Copy,
+    {
+        let plaintext_msg = msg.clone();
+
+        let response_msg = self.send_discovery(msg, session).await?;
+        if !needs_resync(&response_msg)? {
+            return Ok(response_msg);
+        }
+
+        *msg = plaintext_msg;
+        let response_msg = self.send_discovery(msg, session).await?;
+        match report_error(&response_msg) {
+            Some(report) => Err(report.into()),
+            None => Ok(response_msg),
+        }
+    }
+
+    /**
+     * Sends a request and returns the response, even if it's a Report PDU, retransmitting it as
+     * allowed by the retry policy.
+     */
+    pub async fn send_discovery<D, P, S>(
+        &mut self,
+        msg: &mut SnmpMsg,
+        session: &mut Session<'_, D, P, S>,
+    ) -> Result<SnmpMsg>
+    where
+        D: Digest,
+        P: PrivKey<Salt = S>,
+        S: Step + Copy,
+    {
+        let plaintext_msg = msg.clone();
+        let attempts = self.retry_policy.retries.saturating_add(1);
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                // Encrypting again picks the next salt and the current engine time.
+                *msg = plaintext_msg.clone();
+            }
+
+            let encoded_msg = encode_msg(msg, session)?;
+            self.socket.send(&encoded_msg).await?;
+
+            let deadline = Instant::now() + self.retry_policy.timeout(attempt);
+            if let Some(response_msg) = self.recv_msg(msg.id(), deadline, session).await? {
+                return Ok(response_msg);
+            }
+        }
+
+        Err(TimeoutError { attempts }.into())
+    }
+
+    // Waits until `deadline` for the response to `sent_msg_id`. Returns `None` on timeout.
+    async fn recv_msg<D, P, S>(
+        &mut self,
+        sent_msg_id: u32,
+        deadline: Instant,
+        session: &mut Session<'_, D, P, S>,
+    ) -> Result<Option<SnmpMsg>>
+    where
+        D: Digest,
+        P: PrivKey,
+    {
+        loop {
+            let len = match time::timeout_at(deadline, self.socket.recv(&mut self.buf)).await {
+                Ok(result) => result?,
+                Err(_) => return Ok(None),
+            };
+
+            if let Some(msg) = decode_msg(&mut self.buf[..len], sent_msg_id, session)? {
+                return Ok(Some(msg));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snmp_usm::{DesPrivKey, Md5};
+
+    #[tokio::test]
+    async fn it_retransmits_until_attempts_run_out() {
+        let agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut client = AsyncClient::new(agent.local_addr().unwrap()).await.unwrap();
+        let result = client.set_retry_policy(RetryPolicy {
+            timeout_ms: 0,
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(Error::Config(_))));
+        client
+            .set_retry_policy(RetryPolicy {
+                timeout_ms: 20,
+                retries: 1,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let error = match Session::<Md5, DesPrivKey<Md5>, u32>::new_async(&mut client, b"usr").await
+        {
+            Ok(_) => panic!("discovery should time out"),
+            Err(error) => error,
+        };
+        assert!(matches!(
+            error,
+            Error::Timeout(TimeoutError { attempts: 2 })
+        ));
+    }
+}
@@ -0,0 +1,448 @@
+use crate::error::{Error, Result};
+use digest::{BlockInput, FixedOutput, Reset, Update};
+use hmac::{Hmac, Mac, NewMac};
+use snmp_usm::{Md5, SecurityError, SecurityParams, Sha1};
+use std::marker::PhantomData;
+use std::ops::Range;
+
+pub use sha2::{Sha224, Sha256, Sha384, Sha512};
+
+type SecurityResult<T> = std::result::Result<T, SecurityError>;
+
+// Password to key algorithm (RFC 3414, section A.2, reused by RFC 7860 for SHA-2):
+//
+// 1- Forming a string of length 1,048,576 octets by repeating the value of the password as often
+//    as necessary, truncating accordingly, and using the resulting string as the input to the
+//    hashing algorithm. The resulting digest, termed "digest1", is used in the next step.
+// 2- A second string is formed by concatenating digest1, the SNMP engine's snmpEngineID value, and
+//    digest1. This string is used as input to the hashing algorithm.
+const ONE_MEGABYTE: usize = 1_048_576;
+const PASSWD_BUF_LEN: usize = 64;
+
+// Duration in seconds.
+const TIME_WINDOW: u32 = 150;
+
+const SEQUENCE_TAG: u8 = 0x30;
+const INTEGER_TAG: u8 = 0x02;
+const OCTET_STRING_TAG: u8 = 0x04;
+
+/**
+ * Message-digest algorithm usable for USM authentication.
+ *
+ * `AUTH_PARAMS_LEN` is the length the HMAC is truncated to in msgAuthenticationParameters: 12
+ * octets for HMAC-MD5-96 and HMAC-SHA-96 (RFC 3414), and 16, 24, 32 and 48 octets for the
+ * SHA-2 based protocols of RFC 7860.
+*/
+pub trait Digest: Update + BlockInput + FixedOutput + Reset + Default + Clone {
+    const AUTH_PARAMS_LEN: usize;
+}
+
+impl Digest for Md5 {
+    const AUTH_PARAMS_LEN: usize = 12;
+}
+
+impl Digest for Sha1 {
+    const AUTH_PARAMS_LEN: usize = 12;
+}
+
+impl Digest for Sha224 {
+    const AUTH_PARAMS_LEN: usize = 16;
+}
+
+impl Digest for Sha256 {
+    const AUTH_PARAMS_LEN: usize = 24;
+}
+
+impl Digest for Sha384 {
+    const AUTH_PARAMS_LEN: usize = 32;
+}
+
+impl Digest for Sha512 {
+    const AUTH_PARAMS_LEN: usize = 48;
+}
+
+/**
+ * Key derived from a user password and an authoritative engine ID.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct LocalizedKey<'a, D> {
+    bytes: Vec<u8>,
+    _digest_type: PhantomData<&'a D>,
+}
+
+impl<'a, D> LocalizedKey<'a, D> {
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<'a, D> LocalizedKey<'a, D>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+{
+    /**
+     * Creates a key from a user password and an authoritative engine ID.
+     *
+     * Fails with a `ConfigError` if `passwd` is empty.
+     */
+    pub fn new(passwd: &[u8], engine_id: &[u8]) -> Result<Self> {
+        Ok(Self {
+            bytes: localize(&password_to_key::<D>(passwd)?, engine_id, D::default()),
+            _digest_type: PhantomData,
+        })
+    }
+}
+
+// Returns "digest1", the hash of the password repeated over one megabyte.
+pub(crate) fn password_to_key<D>(passwd: &[u8]) -> Result<Vec<u8>>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+{
+    check_passwd(passwd)?;
+    Ok(hash_passwd::<D>(passwd))
+}
+
+// Hashes the password repeated over one megabyte. `passwd` must not be empty.
+pub(crate) fn hash_passwd<D>(passwd: &[u8]) -> Vec<u8>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+{
+    let mut passwd_buf = vec![0; PASSWD_BUF_LEN];
+    let mut passwd_index = 0;
+    let passwd_len = passwd.len();
+    let mut hashing_fn = D::default();
+
+    for _ in (0..ONE_MEGABYTE).step_by(PASSWD_BUF_LEN) {
+        for byte in passwd_buf.iter_mut() {
+            *byte = passwd[passwd_index % passwd_len];
+            passwd_index += 1;
+        }
+
+        hashing_fn.update(&passwd_buf);
+    }
+
+    hashing_fn.finalize_fixed().to_vec()
+}
+
+// An empty password can't be repeated to fill the hashed string.
+pub(crate) fn check_passwd(passwd: &[u8]) -> Result<()> {
+    match passwd.is_empty() {
+        true => Err(Error::config("password for localized key cannot be empty")),
+        false => Ok(()),
+    }
+}
+
+// Hashes digest1 | engine ID | digest1.
+pub(crate) fn localize<D>(key: &[u8], engine_id: &[u8], mut hashing_fn: D) -> Vec<u8>
+where
+    D: Update + FixedOutput,
+{
+    hashing_fn.update(key);
+    hashing_fn.update(engine_id);
+    hashing_fn.update(key);
+    hashing_fn.finalize_fixed().to_vec()
+}
+
+/**
+ * Authentication key used to check data integrity and data origin.
+ *
+ * Unlike `snmp_usm::AuthKey`, the length of the authentication parameters follows the digest, so
+ * it supports the SHA-2 protocols of RFC 7860 as well as HMAC-MD5-96 and HMAC-SHA-96.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AuthKey<'a, D> {
+    localized_key: LocalizedKey<'a, D>,
+}
+
+impl<'a, D> AuthKey<'a, D> {
+    pub fn new(localized_key: LocalizedKey<'a, D>) -> Self {
+        Self { localized_key }
+    }
+}
+
+impl<'a, D> AuthKey<'a, D>
+where
+    D: Digest,
+{
+    /**
+     * Returns zeroed authentication parameters of the right length, to be set in the security
+     * parameters before the message is encoded and authenticated.
+     */
+    pub fn auth_params_placeholder() -> Vec<u8> {
+        vec![0x0; D::AUTH_PARAMS_LEN]
+    }
+
+    /**
+     * Authenticates an incoming SNMP message and checks its timeliness.
+     *
+     * `local_engine_id`, `local_engine_boots` and `local_engine_time` are the local notion of the
+     * authoritative engine. When `local_engine_id` matches the engine ID of the message, the
+     * checks of an authoritative engine are performed.
+     */
+    pub fn auth_in_msg(
+        &self,
+        msg: &mut [u8],
+        local_engine_id: &[u8],
+        local_engine_boots: u32,
+        local_engine_time: u32,
+    ) -> SecurityResult<()> {
+        let security_params = self.verify(msg)?;
+        validate_timeliness(
+            &security_params,
+            local_engine_id,
+            local_engine_boots,
+            local_engine_time,
+        )
+    }
+
+    /**
+     * Authenticates a message sent by the authoritative engine, like a response or a report, and
+     * checks its timeliness the way a non-authoritative engine does (RFC 3414 section 3.2 step
+     * 7b): boots and times later than `engine_boots` and `engine_time` are accepted, for the
+     * caller to adopt.
+     */
+    pub fn auth_in_response(
+        &self,
+        msg: &mut [u8],
+        engine_boots: u32,
+        engine_time: u32,
+    ) -> SecurityResult<()> {
+        let security_params = self.verify(msg)?;
+        validate_non_authoritative_timeliness(&security_params, engine_boots, engine_time)
+    }
+
+    // Checks the authentication parameters of `msg` and returns its security parameters.
+    fn verify(&self, msg: &mut [u8]) -> SecurityResult<SecurityParams> {
+        let (security_params_range, auth_params_range) = Self::params_ranges(msg)?;
+
+        let saved_auth_params = msg[auth_params_range.clone()].to_vec();
+        msg[auth_params_range.clone()].copy_from_slice(&Self::auth_params_placeholder());
+        let auth_params = self.hmac(msg);
+        msg[auth_params_range].copy_from_slice(&saved_auth_params);
+
+        if saved_auth_params != auth_params {
+            return Err(SecurityError::WrongAuthParams);
+        }
+
+        SecurityParams::decode(&msg[security_params_range])
+    }
+
+    /**
+     * Authenticates an outgoing SNMP message whose authentication parameters were set to
+     * `auth_params_placeholder`.
+     */
+    pub fn auth_out_msg(&self, msg: &mut [u8]) -> SecurityResult<()> {
+        let (_, auth_params_range) = Self::params_ranges(msg)?;
+        let auth_params = self.hmac(msg);
+        msg[auth_params_range].copy_from_slice(&auth_params);
+
+        Ok(())
+    }
+
+    // Returns the security parameters and authentication parameters ranges of an SNMP message.
+    fn params_ranges(msg: &[u8]) -> SecurityResult<(Range<usize>, Range<usize>)> {
+        let mut pos_finder = PosFinder::new(msg);
+
+        pos_finder.step_into(SEQUENCE_TAG)?; // Message sequence
+        pos_finder.skip(INTEGER_TAG)?; // Version
+        pos_finder.skip(SEQUENCE_TAG)?; // Header data
+        let security_params_range = pos_finder.step_into(OCTET_STRING_TAG)?;
+
+        let auth_params_range = Self::find_auth_params_range(&mut pos_finder)
+            .map_err(|_| SecurityError::MalformedSecurityParams)?;
+
+        if auth_params_range.len() != D::AUTH_PARAMS_LEN {
+            return Err(SecurityError::WrongAuthParams);
+        }
+
+        Ok((security_params_range, auth_params_range))
+    }
+
+    fn find_auth_params_range(pos_finder: &mut PosFinder) -> SecurityResult<Range<usize>> {
+        pos_finder.step_into(SEQUENCE_TAG)?; // Security parameters
+        pos_finder.skip(OCTET_STRING_TAG)?; // Authoritative engine ID
+        pos_finder.skip(INTEGER_TAG)?; // Authoritative engine boots
+        pos_finder.skip(INTEGER_TAG)?; // Authoritative engine time
+        pos_finder.skip(OCTET_STRING_TAG)?; // Username
+
+        pos_finder.step_into(OCTET_STRING_TAG) // Authentication parameters
+    }
+
+    // Calculates the truncated HMAC of the SNMP message.
+    fn hmac(&self, msg: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<D>::new_varkey(self.localized_key.bytes()).unwrap();
+        mac.update(msg);
+        let bytes = mac.finalize().into_bytes();
+
+        bytes[..D::AUTH_PARAMS_LEN].to_vec()
+    }
+}
+
+fn validate_timeliness(
+    security_params: &SecurityParams,
+    local_engine_id: &[u8],
+    local_engine_boots: u32,
+    local_engine_time: u32,
+) -> SecurityResult<()> {
+    if security_params.engine_id() != local_engine_id {
+        return validate_non_authoritative_timeliness(
+            security_params,
+            local_engine_boots,
+            local_engine_time,
+        );
+    }
+
+    // Checks performed by the authoritative engine.
+    let msg_boots = security_params.engine_boots();
+    let msg_time = security_params.engine_time();
+    if local_engine_boots >= SecurityParams::ENGINE_BOOTS_MAX
+        || msg_boots != local_engine_boots
+        || msg_time.abs_diff(local_engine_time) > TIME_WINDOW
+    {
+        return Err(SecurityError::NotInTimeWindow);
+    }
+
+    Ok(())
+}
+
+// Checks performed by a non-authoritative engine, whose boots and time are its notion of the
+// authoritative engine's.
+fn validate_non_authoritative_timeliness(
+    security_params: &SecurityParams,
+    engine_boots: u32,
+    engine_time: u32,
+) -> SecurityResult<()> {
+    let msg_boots = security_params.engine_boots();
+    let msg_time = security_params.engine_time();
+    if msg_boots >= SecurityParams::ENGINE_BOOTS_MAX
+        || msg_boots < engine_boots
+        || (msg_boots == engine_boots
+            && msg_time < engine_time
+            && engine_time - msg_time > TIME_WINDOW)
+    {
+        return Err(SecurityError::NotInTimeWindow);
+    }
+
+    Ok(())
+}
+
+// Minimal BER walker used to locate the authentication parameters inside an encoded message.
+struct PosFinder<'b> {
+    buf: &'b [u8],
+    pos: usize,
+}
+
+impl<'b> PosFinder<'b> {
+    fn new(buf: &'b [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    // Moves to the content of the element at the current position and returns its range.
+    fn step_into(&mut self, tag: u8) -> SecurityResult<Range<usize>> {
+        let range = self.content_range(tag)?;
+        self.pos = range.start;
+        Ok(range)
+    }
+
+    // Moves past the element at the current position.
+    fn skip(&mut self, tag: u8) -> SecurityResult<()> {
+        self.pos = self.content_range(tag)?.end;
+        Ok(())
+    }
+
+    fn content_range(&self, tag: u8) -> SecurityResult<Range<usize>> {
+        let mut pos = self.pos;
+        if self.buf.get(pos) != Some(&tag) {
+            return Err(SecurityError::MalformedMsg);
+        }
+        pos += 1;
+
+        let first = *self.buf.get(pos).ok_or(SecurityError::MalformedMsg)?;
+        pos += 1;
+
+        let len = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let num_octets = (first & 0x7f) as usize;
+            if num_octets == 0 || num_octets > 4 {
+                return Err(SecurityError::MalformedMsg);
+            }
+
+            let octets = self
+                .buf
+                .get(pos..pos + num_octets)
+                .ok_or(SecurityError::MalformedMsg)?;
+            pos += num_octets;
+            octets.iter().fold(0, |len, &x| (len << 8) | x as usize)
+        };
+
+        if pos + len > self.buf.len() {
+            return Err(SecurityError::MalformedMsg);
+        }
+
+        Ok(pos..pos + len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::priv_key::WithPasswd;
+    use snmp_usm::DesPrivKey;
+
+    const ENGINE_ID: [u8; 12] = [0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x02];
+
+    #[test]
+    fn it_constructs_localized_key_with_md5() {
+        let result = LocalizedKey::<Md5>::new(b"maplesyrup", &ENGINE_ID).unwrap();
+
+        let expected = [
+            0x52, 0x6f, 0x5e, 0xed, 0x9f, 0xcc, 0xe2, 0x6f, 0x89, 0x64, 0xc2, 0x93, 0x07, 0x87,
+            0xd8, 0x2b,
+        ];
+        assert_eq!(result.bytes(), expected);
+    }
+
+    #[test]
+    fn it_constructs_localized_key_with_sha1() {
+        let result = LocalizedKey::<Sha1>::new(b"maplesyrup", &ENGINE_ID).unwrap();
+
+        let expected = [
+            0x66, 0x95, 0xfe, 0xbc, 0x92, 0x88, 0xe3, 0x62, 0x82, 0x23, 0x5f, 0xc7, 0x15, 0x1f,
+            0x12, 0x84, 0x97, 0xb3, 0x8f, 0x3f,
+        ];
+        assert_eq!(result.bytes(), expected);
+    }
+
+    #[test]
+    fn it_rejects_empty_passwords() {
+        let result = LocalizedKey::<Sha1>::new(b"", &ENGINE_ID);
+        assert!(matches!(result, Err(Error::Config(_))));
+        let result = DesPrivKey::<Md5>::with_passwd(b"", &ENGINE_ID);
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn it_authenticates_with_truncated_sha2_macs() {
+        let mut security_params = SecurityParams::new();
+        security_params
+            .set_engine_id(&ENGINE_ID)
+            .set_username(b"user")
+            .set_auth_params(&AuthKey::<Sha256>::auth_params_placeholder());
+
+        let mut msg = snmp_mp::SnmpMsg::new(1);
+        msg.set_security_params(&security_params.encode());
+        msg.set_auth_flag();
+        let mut encoded_msg = msg.encode();
+
+        let key = AuthKey::new(LocalizedKey::<Sha256>::new(b"maplesyrup", &ENGINE_ID).unwrap());
+        key.auth_out_msg(&mut encoded_msg).unwrap();
+        key.auth_in_msg(&mut encoded_msg, &ENGINE_ID, 0, 0).unwrap();
+
+        let other_key = AuthKey::new(LocalizedKey::<Sha256>::new(b"pancakes", &ENGINE_ID).unwrap());
+        assert_eq!(
+            other_key.auth_in_msg(&mut encoded_msg, &ENGINE_ID, 0, 0),
+            Err(SecurityError::WrongAuthParams)
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use crate::error::Result;
+use crate::oids::OidMap;
+use crate::params::{Params, SnmpResult};
+use crate::snmp_session::SnmpSession;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/**
+ * Outcome of one `Params` entry run by `run_batch`.
+*/
+#[derive(Debug)]
+pub struct BatchResult {
+    pub host: String,
+    /// Time spent on the host, discovery included.
+    pub elapsed: Duration,
+    pub result: Result<Vec<SnmpResult>>,
+}
+
+/**
+ * Runs every entry of `params` like `run` does, on at most `concurrency` worker threads.
+ *
+ * A failing host doesn't stop the batch: each entry gets its own `BatchResult`, in the same order
+ * as `params`.
+*/
+pub fn run_batch(oid_map: OidMap, params: Vec<Params>, concurrency: usize) -> Vec<BatchResult> {
+    let workers = concurrency.clamp(1, params.len().max(1));
+    let next = AtomicUsize::new(0);
+    let jobs: Vec<Mutex<Option<Params>>> = params
+        .into_iter()
+        .map(|params| Mutex::new(Some(params)))
+        .collect();
+    let results: Vec<Mutex<Option<BatchResult>>> = jobs.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let params = match jobs.get(index) {
+                    Some(job) => job.lock().unwrap().take().unwrap(),
+                    None => break,
+                };
+
+                let host = params.host.clone();
+                let start = Instant::now();
+                // Sessions check their `Params`, so invalid entries fail here without a panic.
+                let result = SnmpSession::new(&params)
+                    .and_then(|mut session| session.execute(&oid_map, params.cmd));
+                *results[index].lock().unwrap() = Some(BatchResult {
+                    host,
+                    elapsed: start.elapsed(),
+                    result,
+                });
+            });
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.into_inner().unwrap().unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::params::{AuthProtocol, Command, PrivProtocol};
+    use crate::retry::RetryPolicy;
+
+    fn params_for(host: String) -> Params {
+        Params {
+            user: "usr".to_string(),
+            host,
+            auth: None,
+            auth_protocol: None,
+            privacy: None,
+            privacy_protocol: None,
+            local_addr: None,
+            retry: RetryPolicy {
+                timeout_ms: 20,
+                retries: 0,
+                ..Default::default()
+            },
+            cmd: Command::Get { oids: Vec::new() },
+        }
+    }
+
+    #[test]
+    fn it_reports_failures_per_host() {
+        let agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let host = agent.local_addr().unwrap().to_string();
+
+        let mut invalid = params_for(host.clone());
+        invalid.privacy = Some("maplesyrup".to_string());
+        invalid.privacy_protocol = Some(PrivProtocol::Des);
+
+        // Rejected before any key is derived from it.
+        let mut empty_auth = params_for(host.clone());
+        empty_auth.auth = Some(String::new());
+        empty_auth.auth_protocol = Some(AuthProtocol::Sha256);
+
+        let oid_map = OidMap { oids: Vec::new() };
+        let params = vec![
+            params_for(host.clone()),
+            invalid,
+            params_for(host.clone()),
+            empty_auth,
+        ];
+        let results = run_batch(oid_map, params, 2);
+
+        assert_eq!(results.len(), 4);
+        assert!(results.iter().all(|result| result.host == host));
+        for index in [0, 2] {
+            let error = results[index].result.as_ref().unwrap_err();
+            assert!(matches!(error, Error::Timeout(_)));
+        }
+        for index in [1, 3] {
+            let error = results[index].result.as_ref().unwrap_err();
+            assert!(matches!(error, Error::Config(_)));
+        }
+    }
+}
@@ -1,46 +1,91 @@
+use crate::addr;
+use crate::auth::{AuthKey, Digest};
+use crate::error::{Error, Result};
+use crate::error::{ReportError, ReportKind, TimeoutError};
+use crate::retry::RetryPolicy;
 use crate::session::{Session, Step};
-use anyhow::Result;
-use snmp_mp::{self, SnmpMsg};
-use snmp_usm::{Digest, PrivKey, SecurityParams};
+use snmp_mp::{self, PduType, SnmpMsg, VarValue};
+use snmp_usm::{PrivKey, SecurityParams};
 use std::{
-    io::{Error, ErrorKind},
-    net::{ToSocketAddrs, UdpSocket},
-    time::Duration,
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Instant,
 };
 
-const MAX_RETRIES: u32 = 2;
-// Timeout in seconds.
-const TIMEOUT: u64 = 3;
-
 /**
- * Client to send and receive SNMP messages.
- *
- * Only supports IPv4.
+ * Client to send and receive SNMP messages over UDP, on IPv4 or IPv6.
 */
 pub struct Client {
     pub socket: UdpSocket,
+    retry_policy: RetryPolicy,
     buf: [u8; SnmpMsg::MAX_UDP_PACKET_SIZE],
 }
 
 impl Client {
     /**
      * Constructs a new `Client` and connects it to the remote address using UDP.
+     *
+     * The socket is bound to the unspecified address of the remote's family.
      */
     pub fn new<A: ToSocketAddrs>(remote_addr: A) -> Result<Client> {
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        Self::with_local_addr(remote_addr, None)
+    }
+
+    /**
+     * Constructs a new `Client` bound to `local_addr`, or to the unspecified address of the
+     * remote's family if `None`, and connects it to the remote address.
+     *
+     * Binding to a given local address picks the interface and source address used by pollers
+     * on multi-homed hosts.
+     */
+    pub fn with_local_addr<A: ToSocketAddrs>(
+        remote_addr: A,
+        local_addr: Option<SocketAddr>,
+    ) -> Result<Client> {
+        let remote_addr = remote_addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| Error::config("no remote address to connect to"))?;
+        let local_addr = local_addr.unwrap_or_else(|| addr::unspecified_for(&remote_addr));
+        if local_addr.is_ipv4() != remote_addr.is_ipv4() {
+            return Err(Error::config(format!(
+                "local address {} and remote address {} are of different families",
+                local_addr, remote_addr
+            )));
+        }
 
-        let timeout = Some(Duration::from_secs(TIMEOUT));
-        socket.set_read_timeout(timeout)?;
-        socket.set_write_timeout(timeout)?;
+        let socket = UdpSocket::bind(local_addr)?;
+        let retry_policy = RetryPolicy::default();
+        socket.set_write_timeout(Some(retry_policy.timeout(0)))?;
         socket.connect(remote_addr)?;
 
         let buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
 
-        Ok(Self { socket, buf })
+        Ok(Self {
+            socket,
+            retry_policy,
+            buf,
+        })
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<&mut Self> {
+        retry_policy.validate()?;
+        self.socket
+            .set_write_timeout(Some(retry_policy.timeout(0)))?;
+        self.retry_policy = retry_policy;
+        Ok(self)
     }
 
     /**
      * Sends a request and returns the response on success.
+     *
+     * If the agent answers with a Report PDU, a `ReportError` naming the reported counter is
+     * returned. A `usmStatsNotInTimeWindows` report resynchronizes the session's engine boots
+     * and time, and the request is sent once more before giving up.
      */
     pub fn send_request<D, P, S>(
         &mut self,
@@ -52,128 +97,349 @@ impl Client {
         P: PrivKey<Salt = S>,
         S: Step + Copy,
     {
-        self.send_msg(msg, session)?;
-        let response_msg = self.recv_msg(msg.id(), session)?;
-        Ok(response_msg)
+        let plaintext_msg = msg.clone();
+
+        let response_msg = self.send_discovery(msg, session)?;
+        if !needs_resync(&response_msg)? {
+            return Ok(response_msg);
+        }
+
+        // The report carried the agent's current engine boots and time, which `recv_msg` stored
+        // in the session, so encrypting and authenticating again should put the request inside
+        // the time window.
+        *msg = plaintext_msg;
+        let response_msg = self.send_discovery(msg, session)?;
+        match report_error(&response_msg) {
+            Some(report) => Err(report.into()),
+            None => Ok(response_msg),
+        }
     }
 
-    fn send_msg<D, P, S>(&self, msg: &mut SnmpMsg, session: &mut Session<D, P, S>) -> Result<usize>
+    /**
+     * Sends a request and returns the response, even if it's a Report PDU.
+     *
+     * Used for engine discovery, where a `usmStatsUnknownEngineIDs` report is the expected
+     * answer.
+     *
+     * When no response arrives in time the request is retransmitted as allowed by the retry
+     * policy, and a `TimeoutError` is returned once every attempt has timed out.
+     */
+    pub fn send_discovery<D, P, S>(
+        &mut self,
+        msg: &mut SnmpMsg,
+        session: &mut Session<D, P, S>,
+    ) -> Result<SnmpMsg>
     where
         D: Digest,
         P: PrivKey<Salt = S>,
         S: Step + Copy,
     {
-        let mut security_params = SecurityParams::new();
-        security_params
-            .set_auth_params_placeholder()
-            .set_username(session.username())
-            .set_engine_id(session.engine_id())
-            .set_engine_boots(session.engine_boots())
-            .set_engine_time(session.engine_time());
-
-        if let Some((priv_key, salt)) = session.priv_key_and_salt() {
-            msg.encrypt_scoped_pdu(|encoded_scoped_pdu| {
-                let (encrypted_scoped_pdu, priv_params) =
-                    priv_key.encrypt(encoded_scoped_pdu, &security_params, salt);
-                security_params.set_priv_params(&priv_params);
-
-                encrypted_scoped_pdu
-            });
-        }
-
-        msg.set_security_params(&security_params.encode());
-
-        if session.auth_key().is_some() {
-            msg.set_auth_flag();
-        }
-
-        let mut encoded_msg = msg.encode();
-
-        if let Some(auth_key) = session.auth_key() {
-            auth_key.auth_out_msg(&mut encoded_msg)?;
-        }
-
-        for _ in 0..MAX_RETRIES {
-            let result = self.socket.send(&encoded_msg);
-            if let Err(ref error) = result {
-                if error.kind() == ErrorKind::WouldBlock {
-                    continue;
-                }
-
-                if let Some(inner_err) = error.get_ref() {
-                    let err_text = format!("{}", inner_err);
-                    return Err(Error::new(error.kind(), err_text).into());
-                } else {
-                    return Err(Error::new(error.kind(), "").into());
-                }
+        let plaintext_msg = msg.clone();
+        let attempts = self.retry_policy.retries.saturating_add(1);
+        for attempt in 0..attempts {
+            if attempt > 0 {
+                // Encrypting again picks the next salt and the current engine time.
+                *msg = plaintext_msg.clone();
             }
 
-            return Ok(result?);
+            self.send_msg(msg, session)?;
+            let timeout = self.retry_policy.timeout(attempt);
+            if let Some(response_msg) =
+                self.recv_msg(msg.id(), Instant::now() + timeout, session)?
+            {
+                return Ok(response_msg);
+            }
         }
 
-        Err(Error::new(ErrorKind::TimedOut, "unable to send message").into())
+        Err(TimeoutError { attempts }.into())
+    }
+
+    /**
+     * Sends a message without waiting for an answer, as for SNMPv2-Trap PDUs.
+     */
+    pub fn send_msg<D, P, S>(
+        &self,
+        msg: &mut SnmpMsg,
+        session: &mut Session<D, P, S>,
+    ) -> Result<usize>
+    where
+        D: Digest,
+        P: PrivKey<Salt = S>,
+        S: Step + Copy,
+    {
+        let encoded_msg = encode_msg(msg, session)?;
+        Ok(self.socket.send(&encoded_msg)?)
     }
 
+    // Waits until `deadline` for the response to `sent_msg_id`. Returns `None` on timeout.
     fn recv_msg<D, P, S>(
         &mut self,
         sent_msg_id: u32,
+        deadline: Instant,
         session: &mut Session<D, P, S>,
-    ) -> Result<SnmpMsg>
+    ) -> Result<Option<SnmpMsg>>
     where
         D: Digest,
         P: PrivKey,
     {
-        for _ in 0..MAX_RETRIES {
-            let result = self.socket.recv(&mut self.buf);
-
-            match result {
-                Err(error) => {
-                    if error.kind() == ErrorKind::WouldBlock {
-                        continue;
-                    }
-
-                    if let Some(inner_err) = error.get_ref() {
-                        let err_text = format!("{}", inner_err);
-                        return Err(Error::new(error.kind(), err_text).into());
-                    } else {
-                        return Err(Error::new(error.kind(), "").into());
-                    }
-                }
-                Ok(len) => {
-                    let encoded_msg = &mut self.buf[..len];
-                    if let Some(auth_key) = session.auth_key() {
-                        auth_key.auth_in_msg(
-                            encoded_msg,
-                            session.engine_id(),
-                            session.engine_boots(),
-                            session.engine_time(),
-                        )?;
-                    }
-
-                    let mut msg = SnmpMsg::decode(encoded_msg)?;
-
-                    if msg.id() != sent_msg_id {
-                        continue;
-                    }
-
-                    let security_params = SecurityParams::decode(msg.security_params())?;
-                    if let Some(priv_key) = session.priv_key() {
-                        msg.decrypt_scoped_pdu(|encrypted_scoped_pdu| {
-                            priv_key
-                                .decrypt(encrypted_scoped_pdu, &security_params)
-                                .ok()
-                        })?;
-                    }
-
-                    session
-                        .set_engine_boots(security_params.engine_boots())
-                        .set_engine_time(security_params.engine_time());
-
-                    return Ok(msg);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+
+            self.socket.set_read_timeout(Some(remaining))?;
+            let len = match self.socket.recv(&mut self.buf) {
+                Ok(len) => len,
+                Err(error)
+                    if error.kind() == ErrorKind::WouldBlock
+                        || error.kind() == ErrorKind::TimedOut =>
+                {
+                    return Ok(None);
                 }
+                Err(error) => return Err(error.into()),
+            };
+
+            let encoded_msg = &mut self.buf[..len];
+            if let Some(msg) = decode_msg(encoded_msg, sent_msg_id, session)? {
+                return Ok(Some(msg));
             }
         }
+    }
+}
+
+/**
+ * Adds the security parameters to `msg`, encrypting and authenticating it as the session
+ * requires, and returns the encoded message ready to be sent.
+ */
+pub(crate) fn encode_msg<D, P, S>(
+    msg: &mut SnmpMsg,
+    session: &mut Session<D, P, S>,
+) -> Result<Vec<u8>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    encode_msg_with_privacy(msg, session, true)
+}
+
+/**
+ * Like `encode_msg`, but leaves the scoped PDU in plaintext unless `encrypt` is set. Used for
+ * `usmStatsNotInTimeWindows` reports, which are authenticated but not encrypted.
+ */
+pub(crate) fn encode_msg_with_privacy<D, P, S>(
+    msg: &mut SnmpMsg,
+    session: &mut Session<D, P, S>,
+    encrypt: bool,
+) -> Result<Vec<u8>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let mut security_params = SecurityParams::new();
+    if session.auth_key().is_some() {
+        security_params.set_auth_params(&AuthKey::<D>::auth_params_placeholder());
+    }
+    security_params
+        .set_username(session.username())
+        .set_engine_id(session.engine_id())
+        .set_engine_boots(session.engine_boots())
+        .set_engine_time(session.engine_time());
+
+    if let Some((priv_key, salt)) = session.priv_key_and_salt().filter(|_| encrypt) {
+        msg.encrypt_scoped_pdu(|encoded_scoped_pdu| {
+            let (encrypted_scoped_pdu, priv_params) =
+                priv_key.encrypt(encoded_scoped_pdu, &security_params, salt);
+            security_params.set_priv_params(&priv_params);
+
+            encrypted_scoped_pdu
+        });
+    }
+
+    msg.set_security_params(&security_params.encode());
+
+    if session.auth_key().is_some() {
+        msg.set_auth_flag();
+    }
+
+    let mut encoded_msg = msg.encode();
+
+    if let Some(auth_key) = session.auth_key() {
+        auth_key.auth_out_msg(&mut encoded_msg)?;
+    }
+
+    Ok(encoded_msg)
+}
+
+/**
+ * Decodes, authenticates and decrypts a received message, and updates the session's engine
+ * boots and time from it.
+ *
+ * Returns `None` if the message answers another request than `sent_msg_id`.
+ */
+pub(crate) fn decode_msg<D, P, S>(
+    encoded_msg: &mut [u8],
+    sent_msg_id: u32,
+    session: &mut Session<D, P, S>,
+) -> Result<Option<SnmpMsg>>
+where
+    D: Digest,
+    P: PrivKey,
+{
+    let mut msg = SnmpMsg::decode(encoded_msg)?;
+
+    // Retransmissions keep the message ID, so a late answer to an earlier attempt is accepted and
+    // only responses to earlier requests are dropped.
+    if msg.id() != sent_msg_id {
+        return Ok(None);
+    }
+
+    // Reports about unknown users or wrong digests are sent without authentication, so they
+    // can't be checked against the auth key.
+    let authenticated = msg.is_auth();
+    if let Some(auth_key) = session.auth_key() {
+        if authenticated {
+            // The agent is authoritative: a later boots or time, after it rebooted, is accepted
+            // and adopted below, so a notInTimeWindow report leads to a resync.
+            auth_key.auth_in_response(
+                encoded_msg,
+                session.engine_boots(),
+                session.engine_time(),
+            )?;
+        } else if !is_unencrypted_report(&msg) {
+            return Err(Error::Unauthenticated);
+        }
+    }
+
+    let security_params = SecurityParams::decode(msg.security_params())?;
+    if let Some(priv_key) = session.priv_key() {
+        msg.decrypt_scoped_pdu(|encrypted_scoped_pdu| {
+            priv_key
+                .decrypt(encrypted_scoped_pdu, &security_params)
+                .ok()
+        })?;
+    }
+
+    if authenticated || session.auth_key().is_none() {
+        session
+            .set_engine_boots(security_params.engine_boots())
+            .set_engine_time(security_params.engine_time());
+    }
 
-        Err(Error::new(ErrorKind::TimedOut, "unable to receive message").into())
+    Ok(Some(msg))
+}
+
+/**
+ * Tells whether `response` is a `usmStatsNotInTimeWindows` report, after which the request should
+ * be sent again. Any other report is returned as an error.
+ */
+pub(crate) fn needs_resync(response: &SnmpMsg) -> Result<bool> {
+    match report_error(response) {
+        None => Ok(false),
+        Some(report) if report.kind == ReportKind::NotInTimeWindow => Ok(true),
+        Some(report) => Err(report.into()),
+    }
+}
+
+fn is_unencrypted_report(msg: &SnmpMsg) -> bool {
+    msg.scoped_pdu_data
+        .plaintext()
+        .map(|scoped_pdu| scoped_pdu.pdu_type() == PduType::Report)
+        .unwrap_or(false)
+}
+
+/**
+ * Returns the error described by `msg` if it's a Report PDU.
+ */
+pub fn report_error(msg: &SnmpMsg) -> Option<ReportError> {
+    let scoped_pdu = msg.scoped_pdu_data.plaintext()?;
+    if scoped_pdu.pdu_type() != PduType::Report {
+        return None;
+    }
+
+    let report = match scoped_pdu.var_binds().first() {
+        Some(var_bind) => ReportError {
+            kind: ReportKind::from_oid(var_bind.name().components()),
+            oid: var_bind.name().to_string(),
+            counter: match var_bind.value() {
+                VarValue::Counter(x) => Some(*x),
+                _ => None,
+            },
+        },
+        None => ReportError {
+            kind: ReportKind::Other,
+            oid: String::new(),
+            counter: None,
+        },
+    };
+
+    Some(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snmp_mp::{ObjectIdent, VarBind};
+    use snmp_usm::{DesPrivKey, Md5};
+
+    #[test]
+    fn it_identifies_usm_stats_reports() {
+        let mut msg = SnmpMsg::new(1);
+        if let Some(scoped_pdu) = msg.scoped_pdu_data.plaintext_mut() {
+            let not_in_time_windows = ObjectIdent::from_slice(&[1, 3, 6, 1, 6, 3, 15, 1, 1, 2, 0]);
+            scoped_pdu
+                .set_pdu_type(PduType::Report)
+                .push_var_bind(VarBind::with_value(
+                    not_in_time_windows,
+                    VarValue::Counter(7),
+                ));
+        }
+
+        let report = report_error(&msg).unwrap();
+        assert_eq!(report.kind, ReportKind::NotInTimeWindow);
+        assert_eq!(report.oid, "1.3.6.1.6.3.15.1.1.2.0");
+        assert_eq!(report.counter, Some(7));
+    }
+
+    #[test]
+    fn it_ignores_responses() {
+        let mut msg = SnmpMsg::new(1);
+        if let Some(scoped_pdu) = msg.scoped_pdu_data.plaintext_mut() {
+            scoped_pdu.set_pdu_type(PduType::Response);
+        }
+
+        assert!(report_error(&msg).is_none());
+    }
+
+    #[test]
+    fn it_retransmits_until_attempts_run_out() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let mut client = Client::new(agent.local_addr().unwrap()).unwrap();
+        client
+            .set_retry_policy(RetryPolicy {
+                timeout_ms: 20,
+                retries: 2,
+                ..Default::default()
+            })
+            .unwrap();
+
+        let error = match Session::<Md5, DesPrivKey<Md5>, u32>::new(&mut client, b"usr") {
+            Ok(_) => panic!("discovery should time out"),
+            Err(error) => error,
+        };
+        assert!(matches!(
+            error,
+            Error::Timeout(TimeoutError { attempts: 3 })
+        ));
+
+        agent.set_nonblocking(true).unwrap();
+        let mut buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+        let mut received = 0;
+        while agent.recv(&mut buf).is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 3);
     }
 }
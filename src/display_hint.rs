@@ -0,0 +1,329 @@
+use crate::oids::ObjectSyntax;
+use crate::params::SnmpValue;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/**
+ * Formats a value as its textual convention describes it: with the `DISPLAY-HINT` of RFC 2579, or
+ * with a dedicated format for `DateAndTime` (ISO 8601) and the typed `InetAddress` conventions.
+ *
+ * Returns `None` when the syntax has no hint applying to the value, or the hint is malformed. A
+ * plain `InetAddress` also gives `None`, as only its companion `InetAddressType` tells how to read
+ * it; see `format_inet_address`.
+*/
+pub fn format_value(value: &SnmpValue, syntax: &ObjectSyntax) -> Option<String> {
+    let tc = syntax.textual_convention.as_deref().unwrap_or_default();
+    match value {
+        SnmpValue::OctetString(octets) => match tc {
+            "DateAndTime" => format_date_and_time(octets),
+            "InetAddressIPv4" | "InetAddressIPv6" | "InetAddressIPv4z" | "InetAddressIPv6z"
+            | "Ipv6Address" => format_ip_address(octets),
+            _ => format_octets(syntax.display_hint.as_deref()?, octets),
+        },
+        SnmpValue::Int(x) => format_integer(syntax.display_hint.as_deref()?, (*x).into()),
+        SnmpValue::UnsignedInt(x) => format_integer(syntax.display_hint.as_deref()?, (*x).into()),
+        _ => None,
+    }
+}
+
+/**
+ * Formats an OCTET STRING with an RFC 2579 display hint like `1x:` or `255a`.
+ *
+ * The last specification of the hint is reused until every octet is formatted.
+*/
+pub fn format_octets(hint: &str, octets: &[u8]) -> Option<String> {
+    let specs = parse_octet_hint(hint)?;
+    let mut formatted = String::new();
+    let mut pos = 0;
+
+    for spec in specs.iter().chain(std::iter::repeat(specs.last()?)) {
+        if pos >= octets.len() {
+            break;
+        }
+
+        let repeat = match spec.repeat {
+            true => {
+                pos += 1;
+                usize::from(octets[pos - 1])
+            }
+            false => 1,
+        };
+        for count in 1..=repeat {
+            if pos >= octets.len() {
+                break;
+            }
+
+            let end = octets.len().min(pos + spec.len);
+            format_octet_unit(&mut formatted, spec.format, &octets[pos..end])?;
+            pos = end;
+
+            let last_of_repeat = count == repeat && spec.terminator.is_some();
+            if let Some(separator) = spec.separator.filter(|_| !last_of_repeat) {
+                if pos < octets.len() {
+                    formatted.push(separator);
+                }
+            }
+        }
+        if let Some(terminator) = spec.terminator {
+            if pos < octets.len() {
+                formatted.push(terminator);
+            }
+        }
+    }
+
+    Some(formatted)
+}
+
+/**
+ * Formats an INTEGER with an RFC 2579 display hint: `d-2` shows 1234 as `12.34`, and `x`, `o`
+ * and `b` show it in hexadecimal, octal and binary.
+*/
+pub fn format_integer(hint: &str, value: i64) -> Option<String> {
+    let mut chars = hint.chars();
+    let format = chars.next()?;
+    let rest = chars.as_str();
+
+    match (format, rest) {
+        ('d', "") => Some(value.to_string()),
+        ('d', decimals) => {
+            let decimals: usize = decimals.strip_prefix('-')?.parse().ok()?;
+            if decimals == 0 {
+                return Some(value.to_string());
+            }
+            let digits = format!("{:0>width$}", value.unsigned_abs(), width = decimals + 1);
+            let (units, fraction) = digits.split_at(digits.len() - decimals);
+            let sign = if value < 0 { "-" } else { "" };
+            Some(format!("{}{}.{}", sign, units, fraction))
+        }
+        ('x', "") => Some(format!("{:x}", value)),
+        ('o', "") => Some(format!("{:o}", value)),
+        ('b', "") => Some(format!("{:b}", value)),
+        _ => None,
+    }
+}
+
+/**
+ * Formats a `DateAndTime` as an ISO 8601 timestamp, like `2024-01-31T13:05:09.4+02:00`. The UTC
+ * offset is only present in the 11 octet form.
+*/
+pub fn format_date_and_time(octets: &[u8]) -> Option<String> {
+    if octets.len() != 8 && octets.len() != 11 {
+        return None;
+    }
+
+    let year = u16::from_be_bytes([octets[0], octets[1]]);
+    let mut formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{}",
+        year, octets[2], octets[3], octets[4], octets[5], octets[6], octets[7]
+    );
+    if octets.len() == 11 {
+        let direction = match octets[8] {
+            b'+' | b'-' => octets[8] as char,
+            _ => return None,
+        };
+        formatted.push_str(&format!("{}{:02}:{:02}", direction, octets[9], octets[10]));
+    }
+
+    Some(formatted)
+}
+
+/**
+ * Formats an `InetAddress` given the value of its `InetAddressType` (RFC 4001): IPv4 and IPv6
+ * addresses, with a `%zone` suffix for the zoned forms, and DNS names.
+ *
+ * Returns `None` for the `unknown` type, and when the length doesn't match the type.
+*/
+pub fn format_inet_address(address_type: i64, octets: &[u8]) -> Option<String> {
+    match (address_type, octets.len()) {
+        (1, 4) | (2, 16) | (3, 8) | (4, 20) => format_ip_address(octets),
+        (16, _) => format_octets("255a", octets),
+        _ => None,
+    }
+}
+
+fn format_ip_address(octets: &[u8]) -> Option<String> {
+    let zone = |zone: &[u8]| u32::from_be_bytes([zone[0], zone[1], zone[2], zone[3]]);
+    match octets.len() {
+        4 => Some(Ipv4Addr::from(<[u8; 4]>::try_from(octets).ok()?).to_string()),
+        8 => Some(format!(
+            "{}%{}",
+            Ipv4Addr::from(<[u8; 4]>::try_from(&octets[..4]).ok()?),
+            zone(&octets[4..])
+        )),
+        16 => Some(Ipv6Addr::from(<[u8; 16]>::try_from(octets).ok()?).to_string()),
+        20 => Some(format!(
+            "{}%{}",
+            Ipv6Addr::from(<[u8; 16]>::try_from(&octets[..16]).ok()?),
+            zone(&octets[16..])
+        )),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct OctetSpec {
+    // Whether the first octet gives the number of times the spec applies.
+    repeat: bool,
+    len: usize,
+    format: char,
+    separator: Option<char>,
+    terminator: Option<char>,
+}
+
+fn parse_octet_hint(hint: &str) -> Option<Vec<OctetSpec>> {
+    let chars: Vec<char> = hint.chars().collect();
+    // Separators and terminators are any character that can't start a spec.
+    let is_delimiter = |c: &char| !c.is_ascii_digit() && *c != '*';
+    let mut specs = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let repeat = chars[i] == '*';
+        if repeat {
+            i += 1;
+        }
+
+        let start = i;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+            i += 1;
+        }
+        let len: usize = chars[start..i].iter().collect::<String>().parse().ok()?;
+        let format = *chars
+            .get(i)
+            .filter(|x| matches!(x, 'x' | 'd' | 'o' | 'a' | 't'))?;
+        i += 1;
+        if len == 0 {
+            return None;
+        }
+
+        let separator = chars.get(i).copied().filter(is_delimiter);
+        if separator.is_some() {
+            i += 1;
+        }
+        let terminator = chars
+            .get(i)
+            .copied()
+            .filter(|c| repeat && separator.is_some() && is_delimiter(c));
+        if terminator.is_some() {
+            i += 1;
+        }
+
+        specs.push(OctetSpec {
+            repeat,
+            len,
+            format,
+            separator,
+            terminator,
+        });
+    }
+
+    match specs.is_empty() {
+        true => None,
+        false => Some(specs),
+    }
+}
+
+fn format_octet_unit(formatted: &mut String, format: char, octets: &[u8]) -> Option<()> {
+    match format {
+        'x' => {
+            for octet in octets {
+                formatted.push_str(&format!("{:02x}", octet));
+            }
+        }
+        'd' | 'o' => {
+            if octets.len() > 8 {
+                return None;
+            }
+            let value = octets.iter().fold(0u64, |acc, x| acc << 8 | u64::from(*x));
+            match format {
+                'd' => formatted.push_str(&value.to_string()),
+                _ => formatted.push_str(&format!("{:o}", value)),
+            }
+        }
+        // Agents put UTF-8 in ASCII strings too.
+        _ => formatted.push_str(&String::from_utf8_lossy(octets)),
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax(tc: &str, hint: Option<&str>) -> ObjectSyntax {
+        ObjectSyntax {
+            base_type: "OCTET STRING".to_string(),
+            textual_convention: Some(tc.to_string()),
+            display_hint: hint.map(|x| x.to_string()),
+            enums: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn it_formats_octet_strings_with_hints() {
+        let mac = SnmpValue::OctetString(vec![0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+        assert_eq!(
+            format_value(&mac, &syntax("MacAddress", Some("1x:"))).as_deref(),
+            Some("00:1a:2b:3c:4d:5e")
+        );
+
+        // The 8 octet form of DateAndTime, by its hint and as ISO 8601.
+        let date = vec![0x07, 0xe8, 1, 31, 13, 5, 9, 4];
+        let hint = "2d-1d-1d,1d:1d:1d.1d,1a1d:1d";
+        assert_eq!(
+            format_octets(hint, &date).as_deref(),
+            Some("2024-1-31,13:5:9.4")
+        );
+        let mut zoned = date.clone();
+        zoned.extend_from_slice(&[b'+', 2, 0]);
+        assert_eq!(
+            format_value(
+                &SnmpValue::OctetString(zoned),
+                &syntax("DateAndTime", Some(hint))
+            )
+            .as_deref(),
+            Some("2024-01-31T13:05:09.4+02:00")
+        );
+
+        // A repeat count, with a separator and a terminator.
+        assert_eq!(
+            format_octets("*1d./1d", &[3, 10, 0, 1, 24]).as_deref(),
+            Some("10.0.1/24")
+        );
+        assert_eq!(format_octets("1y", &[1]), None);
+    }
+
+    #[test]
+    fn it_formats_inet_addresses_by_type() {
+        assert_eq!(
+            format_inet_address(1, &[192, 0, 2, 1]).as_deref(),
+            Some("192.0.2.1")
+        );
+        let mut ipv6 = vec![0x20, 0x01, 0x0d, 0xb8];
+        ipv6.extend_from_slice(&[0; 11]);
+        ipv6.push(1);
+        assert_eq!(
+            format_inet_address(2, &ipv6).as_deref(),
+            Some("2001:db8::1")
+        );
+        assert_eq!(
+            format_inet_address(3, &[192, 0, 2, 1, 0, 0, 0, 3]).as_deref(),
+            Some("192.0.2.1%3")
+        );
+        assert_eq!(format_inet_address(16, b"rtr1").as_deref(), Some("rtr1"));
+        assert_eq!(format_inet_address(2, &[192, 0, 2, 1]), None);
+
+        // Without the type, a DNS name isn't mistaken for an IPv4 address.
+        let name = SnmpValue::OctetString(b"rtr1".to_vec());
+        assert_eq!(format_value(&name, &syntax("InetAddress", None)), None);
+    }
+
+    #[test]
+    fn it_formats_integers_with_hints() {
+        assert_eq!(format_integer("d-2", 1234).as_deref(), Some("12.34"));
+        assert_eq!(format_integer("d-2", -5).as_deref(), Some("-0.05"));
+        assert_eq!(format_integer("d", 42).as_deref(), Some("42"));
+        assert_eq!(format_integer("x", 255).as_deref(), Some("ff"));
+        assert_eq!(format_integer("d-x", 1), None);
+    }
+}
@@ -0,0 +1,380 @@
+use snmp_mp::{MsgProcessingError, PduErrorStatus};
+use snmp_usm::SecurityError;
+use std::error::Error as StdError;
+use std::{fmt, io};
+
+/**
+ * Result type returned by the public API of this crate.
+*/
+pub type Result<T> = std::result::Result<T, Error>;
+
+/**
+ * Error returned by the public API of this crate.
+ *
+ * Variants wrapping another error return it from `source()`, so the full chain can be reported.
+*/
+#[derive(Debug)]
+pub enum Error {
+    /// No response arrived after every attempt allowed by the retry policy.
+    Timeout(TimeoutError),
+    Io(io::Error),
+    /// A message couldn't be encoded or decoded.
+    Decode(MsgProcessingError),
+    /// A response failed authentication, or its security parameters were malformed.
+    Auth(SecurityError),
+    /// A response arrived without authentication on an authenticated session.
+    Unauthenticated,
+    /// A response couldn't be decrypted with the privacy key.
+    Decryption,
+    /// The agent answered with a Report PDU.
+    ReportPdu(ReportError),
+    /// The agent answered with a non-zero error-status.
+    ErrorStatus(PduError),
+    /// An OID supplied by the caller couldn't be parsed.
+    InvalidOid(String),
+    /// The request can't be made with the given parameters.
+    Config(ConfigError),
+    /// The agent's answer doesn't follow the protocol, e.g. a walk going backwards.
+    Protocol(String),
+    /// A text input, like a recorded walk, couldn't be parsed.
+    Parse(ParseError),
+}
+
+impl Error {
+    pub(crate) fn protocol<M: Into<String>>(message: M) -> Self {
+        Error::Protocol(message.into())
+    }
+
+    pub(crate) fn config<M: Into<String>>(message: M) -> Self {
+        Error::Config(ConfigError::new(message))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Timeout(_) => write!(f, "request timed out"),
+            Error::Io(_) => write!(f, "I/O error"),
+            Error::Decode(_) => write!(f, "invalid SNMP message"),
+            Error::Auth(_) => write!(f, "authentication failed"),
+            Error::Unauthenticated => write!(f, "received an unauthenticated response"),
+            Error::Decryption => write!(f, "unable to decrypt the response"),
+            Error::ReportPdu(_) => write!(f, "request rejected with a Report PDU"),
+            Error::ErrorStatus(_) => write!(f, "request failed"),
+            Error::InvalidOid(oid) => write!(f, "invalid OID supplied: {:?}", oid),
+            Error::Config(_) => write!(f, "invalid parameters"),
+            Error::Protocol(message) => write!(f, "unexpected response: {}", message),
+            Error::Parse(_) => write!(f, "unable to parse the input"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Timeout(error) => Some(error),
+            Error::Io(error) => Some(error),
+            Error::Decode(error) => Some(error),
+            Error::Auth(error) => Some(error),
+            Error::ReportPdu(error) => Some(error),
+            Error::ErrorStatus(error) => Some(error),
+            Error::Config(error) => Some(error),
+            Error::Parse(error) => Some(error),
+            Error::Unauthenticated
+            | Error::Decryption
+            | Error::InvalidOid(_)
+            | Error::Protocol(_) => None,
+        }
+    }
+}
+
+impl From<TimeoutError> for Error {
+    fn from(error: TimeoutError) -> Self {
+        Error::Timeout(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<MsgProcessingError> for Error {
+    fn from(error: MsgProcessingError) -> Self {
+        match error {
+            MsgProcessingError::DecryptError => Error::Decryption,
+            error => Error::Decode(error),
+        }
+    }
+}
+
+impl From<SecurityError> for Error {
+    fn from(error: SecurityError) -> Self {
+        match error {
+            SecurityError::DecryptError => Error::Decryption,
+            error => Error::Auth(error),
+        }
+    }
+}
+
+impl From<ReportError> for Error {
+    fn from(error: ReportError) -> Self {
+        Error::ReportPdu(error)
+    }
+}
+
+impl From<PduError> for Error {
+    fn from(error: PduError) -> Self {
+        Error::ErrorStatus(error)
+    }
+}
+
+impl From<ConfigError> for Error {
+    fn from(error: ConfigError) -> Self {
+        Error::Config(error)
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(error: ParseError) -> Self {
+        Error::Parse(error)
+    }
+}
+
+/**
+ * Error returned when an agent answers a request with a non-zero error-status.
+ *
+ * `index` is the 1-based error-index from the response PDU and `oid` names the variable binding
+ * it points at, when the index is in range.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PduError {
+    pub status: PduErrorStatus,
+    pub index: u32,
+    pub oid: Option<String>,
+}
+
+impl PduError {
+    /**
+     * Returns the name of the error-status as written in RFC 3416, e.g. `notWritable`.
+     */
+    pub fn status_name(&self) -> &'static str {
+        error_status_name(self.status)
+    }
+}
+
+impl fmt::Display for PduError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.oid {
+            Some(oid) => write!(
+                f,
+                "agent returned {} for varbind {} ({})",
+                self.status_name(),
+                self.index,
+                oid
+            ),
+            None => write!(
+                f,
+                "agent returned {} (error index {})",
+                self.status_name(),
+                self.index
+            ),
+        }
+    }
+}
+
+impl StdError for PduError {}
+
+pub fn error_status_name(status: PduErrorStatus) -> &'static str {
+    match status {
+        PduErrorStatus::NoError => "noError",
+        PduErrorStatus::TooBig => "tooBig",
+        PduErrorStatus::NoSuchName => "noSuchName",
+        PduErrorStatus::BadValue => "badValue",
+        PduErrorStatus::ReadOnly => "readOnly",
+        PduErrorStatus::GenErr => "genErr",
+        PduErrorStatus::NoAccess => "noAccess",
+        PduErrorStatus::WrongType => "wrongType",
+        PduErrorStatus::WrongLength => "wrongLength",
+        PduErrorStatus::WrongEncoding => "wrongEncoding",
+        PduErrorStatus::WrongValue => "wrongValue",
+        PduErrorStatus::NoCreation => "noCreation",
+        PduErrorStatus::InconsistentValue => "inconsistentValue",
+        PduErrorStatus::ResourceUnavailable => "resourceUnavailable",
+        PduErrorStatus::CommitFailed => "commitFailed",
+        PduErrorStatus::UndoFailed => "undoFailed",
+        PduErrorStatus::AuthorizationError => "authorizationError",
+        PduErrorStatus::NotWritable => "notWritable",
+        PduErrorStatus::InconsistentName => "inconsistentName",
+    }
+}
+
+/**
+ * The counter an agent reported in a Report PDU.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ReportKind {
+    /// usmStatsUnsupportedSecLevels
+    UnsupportedSecLevel,
+    /// usmStatsNotInTimeWindows
+    NotInTimeWindow,
+    /// usmStatsUnknownUserNames
+    UnknownUserName,
+    /// usmStatsUnknownEngineIDs
+    UnknownEngineId,
+    /// usmStatsWrongDigests
+    WrongDigest,
+    /// usmStatsDecryptionErrors
+    DecryptionError,
+    /// Any other counter, e.g. snmpUnknownContexts.
+    Other,
+}
+
+const USM_STATS_OID: [u64; 9] = [1, 3, 6, 1, 6, 3, 15, 1, 1];
+
+impl ReportKind {
+    /**
+     * Maps the OID of the first variable binding of a Report PDU to the counter it names.
+     */
+    pub fn from_oid(oid: &[u64]) -> Self {
+        if oid.len() < USM_STATS_OID.len() + 1 || oid[..USM_STATS_OID.len()] != USM_STATS_OID {
+            return ReportKind::Other;
+        }
+
+        match oid[USM_STATS_OID.len()] {
+            1 => ReportKind::UnsupportedSecLevel,
+            2 => ReportKind::NotInTimeWindow,
+            3 => ReportKind::UnknownUserName,
+            4 => ReportKind::UnknownEngineId,
+            5 => ReportKind::WrongDigest,
+            6 => ReportKind::DecryptionError,
+            _ => ReportKind::Other,
+        }
+    }
+
+    /**
+     * Returns the OID of the counter instance, e.g. `1.3.6.1.6.3.15.1.1.5.0` for
+     * `usmStatsWrongDigests`, or `None` for `Other`.
+     */
+    pub fn oid(&self) -> Option<Vec<u64>> {
+        let counter = match self {
+            ReportKind::UnsupportedSecLevel => 1,
+            ReportKind::NotInTimeWindow => 2,
+            ReportKind::UnknownUserName => 3,
+            ReportKind::UnknownEngineId => 4,
+            ReportKind::WrongDigest => 5,
+            ReportKind::DecryptionError => 6,
+            ReportKind::Other => return None,
+        };
+
+        let mut oid = USM_STATS_OID.to_vec();
+        oid.extend_from_slice(&[counter, 0]);
+        Some(oid)
+    }
+
+    /**
+     * Returns the MIB name of the counter, e.g. `usmStatsWrongDigests`.
+     */
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReportKind::UnsupportedSecLevel => "usmStatsUnsupportedSecLevels",
+            ReportKind::NotInTimeWindow => "usmStatsNotInTimeWindows",
+            ReportKind::UnknownUserName => "usmStatsUnknownUserNames",
+            ReportKind::UnknownEngineId => "usmStatsUnknownEngineIDs",
+            ReportKind::WrongDigest => "usmStatsWrongDigests",
+            ReportKind::DecryptionError => "usmStatsDecryptionErrors",
+            ReportKind::Other => "unknown report",
+        }
+    }
+}
+
+/**
+ * Error returned when an agent answers a request with a Report PDU.
+ *
+ * `oid` is the dotted OID of the reported counter and `counter` its value, when the agent sent
+ * one.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReportError {
+    pub kind: ReportKind,
+    pub oid: String,
+    pub counter: Option<u32>,
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ReportKind::Other => write!(f, "agent sent a report for {}", self.oid),
+            kind => write!(f, "agent sent a report for {}", kind.name()),
+        }
+    }
+}
+
+impl StdError for ReportError {}
+
+/**
+ * Error returned when `Params` or another configuration value is invalid.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new<M: Into<String>>(message: M) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid configuration: {}", self.message)
+    }
+}
+
+impl StdError for ConfigError {}
+
+/**
+ * Error returned when a text input can't be parsed. `line` is 1-based.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new<M: Into<String>>(line: usize, message: M) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl StdError for ParseError {}
+
+/**
+ * Error returned when no response arrived after every attempt allowed by the retry policy.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct TimeoutError {
+    pub attempts: u32,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no response after {} attempt(s)", self.attempts)
+    }
+}
+
+impl StdError for TimeoutError {}
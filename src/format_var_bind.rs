@@ -15,7 +15,10 @@ pub fn format_var_bind(var_bind: &VarBind) -> String {
 pub fn format_var_value(var_value: &VarValue) -> String {
     match var_value {
         VarValue::Int(i) => format!("INTEGER: {}", i),
-        VarValue::String(s) => format!("STRING: {:?}", String::from_utf8_lossy(s)),
+        VarValue::String(s) => match std::str::from_utf8(s) {
+            Ok(text) => format!("STRING: {:?}", text),
+            Err(_) => format!("Hex-STRING: {:02X?}", s),
+        },
         VarValue::ObjectId(oid) => format!("OID: {}", oid),
         VarValue::IpAddress(ip) => format!("IP ADDRESS: {}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]),
         VarValue::Counter(c) => format!("COUNTER: {}", c),
@@ -37,7 +40,13 @@ pub fn format_var_value(var_value: &VarValue) -> String {
                 time_ticks, days, hours, minutes, seconds, hundredth
             )
         }
-        VarValue::Opaque(o) => format!("OPAQUE: {:X?}", o),
+        VarValue::Opaque(o) => format!(
+            "OPAQUE: {}",
+            o.iter()
+                .map(|octet| format!("{:02X}", octet))
+                .collect::<Vec<String>>()
+                .join(" ")
+        ),
         VarValue::BigCounter(bc) => format!("BIG COUNTER: {}", bc),
         VarValue::Unspecified => "Unspecified".to_string(),
         VarValue::NoSuchObject => "No such object".to_string(),
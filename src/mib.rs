@@ -0,0 +1,1606 @@
+use crate::error::{Error, ParseError, Result};
+use crate::oids::{ObjectSyntax, OidMap, OID};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Types of the SMI that textual conventions are ultimately derived from.
+const BASE_TYPES: [&str; 16] = [
+    "INTEGER",
+    "Integer32",
+    "OCTET STRING",
+    "OBJECT IDENTIFIER",
+    "BITS",
+    "IpAddress",
+    "Counter32",
+    "Gauge32",
+    "Unsigned32",
+    "TimeTicks",
+    "Opaque",
+    "Counter64",
+    "Counter",
+    "Gauge",
+    "NetworkAddress",
+    "NsapAddress",
+];
+
+// File extensions tried, in order, when looking for a module in the search path.
+const MIB_EXTENSIONS: [&str; 5] = ["", "txt", "mib", "my", "smi"];
+
+// Minimal versions of the modules defining the SMI itself, used when the search path doesn't
+// have them. Macro definitions are left out: the parser knows the macros.
+const BUILTIN_MODULES: &str = r#"
+SNMPv2-SMI DEFINITIONS ::= BEGIN
+org            OBJECT IDENTIFIER ::= { iso 3 }
+dod            OBJECT IDENTIFIER ::= { org 6 }
+internet       OBJECT IDENTIFIER ::= { dod 1 }
+directory      OBJECT IDENTIFIER ::= { internet 1 }
+mgmt           OBJECT IDENTIFIER ::= { internet 2 }
+mib-2          OBJECT IDENTIFIER ::= { mgmt 1 }
+transmission   OBJECT IDENTIFIER ::= { mib-2 10 }
+experimental   OBJECT IDENTIFIER ::= { internet 3 }
+private        OBJECT IDENTIFIER ::= { internet 4 }
+enterprises    OBJECT IDENTIFIER ::= { private 1 }
+security       OBJECT IDENTIFIER ::= { internet 5 }
+snmpV2         OBJECT IDENTIFIER ::= { internet 6 }
+snmpDomains    OBJECT IDENTIFIER ::= { snmpV2 1 }
+snmpProxys     OBJECT IDENTIFIER ::= { snmpV2 2 }
+snmpModules    OBJECT IDENTIFIER ::= { snmpV2 3 }
+zeroDotZero    OBJECT IDENTIFIER ::= { 0 0 }
+ObjectName ::= OBJECT IDENTIFIER
+NotificationName ::= OBJECT IDENTIFIER
+Integer32 ::= INTEGER (-2147483648..2147483647)
+IpAddress ::= [APPLICATION 0] IMPLICIT OCTET STRING (SIZE (4))
+Counter32 ::= [APPLICATION 1] IMPLICIT INTEGER (0..4294967295)
+Gauge32 ::= [APPLICATION 2] IMPLICIT INTEGER (0..4294967295)
+Unsigned32 ::= [APPLICATION 2] IMPLICIT INTEGER (0..4294967295)
+TimeTicks ::= [APPLICATION 3] IMPLICIT INTEGER (0..4294967295)
+Opaque ::= [APPLICATION 4] IMPLICIT OCTET STRING
+Counter64 ::= [APPLICATION 6] IMPLICIT INTEGER (0..18446744073709551615)
+END
+
+RFC1155-SMI DEFINITIONS ::= BEGIN
+org            OBJECT IDENTIFIER ::= { iso 3 }
+dod            OBJECT IDENTIFIER ::= { org 6 }
+internet       OBJECT IDENTIFIER ::= { dod 1 }
+directory      OBJECT IDENTIFIER ::= { internet 1 }
+mgmt           OBJECT IDENTIFIER ::= { internet 2 }
+experimental   OBJECT IDENTIFIER ::= { internet 3 }
+private        OBJECT IDENTIFIER ::= { internet 4 }
+enterprises    OBJECT IDENTIFIER ::= { private 1 }
+ObjectName ::= OBJECT IDENTIFIER
+NetworkAddress ::= CHOICE { internet IpAddress }
+IpAddress ::= [APPLICATION 0] IMPLICIT OCTET STRING (SIZE (4))
+Counter ::= [APPLICATION 1] IMPLICIT INTEGER (0..4294967295)
+Gauge ::= [APPLICATION 2] IMPLICIT INTEGER (0..4294967295)
+TimeTicks ::= [APPLICATION 3] IMPLICIT INTEGER (0..4294967295)
+Opaque ::= [APPLICATION 4] IMPLICIT OCTET STRING
+END
+
+SNMPv2-TC DEFINITIONS ::= BEGIN
+DisplayString ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "255a"
+    STATUS current
+    DESCRIPTION "Textual information taken from the NVT ASCII character set."
+    SYNTAX OCTET STRING (SIZE (0..255))
+PhysAddress ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "1x:"
+    STATUS current
+    DESCRIPTION "A media- or physical-level address."
+    SYNTAX OCTET STRING
+MacAddress ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "1x:"
+    STATUS current
+    DESCRIPTION "An 802 MAC address, in canonical order."
+    SYNTAX OCTET STRING (SIZE (6))
+TruthValue ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Represents a boolean value."
+    SYNTAX INTEGER { true(1), false(2) }
+TestAndIncr ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Represents integer-valued information used for atomic operations."
+    SYNTAX INTEGER (0..2147483647)
+AutonomousType ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Represents an independently extensible type identification value."
+    SYNTAX OBJECT IDENTIFIER
+InstancePointer ::= TEXTUAL-CONVENTION
+    STATUS obsolete
+    DESCRIPTION "A pointer to either a specific instance of a MIB object or a conceptual row."
+    SYNTAX OBJECT IDENTIFIER
+VariablePointer ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "A pointer to a specific object instance."
+    SYNTAX OBJECT IDENTIFIER
+RowPointer ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Represents a pointer to a conceptual row."
+    SYNTAX OBJECT IDENTIFIER
+RowStatus ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "The RowStatus textual convention is used to manage the creation and deletion of conceptual rows."
+    SYNTAX INTEGER {
+        active(1), notInService(2), notReady(3),
+        createAndGo(4), createAndWait(5), destroy(6)
+    }
+TimeStamp ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "The value of the sysUpTime object at which a specific occurrence happened."
+    SYNTAX TimeTicks
+TimeInterval ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "A period of time, measured in units of 0.01 seconds."
+    SYNTAX INTEGER (0..2147483647)
+DateAndTime ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "2d-1d-1d,1d:1d:1d.1d,1a1d:1d"
+    STATUS current
+    DESCRIPTION "A date-time specification."
+    SYNTAX OCTET STRING (SIZE (8 | 11))
+StorageType ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Describes the memory realization of a conceptual row."
+    SYNTAX INTEGER {
+        other(1), volatile(2), nonVolatile(3), permanent(4), readOnly(5)
+    }
+TDomain ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Denotes a kind of transport service."
+    SYNTAX OBJECT IDENTIFIER
+TAddress ::= TEXTUAL-CONVENTION
+    STATUS current
+    DESCRIPTION "Denotes a transport service address."
+    SYNTAX OCTET STRING (SIZE (1..255))
+END
+
+SNMPv2-CONF DEFINITIONS ::= BEGIN
+END
+
+RFC-1212 DEFINITIONS ::= BEGIN
+END
+
+RFC-1215 DEFINITIONS ::= BEGIN
+END
+"#;
+
+/**
+ * Kind of definition an OID was assigned by.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ObjectKind {
+    /// A plain `OBJECT IDENTIFIER` value assignment.
+    ObjectIdentifier,
+    ModuleIdentity,
+    ObjectIdentity,
+    ObjectType,
+    NotificationType,
+    /// An SMIv1 `TRAP-TYPE`, whose OID is the enterprise followed by 0 and the trap number.
+    TrapType,
+    ObjectGroup,
+    NotificationGroup,
+    ModuleCompliance,
+    AgentCapabilities,
+}
+
+impl ObjectKind {
+    fn from_macro(name: &str) -> Option<Self> {
+        match name {
+            "MODULE-IDENTITY" => Some(ObjectKind::ModuleIdentity),
+            "OBJECT-IDENTITY" => Some(ObjectKind::ObjectIdentity),
+            "OBJECT-TYPE" => Some(ObjectKind::ObjectType),
+            "NOTIFICATION-TYPE" => Some(ObjectKind::NotificationType),
+            "TRAP-TYPE" => Some(ObjectKind::TrapType),
+            "OBJECT-GROUP" => Some(ObjectKind::ObjectGroup),
+            "NOTIFICATION-GROUP" => Some(ObjectKind::NotificationGroup),
+            "MODULE-COMPLIANCE" => Some(ObjectKind::ModuleCompliance),
+            "AGENT-CAPABILITIES" => Some(ObjectKind::AgentCapabilities),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Type of an object or textual convention, as written in its `SYNTAX` clause.
+ *
+ * `type_name` is the ASN.1 type, like `INTEGER` or `OCTET STRING`, or the name of another type,
+ * like `Counter32` or `DisplayString`. `enums` holds the named numbers of `INTEGER` and `BITS`.
+*/
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct Syntax {
+    pub type_name: String,
+    pub enums: Vec<(String, i64)>,
+}
+
+/**
+ * An OID assignment from a compiled MIB module.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MibObject {
+    pub name: String,
+    pub module: String,
+    pub oid: Vec<u64>,
+    pub kind: ObjectKind,
+    /// Only set for `OBJECT-TYPE`s.
+    pub syntax: Option<Syntax>,
+    /// `MAX-ACCESS`, or `ACCESS` in SMIv1.
+    pub access: Option<String>,
+    pub status: Option<String>,
+    pub units: Option<String>,
+    pub description: Option<String>,
+    /// Objects named in the `INDEX` clause of a conceptual row, without `IMPLIED`.
+    pub index: Vec<String>,
+    /// Row extended by this conceptual row, which shares its index.
+    pub augments: Option<String>,
+}
+
+impl MibObject {
+    /**
+     * Returns the OID in dotted notation.
+     */
+    pub fn dotted_oid(&self) -> String {
+        dotted(&self.oid)
+    }
+}
+
+/**
+ * A `TEXTUAL-CONVENTION`, or a plain type assignment like SMIv1's `DisplayString ::= OCTET
+ * STRING`.
+*/
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextualConvention {
+    pub name: String,
+    pub module: String,
+    pub display_hint: Option<String>,
+    pub syntax: Syntax,
+    pub status: Option<String>,
+    pub description: Option<String>,
+}
+
+/**
+ * Definitions of a set of compiled MIB modules, with every OID resolved.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct Mib {
+    // Sorted by OID.
+    objects: Vec<MibObject>,
+    textual_conventions: Vec<TextualConvention>,
+    unresolved: Vec<String>,
+}
+
+impl Mib {
+    /**
+     * Returns the objects, sorted by OID.
+     */
+    pub fn objects(&self) -> &[MibObject] {
+        &self.objects
+    }
+
+    /**
+     * Finds an object by name, optionally qualified by its module as in `IF-MIB::ifDescr`.
+     */
+    pub fn object(&self, name: &str) -> Option<&MibObject> {
+        let (module, name) = split_qualified(name);
+        self.objects
+            .iter()
+            .find(|x| x.name == name && module.is_none_or(|module| x.module == module))
+    }
+
+    /**
+     * Finds the object assigned to `oid`, given in dotted notation.
+     */
+    pub fn object_by_oid(&self, oid: &str) -> Option<&MibObject> {
+        self.objects.iter().find(|x| x.dotted_oid() == oid)
+    }
+
+    pub fn textual_conventions(&self) -> &[TextualConvention] {
+        &self.textual_conventions
+    }
+
+    /**
+     * Finds a textual convention by name, optionally qualified by its module.
+     */
+    pub fn textual_convention(&self, name: &str) -> Option<&TextualConvention> {
+        let (module, name) = split_qualified(name);
+        self.textual_conventions
+            .iter()
+            .find(|x| x.name == name && module.is_none_or(|module| x.module == module))
+    }
+
+    /**
+     * Returns the `MODULE::name` of the definitions whose OID couldn't be resolved, e.g. because
+     * their parent isn't defined in any loaded module.
+     */
+    pub fn unresolved(&self) -> &[String] {
+        &self.unresolved
+    }
+
+    /**
+     * Resolves the syntax of an object through the textual conventions it's defined by, down to
+     * its SMI base type. Enumerations and the display hint come from the first type that has
+     * them.
+     */
+    pub fn object_syntax(&self, object: &MibObject) -> Option<ObjectSyntax> {
+        let syntax = object.syntax.as_ref()?;
+        let mut object_syntax = ObjectSyntax {
+            base_type: syntax.type_name.clone(),
+            enums: syntax.enums.clone(),
+            ..Default::default()
+        };
+
+        // Bounded, in case textual conventions are defined in terms of each other.
+        let mut module = object.module.as_str();
+        for _ in 0..self.textual_conventions.len() {
+            if BASE_TYPES.contains(&object_syntax.base_type.as_str()) {
+                break;
+            }
+
+            // Textual conventions of the module itself take precedence over homonyms.
+            let tc = match self
+                .textual_convention(&format!("{}::{}", module, object_syntax.base_type))
+                .or_else(|| self.textual_convention(&object_syntax.base_type))
+            {
+                Some(tc) => tc,
+                None => break,
+            };
+            if object_syntax.textual_convention.is_none() {
+                object_syntax.textual_convention = Some(tc.name.clone());
+            }
+            if object_syntax.display_hint.is_none() {
+                object_syntax.display_hint = tc.display_hint.clone();
+            }
+            if object_syntax.enums.is_empty() {
+                object_syntax.enums = tc.syntax.enums.clone();
+            }
+            object_syntax.base_type = tc.syntax.type_name.clone();
+            module = tc.module.as_str();
+        }
+
+        Some(object_syntax)
+    }
+
+    /**
+     * Builds an `OidMap` naming every OID, with the definition of every object, for use in place
+     * of a hand-written `oids.json`.
+     *
+     * When several modules assign the same OID, the first module loaded names it.
+     */
+    pub fn to_oid_map(&self) -> OidMap {
+        let mut seen = HashSet::new();
+        let oids = self
+            .objects
+            .iter()
+            .filter(|x| seen.insert(&x.oid))
+            .map(|x| OID {
+                oid: x.dotted_oid(),
+                name: x.name.clone(),
+                module: Some(x.module.clone()),
+                syntax: self.object_syntax(x),
+                access: x.access.clone(),
+                status: x.status.clone(),
+                units: x.units.clone(),
+                description: x.description.clone(),
+                index: self.row_index(x),
+            })
+            .collect();
+
+        OidMap { oids }
+    }
+
+    // Returns the index of a conceptual row, following `AUGMENTS` to the row it extends.
+    fn row_index(&self, object: &MibObject) -> Vec<String> {
+        let mut object = object;
+        for _ in 0..self.objects.len() {
+            match &object.augments {
+                Some(augments) if object.index.is_empty() => match self.object(augments) {
+                    Some(augmented) => object = augmented,
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+
+        object.index.clone()
+    }
+}
+
+/**
+ * Compiles SMIv1 and SMIv2 MIB modules.
+ *
+ * Modules are loaded by name from the search path, or from files and strings, together with the
+ * modules they import. The SMI modules themselves (`SNMPv2-SMI`, `SNMPv2-TC`, `SNMPv2-CONF`,
+ * `RFC1155-SMI`, `RFC-1212` and `RFC-1215`) are built in, and only read from the search path when
+ * a copy is found there.
+ *
+ * ```no_run
+ * use k0hax_snmpv3::mib::MibCompiler;
+ *
+ * let mut compiler = MibCompiler::new();
+ * compiler.add_search_path("/usr/share/snmp/mibs");
+ * compiler.load_module("IF-MIB").unwrap();
+ * let oid_map = compiler.compile().to_oid_map();
+ * ```
+*/
+#[derive(Debug, Default)]
+pub struct MibCompiler {
+    search_path: Vec<PathBuf>,
+    // In load order.
+    modules: Vec<Module>,
+    // Module names found in the files of the search path, built the first time a module can't
+    // be found by file name.
+    index: Option<HashMap<String, PathBuf>>,
+}
+
+impl MibCompiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /**
+     * Adds a directory to look for imported modules in. Directories are searched in the order
+     * they were added.
+     */
+    pub fn add_search_path<P: Into<PathBuf>>(&mut self, dir: P) -> &mut Self {
+        self.search_path.push(dir.into());
+        self.index = None;
+        self
+    }
+
+    /**
+     * Returns the names of the loaded modules, in load order.
+     */
+    pub fn modules(&self) -> Vec<&str> {
+        self.modules.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    /**
+     * Loads the module `name` and its imports, looking for a file named after the module, with
+     * or without a `.txt`, `.mib`, `.my` or `.smi` extension, then for any file defining it.
+     */
+    pub fn load_module(&mut self, name: &str) -> Result<&mut Self> {
+        if self.is_loaded(name) {
+            return Ok(self);
+        }
+
+        match self.find_module(name) {
+            Some(path) => self.load_file(path),
+            None => match parse_modules(BUILTIN_MODULES)
+                .expect("the built-in modules parse")
+                .into_iter()
+                .find(|x| x.name == name)
+            {
+                Some(module) => self.add_modules(vec![module]),
+                None => Err(Error::config(format!(
+                    "MIB module {} not found in the search path",
+                    name
+                ))),
+            },
+        }
+    }
+
+    /**
+     * Loads the modules defined in the file at `path`, and their imports.
+     */
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut Self> {
+        let path = path.as_ref();
+        let text = fs::read(path)?;
+        // MIB files are ASCII, but comments sometimes hold Latin-1 names.
+        let text = String::from_utf8_lossy(&text);
+        let modules = parse_modules(&text).map_err(|error| {
+            ParseError::new(
+                error.line,
+                format!("{} in {}", error.message, path.display()),
+            )
+        })?;
+
+        self.add_modules(modules)
+    }
+
+    /**
+     * Loads the modules defined in `text`, and their imports.
+     */
+    pub fn load_str(&mut self, text: &str) -> Result<&mut Self> {
+        let modules = parse_modules(text)?;
+        self.add_modules(modules)
+    }
+
+    /**
+     * Resolves the OIDs of every loaded definition.
+     *
+     * Definitions whose OID can't be resolved are left out and listed by `Mib::unresolved`.
+     */
+    pub fn compile(&self) -> Mib {
+        let mut resolver = Resolver {
+            modules: &self.modules,
+            resolved: HashMap::new(),
+        };
+
+        let mut objects = Vec::new();
+        let mut unresolved = Vec::new();
+        for (module_index, module) in self.modules.iter().enumerate() {
+            for (node_index, node) in module.nodes.iter().enumerate() {
+                match resolver.resolve_node(module_index, node_index) {
+                    Some(oid) => objects.push(MibObject {
+                        name: node.name.clone(),
+                        module: module.name.clone(),
+                        oid,
+                        kind: node.kind,
+                        syntax: node.syntax.clone(),
+                        access: node.access.clone(),
+                        status: node.status.clone(),
+                        units: node.units.clone(),
+                        description: node.description.clone(),
+                        index: node.index.clone(),
+                        augments: node.augments.clone(),
+                    }),
+                    None => unresolved.push(format!("{}::{}", module.name, node.name)),
+                }
+            }
+        }
+        // Stable, so the module loaded first stays first among equal OIDs.
+        objects.sort_by(|a, b| a.oid.cmp(&b.oid));
+
+        let textual_conventions = self
+            .modules
+            .iter()
+            .flat_map(|module| {
+                module.types.iter().map(|x| TextualConvention {
+                    name: x.name.clone(),
+                    module: module.name.clone(),
+                    display_hint: x.display_hint.clone(),
+                    syntax: x.syntax.clone(),
+                    status: x.status.clone(),
+                    description: x.description.clone(),
+                })
+            })
+            .collect();
+
+        Mib {
+            objects,
+            textual_conventions,
+            unresolved,
+        }
+    }
+
+    fn is_loaded(&self, name: &str) -> bool {
+        self.modules.iter().any(|x| x.name == name)
+    }
+
+    fn add_modules(&mut self, modules: Vec<Module>) -> Result<&mut Self> {
+        let mut imported = Vec::new();
+        for module in modules {
+            if self.is_loaded(&module.name) {
+                continue;
+            }
+
+            imported.extend(module.imports.iter().map(|(_, from)| from.clone()));
+            self.modules.push(module);
+        }
+
+        // The modules are added before their imports are loaded, so import cycles end.
+        for name in imported {
+            self.load_module(&name)?;
+        }
+
+        Ok(self)
+    }
+
+    fn find_module(&mut self, name: &str) -> Option<PathBuf> {
+        for dir in &self.search_path {
+            for extension in MIB_EXTENSIONS {
+                let path = dir.join(name).with_extension(extension);
+                if path.is_file() {
+                    return Some(path);
+                }
+            }
+        }
+
+        let search_path = &self.search_path;
+        self.index
+            .get_or_insert_with(|| index_modules(search_path))
+            .get(name)
+            .cloned()
+    }
+}
+
+// Maps the names of the modules defined in the files of `search_path` to their file. The first
+// directory defining a module wins.
+fn index_modules(search_path: &[PathBuf]) -> HashMap<String, PathBuf> {
+    let mut index = HashMap::new();
+    for dir in search_path {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for path in entries.filter_map(|x| x.ok()).map(|x| x.path()) {
+            let text = match fs::read(&path) {
+                Ok(text) => String::from_utf8_lossy(&text).into_owned(),
+                Err(_) => continue,
+            };
+            let tokens = match lex(&text) {
+                Ok(tokens) => tokens,
+                Err(_) => continue,
+            };
+
+            for pair in tokens.windows(2) {
+                if pair[1].is_word("DEFINITIONS") && pair[0].kind == TokenKind::Word {
+                    index.entry(pair[0].text.clone()).or_insert(path.clone());
+                }
+            }
+        }
+    }
+
+    index
+}
+
+fn split_qualified(name: &str) -> (Option<&str>, &str) {
+    match name.split_once("::") {
+        Some((module, name)) => (Some(module), name),
+        None => (None, name),
+    }
+}
+
+fn dotted(oid: &[u64]) -> String {
+    oid.iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[derive(Debug, Clone)]
+struct Module {
+    name: String,
+    // Imported symbols with the module they're imported from, in the order of the module.
+    imports: Vec<(String, String)>,
+    nodes: Vec<Node>,
+    types: Vec<TypeDef>,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    name: String,
+    kind: ObjectKind,
+    oid: Vec<OidComponent>,
+    syntax: Option<Syntax>,
+    access: Option<String>,
+    status: Option<String>,
+    units: Option<String>,
+    description: Option<String>,
+    index: Vec<String>,
+    augments: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct TypeDef {
+    name: String,
+    display_hint: Option<String>,
+    syntax: Syntax,
+    status: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum OidComponent {
+    Name(String),
+    Number(u64),
+    // `internet(1)`: only the number matters.
+    NamedNumber(u64),
+}
+
+struct Resolver<'a> {
+    modules: &'a [Module],
+    // Also holds `None` while a node is being resolved, so definition cycles end.
+    resolved: HashMap<(usize, usize), Option<Vec<u64>>>,
+}
+
+impl Resolver<'_> {
+    fn resolve_node(&mut self, module_index: usize, node_index: usize) -> Option<Vec<u64>> {
+        if let Some(oid) = self.resolved.get(&(module_index, node_index)) {
+            return oid.clone();
+        }
+        self.resolved.insert((module_index, node_index), None);
+
+        let components = &self.modules[module_index].nodes[node_index].oid;
+        let mut oid = Vec::new();
+        for (position, component) in components.iter().enumerate() {
+            match component {
+                OidComponent::Number(x) | OidComponent::NamedNumber(x) => oid.push(*x),
+                OidComponent::Name(name) if position == 0 => {
+                    oid = self.resolve_name(module_index, name)?;
+                }
+                OidComponent::Name(_) => return None,
+            }
+        }
+
+        self.resolved
+            .insert((module_index, node_index), Some(oid.clone()));
+        Some(oid)
+    }
+
+    // Looks `name` up in the module, then in the module it's imported from, then in every module,
+    // as many MIBs forget to import the nodes they use.
+    fn resolve_name(&mut self, module_index: usize, name: &str) -> Option<Vec<u64>> {
+        let module = &self.modules[module_index];
+        if let Some(node_index) = module.nodes.iter().position(|x| x.name == name) {
+            return self.resolve_node(module_index, node_index);
+        }
+
+        if let Some((_, from)) = module.imports.iter().find(|(symbol, _)| symbol == name) {
+            if let Some(from_index) = self.modules.iter().position(|x| x.name == *from) {
+                if let Some(node_index) = self.modules[from_index]
+                    .nodes
+                    .iter()
+                    .position(|x| x.name == name)
+                {
+                    return self.resolve_node(from_index, node_index);
+                }
+            }
+        }
+
+        match name {
+            "ccitt" => return Some(vec![0]),
+            "iso" => return Some(vec![1]),
+            "joint-iso-ccitt" => return Some(vec![2]),
+            _ => (),
+        }
+
+        let found = self.modules.iter().enumerate().find_map(|(index, module)| {
+            module
+                .nodes
+                .iter()
+                .position(|x| x.name == name)
+                .map(|node_index| (index, node_index))
+        });
+        let (found_module, node_index) = found?;
+        self.resolve_node(found_module, node_index)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum TokenKind {
+    Word,
+    Number,
+    // A quoted string, without its quotes.
+    Text,
+    // A hex or binary string like `'00FF'H`.
+    Binary,
+    Symbol,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    text: String,
+    line: usize,
+}
+
+impl Token {
+    fn is_word(&self, word: &str) -> bool {
+        self.kind == TokenKind::Word && self.text == word
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        self.kind == TokenKind::Symbol && self.text == symbol
+    }
+}
+
+fn lex(text: &str) -> std::result::Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start_line = line;
+
+        if c == '\n' {
+            line += 1;
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            // Comments end at the end of the line or at the next `--`.
+            i += 2;
+            while i < chars.len() && chars[i] != '\n' {
+                if chars[i] == '-' && chars.get(i + 1) == Some(&'-') {
+                    i += 2;
+                    break;
+                }
+                i += 1;
+            }
+        } else if c == '"' {
+            let mut string = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(ParseError::new(start_line, "unterminated string")),
+                    // A doubled quote stands for a quote.
+                    Some('"') if chars.get(i + 1) == Some(&'"') => {
+                        string.push('"');
+                        i += 2;
+                    }
+                    Some('"') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(c) => {
+                        if *c == '\n' {
+                            line += 1;
+                        }
+                        string.push(*c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(Token {
+                kind: TokenKind::Text,
+                text: string,
+                line: start_line,
+            });
+        } else if c == '\'' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|x| *x == '\'')
+                .ok_or_else(|| ParseError::new(start_line, "unterminated binary string"))?;
+            let mut string: String = chars[i..i + end + 2].iter().collect();
+            i += end + 2;
+            if let Some(radix) = chars.get(i).filter(|x| x.is_ascii_alphabetic()) {
+                string.push(*radix);
+                i += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Binary,
+                text: string,
+                line: start_line,
+            });
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).is_some_and(|x| x.is_ascii_digit()))
+        {
+            let start = i;
+            i += 1;
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Number,
+                text: chars[start..i].iter().collect(),
+                line: start_line,
+            });
+        } else if c.is_ascii_alphabetic() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric()
+                    || chars[i] == '_'
+                    || (chars[i] == '-' && chars.get(i + 1) != Some(&'-')))
+            {
+                i += 1;
+            }
+            // A hyphen can't end an identifier.
+            while chars[i - 1] == '-' {
+                i -= 1;
+            }
+            tokens.push(Token {
+                kind: TokenKind::Word,
+                text: chars[start..i].iter().collect(),
+                line: start_line,
+            });
+        } else {
+            let symbol = if chars[i..].starts_with(&[':', ':', '=']) {
+                "::="
+            } else if chars[i..].starts_with(&['.', '.']) {
+                ".."
+            } else {
+                ""
+            };
+            let text = match symbol {
+                "" => c.to_string(),
+                symbol => symbol.to_string(),
+            };
+            i += text.chars().count();
+            tokens.push(Token {
+                kind: TokenKind::Symbol,
+                text,
+                line: start_line,
+            });
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn parse_modules(text: &str) -> std::result::Result<Vec<Module>, ParseError> {
+    let tokens = lex(text)?;
+    let mut parser = Parser { tokens, pos: 0 };
+
+    let mut modules = Vec::new();
+    while !parser.at_end() {
+        modules.push(parser.module()?);
+    }
+
+    Ok(modules)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn line(&self) -> usize {
+        self.peek()
+            .or_else(|| self.tokens.last())
+            .map_or(1, |x| x.line)
+    }
+
+    fn error<M: Into<String>>(&self, message: M) -> ParseError {
+        ParseError::new(self.line(), message)
+    }
+
+    fn next(&mut self) -> std::result::Result<Token, ParseError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| self.error("unexpected end of file"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn next_is_word(&self, word: &str) -> bool {
+        self.peek().is_some_and(|x| x.is_word(word))
+    }
+
+    fn next_is_symbol(&self, symbol: &str) -> bool {
+        self.peek().is_some_and(|x| x.is_symbol(symbol))
+    }
+
+    fn expect_word(&mut self, word: &str) -> std::result::Result<(), ParseError> {
+        match self.next_is_word(word) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(self.error(format!("expected {}", word))),
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> std::result::Result<(), ParseError> {
+        match self.next_is_symbol(symbol) {
+            true => {
+                self.pos += 1;
+                Ok(())
+            }
+            false => Err(self.error(format!("expected '{}'", symbol))),
+        }
+    }
+
+    fn word(&mut self) -> std::result::Result<String, ParseError> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Word => Ok(self.next()?.text),
+            _ => Err(self.error("expected an identifier")),
+        }
+    }
+
+    fn text(&mut self) -> std::result::Result<String, ParseError> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Text => Ok(self.next()?.text),
+            _ => Err(self.error("expected a quoted string")),
+        }
+    }
+
+    fn number(&mut self) -> std::result::Result<i64, ParseError> {
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Number => {
+                let text = self.next()?.text;
+                text.parse()
+                    .map_err(|_| self.error(format!("number out of range: {}", text)))
+            }
+            _ => Err(self.error("expected a number")),
+        }
+    }
+
+    // Skips a `{ ... }`, `( ... )` or `[ ... ]` block, nested blocks included.
+    fn skip_block(&mut self) -> std::result::Result<(), ParseError> {
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            if token.kind != TokenKind::Symbol {
+                continue;
+            }
+
+            match token.text.as_str() {
+                "{" | "(" | "[" => depth += 1,
+                "}" | ")" | "]" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn module(&mut self) -> std::result::Result<Module, ParseError> {
+        let name = self.word()?;
+        self.expect_word("DEFINITIONS")?;
+        // Tagging defaults, like `IMPLICIT TAGS`, are irrelevant here.
+        while !self.next_is_symbol("::=") {
+            self.next()?;
+        }
+        self.expect_symbol("::=")?;
+        self.expect_word("BEGIN")?;
+
+        let mut module = Module {
+            name,
+            imports: Vec::new(),
+            nodes: Vec::new(),
+            types: Vec::new(),
+        };
+
+        loop {
+            if self.next_is_word("END") {
+                self.pos += 1;
+                return Ok(module);
+            } else if self.next_is_word("IMPORTS") {
+                self.pos += 1;
+                self.imports(&mut module)?;
+            } else if self.next_is_word("EXPORTS") {
+                while !self.next_is_symbol(";") {
+                    self.next()?;
+                }
+                self.pos += 1;
+            } else {
+                self.assignment(&mut module)?;
+            }
+        }
+    }
+
+    fn imports(&mut self, module: &mut Module) -> std::result::Result<(), ParseError> {
+        let mut symbols = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token.is_symbol(";") {
+                return Ok(());
+            } else if token.is_word("FROM") {
+                let from = self.word()?;
+                for symbol in symbols.drain(..) {
+                    module.imports.push((symbol, from.clone()));
+                }
+            } else if token.kind == TokenKind::Word {
+                symbols.push(token.text);
+            }
+        }
+    }
+
+    fn assignment(&mut self, module: &mut Module) -> std::result::Result<(), ParseError> {
+        let name = self.word()?;
+
+        if self.next_is_symbol("::=") {
+            self.pos += 1;
+            return self.type_assignment(name, module);
+        }
+
+        if self.next_is_word("MACRO") {
+            while !self.next_is_word("END") {
+                self.next()?;
+            }
+            self.pos += 1;
+            return Ok(());
+        }
+
+        if self.next_is_word("OBJECT") && self.peek_at(1).is_some_and(|x| x.is_word("IDENTIFIER")) {
+            self.pos += 2;
+            self.expect_symbol("::=")?;
+            let oid = self.oid_value()?;
+            module.nodes.push(Node {
+                name,
+                kind: ObjectKind::ObjectIdentifier,
+                oid,
+                syntax: None,
+                access: None,
+                status: None,
+                units: None,
+                description: None,
+                index: Vec::new(),
+                augments: None,
+            });
+            return Ok(());
+        }
+
+        let kind = self
+            .peek()
+            .filter(|x| x.kind == TokenKind::Word)
+            .and_then(|x| ObjectKind::from_macro(&x.text));
+        match kind {
+            Some(kind) => {
+                self.pos += 1;
+                let node = self.macro_value(name, kind)?;
+                module.nodes.push(node);
+            }
+            // A value of another type, like `name INTEGER ::= 5`.
+            None => {
+                self.syntax()?;
+                self.expect_symbol("::=")?;
+                match self.next_is_symbol("{") {
+                    true => self.skip_block()?,
+                    false => {
+                        self.next()?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn type_assignment(
+        &mut self,
+        name: String,
+        module: &mut Module,
+    ) -> std::result::Result<(), ParseError> {
+        if !self.next_is_word("TEXTUAL-CONVENTION") {
+            let syntax = self.syntax()?;
+            if !matches!(syntax.type_name.as_str(), "SEQUENCE" | "CHOICE") {
+                module.types.push(TypeDef {
+                    name,
+                    display_hint: None,
+                    syntax,
+                    status: None,
+                    description: None,
+                });
+            }
+            return Ok(());
+        }
+
+        self.pos += 1;
+        let mut type_def = TypeDef {
+            name,
+            display_hint: None,
+            syntax: Syntax::default(),
+            status: None,
+            description: None,
+        };
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "DISPLAY-HINT" => type_def.display_hint = Some(self.text()?),
+                "STATUS" => type_def.status = Some(self.word()?),
+                "DESCRIPTION" => type_def.description = Some(self.text()?),
+                "REFERENCE" => {
+                    self.text()?;
+                }
+                "SYNTAX" => {
+                    type_def.syntax = self.syntax()?;
+                    module.types.push(type_def);
+                    return Ok(());
+                }
+                _ => return Err(ParseError::new(token.line, "expected SYNTAX")),
+            }
+        }
+    }
+
+    fn macro_value(
+        &mut self,
+        name: String,
+        kind: ObjectKind,
+    ) -> std::result::Result<Node, ParseError> {
+        let mut node = Node {
+            name,
+            kind,
+            oid: Vec::new(),
+            syntax: None,
+            access: None,
+            status: None,
+            units: None,
+            description: None,
+            index: Vec::new(),
+            augments: None,
+        };
+        let mut enterprise = None;
+
+        while !self.next_is_symbol("::=") {
+            let token = self.next()?;
+            match token.text.as_str() {
+                _ if token.kind == TokenKind::Symbol => {
+                    if matches!(token.text.as_str(), "{" | "(" | "[") {
+                        self.pos -= 1;
+                        self.skip_block()?;
+                    }
+                }
+                "SYNTAX" => {
+                    // Compliance statements and capabilities refine the syntax and access of
+                    // other objects.
+                    let syntax = self.syntax()?;
+                    if kind == ObjectKind::ObjectType && node.syntax.is_none() {
+                        node.syntax = Some(syntax);
+                    }
+                }
+                "MAX-ACCESS" | "ACCESS"
+                    if kind == ObjectKind::ObjectType && node.access.is_none() =>
+                {
+                    node.access = Some(self.word()?)
+                }
+                "STATUS" if node.status.is_none() => node.status = Some(self.word()?),
+                "UNITS" if kind == ObjectKind::ObjectType => node.units = Some(self.text()?),
+                "DESCRIPTION" if node.description.is_none() => {
+                    node.description = Some(self.text()?)
+                }
+                "INDEX" if kind == ObjectKind::ObjectType => node.index = self.index()?,
+                "AUGMENTS" if kind == ObjectKind::ObjectType => {
+                    node.augments = self.index()?.into_iter().next();
+                }
+                "ENTERPRISE" if kind == ObjectKind::TrapType => {
+                    enterprise = Some(match self.next_is_symbol("{") {
+                        true => self.oid_value()?,
+                        false => vec![OidComponent::Name(self.word()?)],
+                    });
+                }
+                _ => (),
+            }
+        }
+        self.expect_symbol("::=")?;
+
+        node.oid = match (kind, enterprise) {
+            (ObjectKind::TrapType, Some(mut enterprise)) => {
+                let number = self.number()?;
+                enterprise.push(OidComponent::Number(0));
+                enterprise.push(OidComponent::Number(number as u64));
+                enterprise
+            }
+            (ObjectKind::TrapType, None) => return Err(self.error("TRAP-TYPE without ENTERPRISE")),
+            _ => self.oid_value()?,
+        };
+
+        Ok(node)
+    }
+
+    // Reads the object names of an `INDEX` or `AUGMENTS` clause.
+    fn index(&mut self) -> std::result::Result<Vec<String>, ParseError> {
+        self.expect_symbol("{")?;
+
+        let mut names = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.kind {
+                TokenKind::Symbol if token.text == "}" => return Ok(names),
+                TokenKind::Word if token.text != "IMPLIED" => names.push(token.text),
+                _ => (),
+            }
+        }
+    }
+
+    // Reads a type: an optional tag, the type itself with its named numbers, and constraints.
+    fn syntax(&mut self) -> std::result::Result<Syntax, ParseError> {
+        if self.next_is_symbol("[") {
+            self.skip_block()?;
+        }
+        if self.next_is_word("IMPLICIT") || self.next_is_word("EXPLICIT") {
+            self.pos += 1;
+        }
+
+        let type_name = match self.word()?.as_str() {
+            "OCTET" => {
+                self.expect_word("STRING")?;
+                "OCTET STRING".to_string()
+            }
+            "OBJECT" => {
+                self.expect_word("IDENTIFIER")?;
+                "OBJECT IDENTIFIER".to_string()
+            }
+            "SEQUENCE" if self.next_is_word("OF") => {
+                self.pos += 1;
+                format!("SEQUENCE OF {}", self.syntax()?.type_name)
+            }
+            "SEQUENCE" | "CHOICE" => {
+                let type_name = self.tokens[self.pos - 1].text.clone();
+                self.skip_block()?;
+                type_name
+            }
+            type_name => type_name.to_string(),
+        };
+
+        let mut enums = Vec::new();
+        if self.next_is_symbol("{") {
+            self.pos += 1;
+            while !self.next_is_symbol("}") {
+                let label = self.word()?;
+                self.expect_symbol("(")?;
+                let number = self.number()?;
+                self.expect_symbol(")")?;
+                enums.push((label, number));
+                if self.next_is_symbol(",") {
+                    self.pos += 1;
+                }
+            }
+            self.pos += 1;
+        }
+
+        while self.next_is_symbol("(") {
+            self.skip_block()?;
+        }
+
+        Ok(Syntax { type_name, enums })
+    }
+
+    fn oid_value(&mut self) -> std::result::Result<Vec<OidComponent>, ParseError> {
+        self.expect_symbol("{")?;
+
+        let mut components = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token.kind {
+                TokenKind::Symbol if token.text == "}" => return Ok(components),
+                TokenKind::Number => components.push(OidComponent::Number(
+                    token
+                        .text
+                        .parse()
+                        .map_err(|_| ParseError::new(token.line, "invalid OID component"))?,
+                )),
+                TokenKind::Word if self.next_is_symbol("(") => {
+                    self.pos += 1;
+                    let number = self.number()?;
+                    self.expect_symbol(")")?;
+                    components.push(OidComponent::NamedNumber(number as u64));
+                }
+                TokenKind::Word => components.push(OidComponent::Name(token.text)),
+                _ => return Err(ParseError::new(token.line, "invalid OID component")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oids::FindOidName;
+    use crate::params::SnmpValue;
+
+    const TEST_MIB: &str = r#"
+TEST-MIB DEFINITIONS ::= BEGIN
+
+IMPORTS
+    MODULE-IDENTITY, OBJECT-TYPE, NOTIFICATION-TYPE, Integer32, enterprises
+        FROM SNMPv2-SMI
+    DisplayString, TEXTUAL-CONVENTION
+        FROM SNMPv2-TC;
+
+testMib MODULE-IDENTITY
+    LAST-UPDATED "202401010000Z"
+    ORGANIZATION "Example"
+    CONTACT-INFO "noc@example.com"
+    DESCRIPTION  "A module with ""quoted"" text -- not a comment."
+    REVISION     "202401010000Z"
+    DESCRIPTION  "Initial revision."
+    ::= { enterprises 99999 }
+
+Celsius ::= TEXTUAL-CONVENTION
+    DISPLAY-HINT "d-1"
+    STATUS       current
+    DESCRIPTION  "Tenths of a degree Celsius."
+    SYNTAX       Integer32 (-1000..1000)
+
+testObjects OBJECT IDENTIFIER ::= { testMib 1 }  -- a comment
+
+testTable OBJECT-TYPE
+    SYNTAX      SEQUENCE OF TestEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "Sensors."
+    ::= { testObjects 1 }
+
+testEntry OBJECT-TYPE
+    SYNTAX      TestEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "A sensor."
+    INDEX       { testIndex }
+    ::= { testTable 1 }
+
+TestEntry ::= SEQUENCE {
+    testIndex  Integer32,
+    testName   DisplayString,
+    testState  INTEGER,
+    testTemp   Celsius
+}
+
+testIndex OBJECT-TYPE
+    SYNTAX      Integer32 (1..2147483647)
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "Index."
+    ::= { testEntry 1 }
+
+testName OBJECT-TYPE
+    SYNTAX      DisplayString (SIZE (0..32))
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "Name."
+    ::= { testEntry 2 }
+
+testState OBJECT-TYPE
+    SYNTAX      INTEGER { ok(1), warning(2), failed(3) }
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "State."
+    DEFVAL      { ok }
+    ::= { testEntry 3 }
+
+testTemp OBJECT-TYPE
+    SYNTAX      Celsius
+    UNITS       "degrees Celsius"
+    MAX-ACCESS  read-only
+    STATUS      current
+    DESCRIPTION "Temperature."
+    ::= { testEntry 4 }
+
+testLimitEntry OBJECT-TYPE
+    SYNTAX      TestLimitEntry
+    MAX-ACCESS  not-accessible
+    STATUS      current
+    DESCRIPTION "Limits of a sensor."
+    AUGMENTS    { testEntry }
+    ::= { testObjects 2 1 }
+
+testFailed NOTIFICATION-TYPE
+    OBJECTS     { testName, testState }
+    STATUS      current
+    DESCRIPTION "A sensor failed."
+    ::= { testMib 0 1 }
+
+END
+"#;
+
+    #[test]
+    fn it_compiles_smiv2_modules() {
+        let mut compiler = MibCompiler::new();
+        compiler.load_str(TEST_MIB).unwrap();
+        assert_eq!(
+            compiler.modules(),
+            vec!["TEST-MIB", "SNMPv2-SMI", "SNMPv2-TC"]
+        );
+
+        let mib = compiler.compile();
+        assert!(mib.unresolved().is_empty(), "{:?}", mib.unresolved());
+
+        let test_mib = mib.object("TEST-MIB::testMib").unwrap();
+        assert_eq!(test_mib.kind, ObjectKind::ModuleIdentity);
+        assert_eq!(test_mib.dotted_oid(), "1.3.6.1.4.1.99999");
+        assert_eq!(
+            test_mib.description.as_deref(),
+            Some("A module with \"quoted\" text -- not a comment.")
+        );
+
+        let state = mib.object("testState").unwrap();
+        assert_eq!(state.dotted_oid(), "1.3.6.1.4.1.99999.1.1.1.3");
+        assert_eq!(state.access.as_deref(), Some("read-only"));
+        let syntax = state.syntax.as_ref().unwrap();
+        assert_eq!(syntax.type_name, "INTEGER");
+        assert_eq!(syntax.enums[2], ("failed".to_string(), 3));
+
+        let table = mib.object("testTable").unwrap();
+        assert_eq!(
+            table.syntax.as_ref().unwrap().type_name,
+            "SEQUENCE OF TestEntry"
+        );
+
+        let failed = mib.object("testFailed").unwrap();
+        assert_eq!(failed.kind, ObjectKind::NotificationType);
+        assert_eq!(failed.oid, vec![1, 3, 6, 1, 4, 1, 99999, 0, 1]);
+
+        let celsius = mib.textual_convention("Celsius").unwrap();
+        assert_eq!(celsius.display_hint.as_deref(), Some("d-1"));
+        assert_eq!(celsius.syntax.type_name, "Integer32");
+        let display_string = mib.textual_convention("SNMPv2-TC::DisplayString").unwrap();
+        assert_eq!(display_string.display_hint.as_deref(), Some("255a"));
+
+        let oid_map = mib.to_oid_map();
+        assert_eq!(
+            oid_map.find_oid_name("1.3.6.1.4.1.99999.1.1.1.2.7"),
+            Some("testName.7".to_string())
+        );
+    }
+
+    #[test]
+    fn it_resolves_object_metadata() {
+        let mut compiler = MibCompiler::new();
+        compiler.load_str(TEST_MIB).unwrap();
+        let oid_map = compiler.compile().to_oid_map();
+        let object = |name: &str| oid_map.oids.iter().find(|x| x.name == name).unwrap();
+
+        let temp = object("testTemp");
+        assert_eq!(temp.module.as_deref(), Some("TEST-MIB"));
+        assert_eq!(temp.units.as_deref(), Some("degrees Celsius"));
+        let syntax = temp.syntax.as_ref().unwrap();
+        assert_eq!(syntax.base_type, "Integer32");
+        assert_eq!(syntax.textual_convention.as_deref(), Some("Celsius"));
+        assert_eq!(syntax.display_hint.as_deref(), Some("d-1"));
+        assert_eq!(
+            temp.format_value(&SnmpValue::Int(215)),
+            "21.5 degrees Celsius"
+        );
+
+        let name = object("testName").syntax.clone().unwrap();
+        assert_eq!(name.base_type, "OCTET STRING");
+        assert_eq!(name.display_hint.as_deref(), Some("255a"));
+
+        assert_eq!(object("testEntry").index, vec!["testIndex"]);
+        assert_eq!(object("testLimitEntry").index, vec!["testIndex"]);
+
+        let state = object("testState");
+        assert_eq!(state.format_value(&SnmpValue::Int(3)), "failed(3)");
+        assert_eq!(state.format_value(&SnmpValue::Int(4)), "4");
+    }
+
+    #[test]
+    fn it_compiles_smiv1_modules() {
+        let text = r#"
+            TEST-V1-MIB DEFINITIONS ::= BEGIN
+            IMPORTS enterprises FROM RFC1155-SMI
+                    OBJECT-TYPE FROM RFC-1212
+                    TRAP-TYPE FROM RFC-1215;
+
+            example OBJECT IDENTIFIER ::= { enterprises 99998 }
+            exampleUptime OBJECT-TYPE
+                SYNTAX  Counter
+                ACCESS  read-only
+                STATUS  mandatory
+                ::= { example 1 }
+            exampleRestart TRAP-TYPE
+                ENTERPRISE example
+                VARIABLES { exampleUptime }
+                ::= 3
+            END
+        "#;
+
+        let mut compiler = MibCompiler::new();
+        compiler.load_str(text).unwrap();
+        let mib = compiler.compile();
+
+        let uptime = mib.object("exampleUptime").unwrap();
+        assert_eq!(uptime.dotted_oid(), "1.3.6.1.4.1.99998.1");
+        assert_eq!(uptime.access.as_deref(), Some("read-only"));
+        assert_eq!(uptime.status.as_deref(), Some("mandatory"));
+        let restart = mib.object("exampleRestart").unwrap();
+        assert_eq!(restart.dotted_oid(), "1.3.6.1.4.1.99998.0.3");
+    }
+
+    #[test]
+    fn it_loads_imports_from_the_search_path() {
+        let dir = std::env::temp_dir().join(format!("k0hax-snmpv3-mibs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("TEST-MIB.txt"), TEST_MIB).unwrap();
+        // Not named after the module, so it's found by scanning the directory.
+        fs::write(
+            dir.join("test-ext.my"),
+            "TEST-EXT-MIB DEFINITIONS ::= BEGIN\n\
+             IMPORTS testObjects FROM TEST-MIB;\n\
+             testExt OBJECT IDENTIFIER ::= { testObjects 9 }\n\
+             END\n",
+        )
+        .unwrap();
+
+        let mut compiler = MibCompiler::new();
+        compiler.add_search_path(&dir);
+        compiler.load_module("TEST-EXT-MIB").unwrap();
+        let result = compiler.load_module("MISSING-MIB").map(|_| ());
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mib = compiler.compile();
+        assert_eq!(
+            mib.object("testExt").unwrap().dotted_oid(),
+            "1.3.6.1.4.1.99999.1.9"
+        );
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+}
@@ -1,5 +1,12 @@
+use crate::error::{Error, Result};
 use crate::Session;
-use snmp_mp::{self, PduType, SnmpMsg, VarBind};
+use snmp_mp::{self, ObjectIdent, PduErrorStatus, PduType, SnmpMsg, VarBind, VarValue};
+use std::convert::TryFrom;
+
+/// `sysUpTime.0`, the first variable binding of a notification.
+pub const SYS_UP_TIME_OID: [u64; 9] = [1, 3, 6, 1, 2, 1, 1, 3, 0];
+/// `snmpTrapOID.0`, the second variable binding of a notification.
+pub const SNMP_TRAP_OID_OID: [u64; 11] = [1, 3, 6, 1, 6, 3, 1, 1, 4, 1, 0];
 
 pub fn create_reportable_msg<D, P, S>(session: &mut Session<D, P, S>) -> SnmpMsg {
     let mut reportable_msg = SnmpMsg::new(session.msg_id());
@@ -31,3 +38,87 @@ where
 
     get_request
 }
+
+/**
+ * Creates a `GetBulkRequest` message.
+ *
+ * A GetBulk PDU reuses the error-status and error-index fields to carry non-repeaters and
+ * max-repetitions. Because `snmp_mp` stores the error-status as a `PduErrorStatus`, only
+ * non-repeaters values that map onto a valid error status (0 to 18) can be encoded; larger
+ * values fail with a `ConfigError`. Non-repeaters is first reduced to the number of bindings, as
+ * RFC 3416 treats a larger value like that number.
+*/
+pub fn create_bulk_request_msg<I, D, P, S>(
+    var_binds_iter: I,
+    non_repeaters: u32,
+    max_repetitions: u32,
+    session: &mut Session<D, P, S>,
+) -> Result<SnmpMsg>
+where
+    I: IntoIterator<Item = VarBind>,
+{
+    let var_binds: Vec<VarBind> = var_binds_iter.into_iter().collect();
+    let non_repeaters = non_repeaters.min(var_binds.len() as u32);
+    let non_repeaters = u8::try_from(non_repeaters)
+        .ok()
+        .and_then(|x| PduErrorStatus::try_from(x).ok())
+        .ok_or_else(|| {
+            Error::config(format!(
+                "unsupported non-repeaters value: {}",
+                non_repeaters
+            ))
+        })?;
+
+    let mut bulk_request = create_request_msg(PduType::GetBulkRequest, var_binds, session);
+    if let Some(scoped_pdu) = bulk_request.scoped_pdu_data.plaintext_mut() {
+        scoped_pdu
+            .set_error_status(non_repeaters)
+            .set_error_index(max_repetitions);
+    }
+
+    Ok(bulk_request)
+}
+
+/**
+ * Creates an `SNMPv2-Trap` or `InformRequest` message.
+ *
+ * The `sysUpTime.0` and `snmpTrapOID.0` variable bindings that start every notification (RFC
+ * 3416) are put before the ones of `var_binds_iter`. Traps are never answered, so only informs are
+ * marked reportable.
+*/
+pub fn create_notification_msg<I, D, P, S>(
+    pdu_type: PduType,
+    uptime: u32,
+    trap_oid: ObjectIdent,
+    var_binds_iter: I,
+    session: &mut Session<D, P, S>,
+) -> SnmpMsg
+where
+    I: IntoIterator<Item = VarBind>,
+{
+    let mut notification = SnmpMsg::new(session.msg_id());
+    if pdu_type == PduType::InformRequest {
+        notification.set_reportable_flag();
+    }
+
+    let var_binds = [
+        VarBind::with_value(
+            ObjectIdent::from_slice(&SYS_UP_TIME_OID),
+            VarValue::TimeTicks(uptime),
+        ),
+        VarBind::with_value(
+            ObjectIdent::from_slice(&SNMP_TRAP_OID_OID),
+            VarValue::ObjectId(trap_oid),
+        ),
+    ];
+
+    if let Some(scoped_pdu) = notification.scoped_pdu_data.plaintext_mut() {
+        scoped_pdu
+            .set_pdu_type(pdu_type)
+            .set_request_id(session.request_id())
+            .set_engine_id(session.engine_id())
+            .set_var_binds(var_binds.into_iter().chain(var_binds_iter));
+    }
+
+    notification
+}
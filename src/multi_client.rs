@@ -0,0 +1,487 @@
+use crate::auth::Digest;
+use crate::client::{decode_msg, encode_msg};
+use crate::error::{Error, Result};
+use crate::session::{Session, Step};
+use mio::net::UdpSocket;
+use mio::{Events, Interest, Poll, Token};
+use snmp_mp::SnmpMsg;
+use snmp_usm::PrivKey;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/**
+ * Client that talks to many agents over a small pool of unconnected UDP sockets.
+ *
+ * Requests are sent with `send_request` and their outcome is collected with `recv`, in the order
+ * responses arrive. Responses are matched to requests by source address and message ID, so an
+ * agent answering from another address than the one it was queried on is not recognized.
+ *
+ * `MultiClient` only moves datagrams: the caller keeps one `Session` per agent and passes it to
+ * `Response::decode`. Requests are not retransmitted; a request that got no answer by its
+ * deadline is reported as `Completion::TimedOut` and can be sent again. When a socket's send
+ * buffer is full, requests are queued and sent by `recv` once the socket is writable again.
+*/
+pub struct MultiClient {
+    poll: Poll,
+    events: Events,
+    pool_size: usize,
+    sockets: Vec<UdpSocket>,
+    // Datagrams waiting for each socket to become writable, in the order they were sent.
+    unsent: Vec<VecDeque<(SocketAddr, Vec<u8>)>>,
+    // Indexes into `sockets` for each address family.
+    ipv4_sockets: Vec<usize>,
+    ipv6_sockets: Vec<usize>,
+    next_socket: usize,
+    pending: HashMap<(SocketAddr, u32), Instant>,
+    deadlines: BinaryHeap<Reverse<(Instant, SocketAddr, u32)>>,
+    completed: VecDeque<Completion>,
+    buf: Vec<u8>,
+}
+
+/**
+ * Outcome of a request sent through a `MultiClient`.
+*/
+#[derive(Debug)]
+pub enum Completion {
+    Response(Response),
+    TimedOut { agent: SocketAddr, msg_id: u32 },
+}
+
+/**
+ * Undecoded response received by a `MultiClient`.
+*/
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub agent: SocketAddr,
+    pub msg_id: u32,
+    datagram: Vec<u8>,
+}
+
+impl Response {
+    /**
+     * Authenticates and decrypts the response with the session of the agent it came from.
+     */
+    pub fn decode<D, P, S>(mut self, session: &mut Session<D, P, S>) -> Result<SnmpMsg>
+    where
+        D: Digest,
+        P: PrivKey,
+    {
+        decode_msg(&mut self.datagram, self.msg_id, session)?.ok_or_else(|| {
+            Error::protocol(format!("response doesn't match message {}", self.msg_id))
+        })
+    }
+}
+
+impl MultiClient {
+    /**
+     * Constructs a `MultiClient` using up to `pool_size` sockets per address family. Sockets are
+     * bound to the unspecified address the first time an agent of their family is queried.
+     */
+    pub fn new(pool_size: usize) -> Result<Self> {
+        if pool_size == 0 {
+            return Err(Error::config("the socket pool can't be empty"));
+        }
+
+        Ok(Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(pool_size * 2),
+            pool_size,
+            sockets: Vec::new(),
+            unsent: Vec::new(),
+            ipv4_sockets: Vec::new(),
+            ipv6_sockets: Vec::new(),
+            next_socket: 0,
+            pending: HashMap::new(),
+            deadlines: BinaryHeap::new(),
+            completed: VecDeque::new(),
+            buf: vec![0; SnmpMsg::MAX_UDP_PACKET_SIZE],
+        })
+    }
+
+    /**
+     * Returns the number of requests still waiting for a response.
+     */
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /**
+     * Encodes `msg` for `session` and sends it to `agent`. The request times out if no response
+     * arrives within `timeout`.
+     */
+    pub fn send_request<D, P, S>(
+        &mut self,
+        agent: SocketAddr,
+        msg: &mut SnmpMsg,
+        session: &mut Session<D, P, S>,
+        timeout: Duration,
+    ) -> Result<()>
+    where
+        D: Digest,
+        P: PrivKey<Salt = S>,
+        S: Step + Copy,
+    {
+        let key = (agent, msg.id());
+        if self.pending.contains_key(&key) {
+            return Err(Error::config(format!(
+                "message {} to {} is already pending",
+                msg.id(),
+                agent
+            )));
+        }
+
+        let encoded_msg = encode_msg(msg, session)?;
+        let socket = self.socket_for(&agent)?;
+        // Only fails before anything was sent or queued, so nothing is left to clean up.
+        self.send_or_queue(socket, agent, encoded_msg)?;
+
+        let deadline = Instant::now() + timeout;
+        self.pending.insert(key, deadline);
+        self.deadlines.push(Reverse((deadline, agent, msg.id())));
+
+        Ok(())
+    }
+
+    /**
+     * Waits for the next request to complete.
+     *
+     * Returns `None` once no request is pending. Datagrams that don't answer a pending request
+     * are dropped.
+     */
+    pub fn recv(&mut self) -> Result<Option<Completion>> {
+        loop {
+            if let Some(completion) = self.completed.pop_front() {
+                return Ok(Some(completion));
+            }
+
+            let now = Instant::now();
+            self.expire(now);
+            if !self.completed.is_empty() {
+                continue;
+            }
+
+            if self.pending.is_empty() {
+                self.deadlines.clear();
+                return Ok(None);
+            }
+
+            // The earliest deadline may belong to an answered request, which only makes the poll
+            // wake up early.
+            let next_deadline = match self.deadlines.peek() {
+                Some(Reverse((deadline, _, _))) => *deadline,
+                None => return Ok(None),
+            };
+
+            match self.poll.poll(
+                &mut self.events,
+                Some(next_deadline.saturating_duration_since(now)),
+            ) {
+                Ok(()) => (),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error.into()),
+            }
+
+            let ready: Vec<(usize, bool, bool)> = self
+                .events
+                .iter()
+                .map(|event| (event.token().0, event.is_readable(), event.is_writable()))
+                .collect();
+            for (socket, readable, writable) in ready {
+                if writable {
+                    self.flush(socket)?;
+                }
+                if readable {
+                    self.drain(socket)?;
+                }
+            }
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some(Reverse((deadline, agent, msg_id))) = self.deadlines.peek().copied() {
+            if deadline > now {
+                break;
+            }
+
+            self.deadlines.pop();
+            // Entries for requests that already got their response are skipped.
+            if self.pending.get(&(agent, msg_id)) == Some(&deadline) {
+                self.pending.remove(&(agent, msg_id));
+                self.completed
+                    .push_back(Completion::TimedOut { agent, msg_id });
+            }
+        }
+    }
+
+    // Reads every queued datagram, as readiness is only reported once per batch.
+    fn drain(&mut self, socket: usize) -> Result<()> {
+        loop {
+            let (len, agent) = match self.sockets[socket].recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                // ICMP errors for one agent must not stop the others from being served.
+                Err(error) if error.kind() == ErrorKind::ConnectionRefused => continue,
+                Err(error) => return Err(error.into()),
+            };
+
+            let datagram = &self.buf[..len];
+            let msg_id = match SnmpMsg::decode(datagram) {
+                Ok(msg) => msg.id(),
+                Err(_) => continue,
+            };
+
+            if self.pending.remove(&(agent, msg_id)).is_some() {
+                self.completed.push_back(Completion::Response(Response {
+                    agent,
+                    msg_id,
+                    datagram: datagram.to_vec(),
+                }));
+            }
+        }
+    }
+
+    fn send_or_queue(&mut self, socket: usize, agent: SocketAddr, datagram: Vec<u8>) -> Result<()> {
+        // Sending ahead of queued datagrams would reorder them.
+        if !self.unsent[socket].is_empty() {
+            self.unsent[socket].push_back((agent, datagram));
+            return Ok(());
+        }
+
+        loop {
+            match self.sockets[socket].send_to(&datagram, agent) {
+                Ok(_) => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    return self.queue(socket, agent, datagram);
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                // The request times out, like a lost datagram, so other agents keep being served.
+                Err(_) => return Ok(()),
+            }
+        }
+    }
+
+    fn queue(&mut self, socket: usize, agent: SocketAddr, datagram: Vec<u8>) -> Result<()> {
+        if self.unsent[socket].is_empty() {
+            self.poll.registry().reregister(
+                &mut self.sockets[socket],
+                Token(socket),
+                Interest::READABLE | Interest::WRITABLE,
+            )?;
+        }
+        self.unsent[socket].push_back((agent, datagram));
+
+        Ok(())
+    }
+
+    // Sends queued datagrams until the send buffer is full again.
+    fn flush(&mut self, socket: usize) -> Result<()> {
+        while let Some((agent, datagram)) = self.unsent[socket].front() {
+            match self.sockets[socket].send_to(datagram, *agent) {
+                Ok(_) => {
+                    self.unsent[socket].pop_front();
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                // The request stays pending and times out, like a lost datagram.
+                Err(_) => {
+                    self.unsent[socket].pop_front();
+                }
+            }
+        }
+
+        self.poll.registry().reregister(
+            &mut self.sockets[socket],
+            Token(socket),
+            Interest::READABLE,
+        )?;
+        Ok(())
+    }
+
+    fn socket_for(&mut self, agent: &SocketAddr) -> Result<usize> {
+        let (family, unspecified) = match agent {
+            SocketAddr::V4(_) => (&mut self.ipv4_sockets, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            SocketAddr::V6(_) => (&mut self.ipv6_sockets, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        };
+
+        if family.len() < self.pool_size {
+            let mut socket = UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+            let index = self.sockets.len();
+            self.poll
+                .registry()
+                .register(&mut socket, Token(index), Interest::READABLE)?;
+            self.sockets.push(socket);
+            self.unsent.push(VecDeque::new());
+            family.push(index);
+
+            return Ok(index);
+        }
+
+        self.next_socket = self.next_socket.wrapping_add(1);
+        Ok(family[self.next_socket % family.len()])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg_factory;
+    use snmp_usm::{DesPrivKey, Md5};
+
+    type TestSession = Session<'static, Md5, DesPrivKey<'static, Md5>, u32>;
+
+    #[test]
+    fn it_demultiplexes_responses_by_agent() {
+        let echoing_agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let echoing_addr = echoing_agent.local_addr().unwrap();
+        let silent_addr = silent_agent.local_addr().unwrap();
+
+        let mut client = MultiClient::new(1).unwrap();
+        let mut echoing_session = TestSession::undiscovered();
+        let mut silent_session = TestSession::undiscovered();
+
+        let mut msg = msg_factory::create_reportable_msg(&mut echoing_session);
+        let echoed_id = msg.id();
+        client
+            .send_request(
+                echoing_addr,
+                &mut msg,
+                &mut echoing_session,
+                Duration::from_secs(5),
+            )
+            .unwrap();
+
+        let mut msg = msg_factory::create_reportable_msg(&mut silent_session);
+        let timed_out_id = msg.id();
+        client
+            .send_request(
+                silent_addr,
+                &mut msg,
+                &mut silent_session,
+                Duration::from_millis(20),
+            )
+            .unwrap();
+        assert_eq!(client.pending(), 2);
+
+        let mut buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+        let (len, client_addr) = echoing_agent.recv_from(&mut buf).unwrap();
+        echoing_agent.send_to(&buf[..len], client_addr).unwrap();
+
+        let mut responses = 0;
+        let mut timeouts = 0;
+        while let Some(completion) = client.recv().unwrap() {
+            match completion {
+                Completion::Response(response) => {
+                    assert_eq!(response.agent, echoing_addr);
+                    let msg = response.decode(&mut echoing_session).unwrap();
+                    assert_eq!(msg.id(), echoed_id);
+                    responses += 1;
+                }
+                Completion::TimedOut { agent, msg_id } => {
+                    assert_eq!(agent, silent_addr);
+                    assert_eq!(msg_id, timed_out_id);
+                    timeouts += 1;
+                }
+            }
+        }
+
+        assert_eq!((responses, timeouts), (1, 1));
+        assert_eq!(client.pending(), 0);
+    }
+
+    #[test]
+    fn it_sends_queued_datagrams_once_writable() {
+        let agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let agent_addr = agent.local_addr().unwrap();
+
+        let mut client = MultiClient::new(1).unwrap();
+        let mut session = TestSession::undiscovered();
+        let mut msg = msg_factory::create_reportable_msg(&mut session);
+        client
+            .send_request(
+                agent_addr,
+                &mut msg,
+                &mut session,
+                Duration::from_millis(50),
+            )
+            .unwrap();
+
+        // As if the send buffer had been full.
+        client.queue(0, agent_addr, b"queued".to_vec()).unwrap();
+        let mut msg = msg_factory::create_reportable_msg(&mut session);
+        client
+            .send_request(
+                agent_addr,
+                &mut msg,
+                &mut session,
+                Duration::from_millis(50),
+            )
+            .unwrap();
+        assert_eq!(client.unsent[0].len(), 2);
+
+        while client.recv().unwrap().is_some() {}
+        assert!(client.unsent[0].is_empty());
+
+        let mut buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let (len, _) = agent.recv_from(&mut buf).unwrap();
+            received.push(buf[..len].to_vec());
+        }
+        assert_eq!(received[1], b"queued");
+        assert_eq!(SnmpMsg::decode(&received[2]).unwrap().id(), msg.id());
+    }
+
+    #[test]
+    fn it_times_out_requests_that_fail_to_send() {
+        let echoing_agent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let echoing_addr = echoing_agent.local_addr().unwrap();
+        // Sending to the broadcast address fails without SO_BROADCAST.
+        let unreachable: SocketAddr = "255.255.255.255:161".parse().unwrap();
+
+        let mut client = MultiClient::new(1).unwrap();
+        let mut session = TestSession::undiscovered();
+        let mut msg = msg_factory::create_reportable_msg(&mut session);
+        let failed_id = msg.id();
+        client
+            .send_request(
+                unreachable,
+                &mut msg,
+                &mut session,
+                Duration::from_millis(20),
+            )
+            .unwrap();
+
+        // A queued datagram failing the same way doesn't stop the ones behind it.
+        client.queue(0, unreachable, b"queued".to_vec()).unwrap();
+        let mut msg = msg_factory::create_reportable_msg(&mut session);
+        let echoed_id = msg.id();
+        client
+            .send_request(echoing_addr, &mut msg, &mut session, Duration::from_secs(5))
+            .unwrap();
+
+        let echo = std::thread::spawn(move || {
+            let mut buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+            let (len, client_addr) = echoing_agent.recv_from(&mut buf).unwrap();
+            echoing_agent.send_to(&buf[..len], client_addr).unwrap();
+        });
+        let mut completions = Vec::new();
+        while let Some(completion) = client.recv().unwrap() {
+            completions.push(completion);
+        }
+        echo.join().unwrap();
+
+        assert!(completions.iter().any(|completion| matches!(
+            completion,
+            Completion::TimedOut { agent, msg_id } if *agent == unreachable && *msg_id == failed_id
+        )));
+        assert!(completions.iter().any(|completion| matches!(
+            completion,
+            Completion::Response(response) if response.msg_id == echoed_id
+        )));
+    }
+}
@@ -1,14 +1,114 @@
+use crate::display_hint;
+use crate::params::{SnmpResult, SnmpValue};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub trait FindOidName {
-    fn find_oid_name(self, input: String) -> Option<String>;
+    fn find_oid_name(&self, input: &str) -> Option<String>;
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/**
+ * A named OID, with the MIB definition of the object when it's known.
+ *
+ * Only `oid` and `name` are required in JSON, so hand-written OID maps stay valid; the other
+ * fields are filled in by `mib::Mib::to_oid_map`.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
 pub struct OID {
     pub oid: String,
     pub name: String,
+    /// MIB module defining the object.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syntax: Option<ObjectSyntax>,
+    /// `MAX-ACCESS`, like `read-only` or `read-create`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Objects named in the `INDEX` clause of a conceptual row, or of the row it `AUGMENTS`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub index: Vec<String>,
+}
+
+impl OID {
+    /**
+     * Formats `value` the way the object's syntax describes it: `INTEGER` values by their
+     * enumeration label, like `down(2)`, `BITS` by the labels of the bits set, values of textual
+     * conventions with their display hint, and numbers followed by their `UNITS`.
+     */
+    pub fn format_value(&self, value: &SnmpValue) -> String {
+        let enums = self.syntax.as_ref().map_or(&[][..], |x| &x.enums[..]);
+        let is_bits = self.syntax.as_ref().is_some_and(|x| x.base_type == "BITS");
+
+        match value {
+            SnmpValue::Int(x) => {
+                if let Some((label, _)) = enums.iter().find(|(_, number)| *number == i64::from(*x))
+                {
+                    return format!("{}({})", label, x);
+                }
+            }
+            SnmpValue::OctetString(bits) if is_bits && !enums.is_empty() => {
+                return enums
+                    .iter()
+                    .filter(|(_, number)| is_bit_set(bits, *number))
+                    .map(|(label, number)| format!("{}({})", label, number))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+            }
+            _ => (),
+        }
+
+        let formatted = self
+            .syntax
+            .as_ref()
+            .and_then(|x| display_hint::format_value(value, x))
+            .unwrap_or_else(|| value.to_string());
+        match (value, &self.units) {
+            (
+                SnmpValue::Int(_)
+                | SnmpValue::Counter(_)
+                | SnmpValue::UnsignedInt(_)
+                | SnmpValue::BigCounter(_),
+                Some(units),
+            ) => format!("{} {}", formatted, units),
+            _ => formatted,
+        }
+    }
+}
+
+// Bit 0 is the most significant bit of the first octet.
+fn is_bit_set(bits: &[u8], number: i64) -> bool {
+    let number = match usize::try_from(number) {
+        Ok(number) => number,
+        Err(_) => return false,
+    };
+
+    bits.get(number / 8)
+        .is_some_and(|x| x & (0x80 >> (number % 8)) != 0)
+}
+
+/**
+ * The `SYNTAX` of an object.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ObjectSyntax {
+    /// SMI base type, like `INTEGER`, `OCTET STRING`, `BITS` or `Counter32`.
+    pub base_type: String,
+    /// Textual convention the syntax refers to, like `DisplayString`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub textual_convention: Option<String>,
+    /// `DISPLAY-HINT` of the textual convention, or of the ones it's derived from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_hint: Option<String>,
+    /// Labels of the `INTEGER` values or `BITS`, with their number.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub enums: Vec<(String, i64)>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -16,29 +116,243 @@ pub struct OidMap {
     pub oids: Vec<OID>,
 }
 
-impl FindOidName for OidMap {
-    fn find_oid_name(self, input: String) -> Option<String> {
-        let oid_map: HashMap<String, String> = self
-            .oids
+impl OidMap {
+    /**
+     * Finds the entry with the longest OID `oid` starts with, and returns it with the rest of
+     * `oid`, usually the instance.
+     */
+    pub fn find<'a, 'b>(&'a self, oid: &'b str) -> Option<(&'a OID, &'b str)> {
+        self.oids
             .iter()
-            .map(|x| (x.oid.clone(), x.name.clone()))
-            .collect();
-        let input_parts: Vec<&str> = input.as_str().split(".").collect();
-        for i in (0..input_parts.len() + 1).rev() {
-            let this_try = input_parts[0..i].join(".");
-            match oid_map.get(this_try.as_str()) {
-                Some(x) => {
-                    let this_val = x.to_string();
-                    let remainder = input_parts[i..input_parts.len()].join(".").to_string();
-                    if remainder.len() == 0 {
-                        return Some(this_val);
-                    }
-                    let retval = vec![this_val, remainder].join(".");
-                    return Some(retval);
+            .filter_map(|x| {
+                let suffix = oid.strip_prefix(x.oid.as_str())?;
+                match suffix.strip_prefix('.') {
+                    Some(suffix) => Some((x, suffix)),
+                    None if suffix.is_empty() => Some((x, suffix)),
+                    None => None,
                 }
-                _ => (),
+            })
+            .max_by_key(|(x, _)| x.oid.len())
+    }
+
+    /**
+     * Formats a result as `name.instance = value`, like `ifOperStatus.3 = down(2)`, using the
+     * definition of the object to format the value.
+     */
+    pub fn format_result(&self, result: &SnmpResult) -> String {
+        format_found(result, self.find(result_oid(result)), None)
+    }
+
+    /**
+     * Formats results like `format_result`, reading each `InetAddress` with the `InetAddressType`
+     * of the same row when it's among `results`.
+     *
+     * The type is taken from the closest `InetAddressType` column before the address, as RFC 4001
+     * has them defined in pairs.
+     */
+    pub fn format_results(&self, results: &[SnmpResult]) -> Vec<String> {
+        let found: Vec<_> = results
+            .iter()
+            .map(|result| self.find(result_oid(result)))
+            .collect();
+
+        // Columns and values of the address types, by conceptual row and instance.
+        let mut address_types: HashMap<(&str, &str), Vec<(u64, i64)>> = HashMap::new();
+        for (result, found) in results.iter().zip(&found) {
+            let (object, instance) = match found {
+                Some(found) if textual_convention(found.0) == Some("InetAddressType") => *found,
+                _ => continue,
             };
+            if let (Some((entry, column)), Some(SnmpValue::Int(address_type))) =
+                (column_of(object), &result.result)
+            {
+                address_types
+                    .entry((entry, instance))
+                    .or_default()
+                    .push((column, i64::from(*address_type)));
+            }
         }
-        None
+
+        results
+            .iter()
+            .zip(&found)
+            .map(|(result, found)| {
+                let address_type = found
+                    .filter(|(object, _)| textual_convention(object) == Some("InetAddress"))
+                    .and_then(|(object, instance)| {
+                        let (entry, column) = column_of(object)?;
+                        address_types
+                            .get(&(entry, instance))?
+                            .iter()
+                            .filter(|(type_column, _)| *type_column < column)
+                            .max_by_key(|(type_column, _)| *type_column)
+                            .map(|(_, address_type)| *address_type)
+                    });
+                format_found(result, *found, address_type)
+            })
+            .collect()
+    }
+}
+
+// Formats a result with the object it was found to be an instance of.
+fn format_found(
+    result: &SnmpResult,
+    found: Option<(&OID, &str)>,
+    address_type: Option<i64>,
+) -> String {
+    let value = match &result.result {
+        Some(value) => value,
+        None => return format!("{} = <none>", result.oid),
+    };
+
+    match found {
+        Some((object, suffix)) if result.value().is_some() => {
+            let formatted = match (address_type, value) {
+                (Some(address_type), SnmpValue::OctetString(octets)) => {
+                    display_hint::format_inet_address(address_type, octets)
+                }
+                _ => None,
+            }
+            .unwrap_or_else(|| object.format_value(value));
+
+            match suffix {
+                "" => format!("{} = {}", object.name, formatted),
+                suffix => format!("{}.{} = {}", object.name, suffix, formatted),
+            }
+        }
+        _ => format!("{} = {}", result.oid, value),
+    }
+}
+
+fn result_oid(result: &SnmpResult) -> &str {
+    match result.returned_oid.as_str() {
+        "" => result.oid.as_str(),
+        returned_oid => returned_oid,
+    }
+}
+
+fn textual_convention(object: &OID) -> Option<&str> {
+    object.syntax.as_ref()?.textual_convention.as_deref()
+}
+
+// Splits a column OID into the OID of its conceptual row and the column number.
+fn column_of(object: &OID) -> Option<(&str, u64)> {
+    let (entry, column) = object.oid.rsplit_once('.')?;
+    Some((entry, column.parse().ok()?))
+}
+
+impl FindOidName for OidMap {
+    fn find_oid_name(&self, input: &str) -> Option<String> {
+        match self.find(input)? {
+            (x, "") => Some(x.name.clone()),
+            (x, suffix) => Some([x.name.as_str(), suffix].join(".")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn if_oper_status() -> OID {
+        OID {
+            oid: "1.3.6.1.2.1.2.2.1.8".to_string(),
+            name: "ifOperStatus".to_string(),
+            module: Some("IF-MIB".to_string()),
+            syntax: Some(ObjectSyntax {
+                base_type: "INTEGER".to_string(),
+                enums: vec![("up".to_string(), 1), ("down".to_string(), 2)],
+                ..Default::default()
+            }),
+            access: Some("read-only".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_formats_results_with_object_metadata() {
+        let oid_map = OidMap {
+            oids: vec![
+                OID {
+                    oid: "1.3.6.1.2.1.2".to_string(),
+                    name: "interfaces".to_string(),
+                    ..Default::default()
+                },
+                if_oper_status(),
+            ],
+        };
+
+        let result = SnmpResult {
+            host: "h".to_string(),
+            oid: "ifOperStatus.3".to_string(),
+            requested_oid: "1.3.6.1.2.1.2.2.1.8.3".to_string(),
+            returned_oid: "1.3.6.1.2.1.2.2.1.8.3".to_string(),
+            outcome: Default::default(),
+            result: Some(SnmpValue::Int(2)),
+        };
+        assert_eq!(oid_map.format_result(&result), "ifOperStatus.3 = down(2)");
+
+        let (object, suffix) = oid_map.find("1.3.6.1.2.1.2.2.1.80.1").unwrap();
+        assert_eq!((object.name.as_str(), suffix), ("interfaces", "2.1.80.1"));
+    }
+
+    #[test]
+    fn it_reads_inet_addresses_with_their_type() {
+        let column = |column: u64, name: &str, tc: &str| OID {
+            oid: format!("1.3.6.1.2.1.4.24.7.1.{}", column),
+            name: name.to_string(),
+            syntax: Some(ObjectSyntax {
+                base_type: "OCTET STRING".to_string(),
+                textual_convention: Some(tc.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let oid_map = OidMap {
+            oids: vec![
+                column(6, "inetCidrRouteNextHopType", "InetAddressType"),
+                column(7, "inetCidrRouteNextHop", "InetAddress"),
+            ],
+        };
+        let result = |oid: &str, value: SnmpValue| SnmpResult {
+            host: "h".to_string(),
+            oid: oid.to_string(),
+            requested_oid: oid.to_string(),
+            returned_oid: oid.to_string(),
+            outcome: Default::default(),
+            result: Some(value),
+        };
+
+        let results = vec![
+            result("1.3.6.1.2.1.4.24.7.1.6.1", SnmpValue::Int(1)),
+            result(
+                "1.3.6.1.2.1.4.24.7.1.7.1",
+                SnmpValue::OctetString(vec![192, 0, 2, 1]),
+            ),
+            result(
+                "1.3.6.1.2.1.4.24.7.1.7.2",
+                SnmpValue::OctetString(vec![192, 0, 2, 1]),
+            ),
+        ];
+        let formatted = oid_map.format_results(&results);
+        assert_eq!(formatted[1], "inetCidrRouteNextHop.1 = 192.0.2.1");
+        // The type of row 2 wasn't returned, so the address isn't guessed from its length.
+        assert_eq!(formatted[2], "inetCidrRouteNextHop.2 = C0 00 02 01");
+    }
+
+    #[test]
+    fn it_reads_hand_written_oid_maps() {
+        let oid_map: OidMap = serde_json::from_str(
+            r#"{"oids": [{"oid": "1.3.6.1.2.1.1.1.0", "name": "sysDescr.0"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(oid_map.oids[0].syntax, None);
+
+        let json = serde_json::to_string(&OidMap {
+            oids: vec![if_oper_status()],
+        })
+        .unwrap();
+        let oid_map: OidMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(oid_map.oids[0], if_oper_status());
     }
 }
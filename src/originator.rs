@@ -0,0 +1,320 @@
+use crate::addr;
+use crate::auth::{Digest, Sha224, Sha256, Sha384, Sha512};
+use crate::client::Client;
+use crate::error::{Error, ReportKind, Result};
+use crate::oids::OID;
+use crate::params::{AuthProtocol, PrivProtocol, SnmpValue, UsmUser};
+use crate::priv_key::{Aes192CPrivKey, Aes192PrivKey, Aes256CPrivKey, Aes256PrivKey, WithPasswd};
+use crate::receiver::SNMP_TRAP_PORT_NUM;
+use crate::request;
+use crate::retry::RetryPolicy;
+use crate::session::{Session, Step};
+use rand::distributions::{Distribution, Standard};
+use snmp_usm::{Aes128PrivKey, DesPrivKey, Md5, PrivKey, Sha1};
+use std::time::Instant;
+
+macro_rules! open_fn {
+    ($digest:ty, $user:expr) => {{
+        match $user.privacy_protocol {
+            Some(PrivProtocol::Aes128) => open_with!($digest, Aes128PrivKey<$digest>),
+            Some(PrivProtocol::Aes192) => open_with!($digest, Aes192PrivKey<$digest>),
+            Some(PrivProtocol::Aes256) => open_with!($digest, Aes256PrivKey<$digest>),
+            Some(PrivProtocol::Aes192C) => open_with!($digest, Aes192CPrivKey<$digest>),
+            Some(PrivProtocol::Aes256C) => open_with!($digest, Aes256CPrivKey<$digest>),
+            Some(PrivProtocol::Des) | None => open_with!($digest, DesPrivKey<$digest>),
+        }
+    }};
+}
+
+macro_rules! open_with {
+    ($digest:ty, $priv_key:ty) => {
+        open::<$digest, $priv_key, <$priv_key as PrivKey>::Salt> as OpenFn
+    };
+}
+
+type OpenFn = fn(&UsmUser, Client, &[u8], u32) -> Result<Box<dyn Notify + Send>>;
+
+/**
+ * Sends SNMPv3 notifications to a notification receiver.
+ *
+ * Traps are sent by the local engine, identified by the engine ID and boots given to `new`, so
+ * the receiver must know the user's keys localized to that engine ID. Informs are sent to the
+ * receiver's engine, which is discovered before the first inform is sent.
+*/
+pub struct NotificationOriginator {
+    host: String,
+    start_time: Instant,
+    inner: Box<dyn Notify + Send>,
+}
+
+impl NotificationOriginator {
+    /**
+     * Connects to the receiver `host`, on port 162 unless it names another one, to send
+     * notifications as `user`.
+     *
+     * `engine_boots` should be incremented, and persisted, every time the local engine restarts,
+     * as receivers reject traps from an engine that went back in time.
+     */
+    pub fn new(host: &str, user: &UsmUser, engine_id: &[u8], engine_boots: u32) -> Result<Self> {
+        user.validate()?;
+        if engine_id.is_empty() {
+            return Err(Error::config("the engine ID can't be empty"));
+        }
+
+        let client = Client::new(addr::resolve(host, SNMP_TRAP_PORT_NUM)?)?;
+
+        // Without authentication the digest type is never used.
+        let open = match user.auth_protocol {
+            Some(AuthProtocol::Md5) | None => open_fn!(Md5, user),
+            Some(AuthProtocol::Sha1) => open_fn!(Sha1, user),
+            Some(AuthProtocol::Sha224) => open_fn!(Sha224, user),
+            Some(AuthProtocol::Sha256) => open_fn!(Sha256, user),
+            Some(AuthProtocol::Sha384) => open_fn!(Sha384, user),
+            Some(AuthProtocol::Sha512) => open_fn!(Sha512, user),
+        };
+        let inner = open(user, client, engine_id, engine_boots)?;
+
+        Ok(Self {
+            host: host.to_string(),
+            start_time: Instant::now(),
+            inner,
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /**
+     * Returns the local `sysUpTime` sent with notifications, in hundredths of a second since the
+     * originator was created.
+     */
+    pub fn uptime(&self) -> u32 {
+        (self.start_time.elapsed().as_millis() / 10) as u32
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    /**
+     * Changes the timeout and retransmission settings used by later informs.
+     */
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()> {
+        self.inner.set_retry_policy(retry_policy)
+    }
+
+    /**
+     * Sends an `SNMPv2-Trap` for `trap_oid`, e.g. `linkDown`, with the given bindings.
+     */
+    pub fn send_trap(&mut self, trap_oid: &OID, bindings: Vec<(OID, SnmpValue)>) -> Result<()> {
+        let uptime = self.uptime();
+        self.inner.send_trap(trap_oid, uptime, bindings)
+    }
+
+    /**
+     * Sends an `InformRequest` for `trap_oid` and waits until the receiver acknowledges it.
+     *
+     * A `TimeoutError` is returned when no acknowledgement arrives within the retry policy.
+     */
+    pub fn send_inform(&mut self, trap_oid: &OID, bindings: Vec<(OID, SnmpValue)>) -> Result<()> {
+        let uptime = self.uptime();
+        self.inner.send_inform(trap_oid, uptime, bindings)
+    }
+}
+
+// Object safe view of the client and the two sessions, so the digest and privacy types chosen at
+// runtime don't leak into `NotificationOriginator`.
+trait Notify {
+    fn retry_policy(&self) -> &RetryPolicy;
+    fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()>;
+    fn send_trap(
+        &mut self,
+        trap_oid: &OID,
+        uptime: u32,
+        bindings: Vec<(OID, SnmpValue)>,
+    ) -> Result<()>;
+    fn send_inform(
+        &mut self,
+        trap_oid: &OID,
+        uptime: u32,
+        bindings: Vec<(OID, SnmpValue)>,
+    ) -> Result<()>;
+}
+
+struct Handle<D: 'static, P, S> {
+    client: Client,
+    user: UsmUser,
+    salt: S,
+    // The local engine is authoritative for traps.
+    trap_session: Session<'static, D, P, S>,
+    // The receiver's engine is authoritative for informs. `None` until it's discovered.
+    inform_session: Option<Session<'static, D, P, S>>,
+}
+
+impl<D, P, S> Notify for Handle<D, P, S>
+where
+    D: Digest,
+    P: PrivKey<Salt = S> + WithPasswd<'static, D>,
+    S: Step + Copy,
+{
+    fn retry_policy(&self) -> &RetryPolicy {
+        self.client.retry_policy()
+    }
+
+    fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()> {
+        self.client.set_retry_policy(retry_policy)?;
+        Ok(())
+    }
+
+    fn send_trap(
+        &mut self,
+        trap_oid: &OID,
+        uptime: u32,
+        bindings: Vec<(OID, SnmpValue)>,
+    ) -> Result<()> {
+        request::snmp_trap(
+            trap_oid,
+            uptime,
+            bindings,
+            &self.client,
+            &mut self.trap_session,
+        )
+    }
+
+    fn send_inform(
+        &mut self,
+        trap_oid: &OID,
+        uptime: u32,
+        bindings: Vec<(OID, SnmpValue)>,
+    ) -> Result<()> {
+        let session = match self.inform_session.take() {
+            Some(session) => session,
+            None => discover(&mut self.client, &self.user, self.salt)?,
+        };
+        let session = self.inform_session.insert(session);
+        let result = request::snmp_inform(
+            trap_oid,
+            uptime,
+            bindings.clone(),
+            &mut self.client,
+            session,
+        );
+
+        match result {
+            // The receiver restarted with another engine ID, so it's discovered again.
+            Err(Error::ReportPdu(report)) if report.kind == ReportKind::UnknownEngineId => {
+                let session = discover(&mut self.client, &self.user, self.salt)?;
+                let session = self.inform_session.insert(session);
+                request::snmp_inform(trap_oid, uptime, bindings, &mut self.client, session)
+            }
+            result => result,
+        }
+    }
+}
+
+// Discovers the receiver's engine and localizes the user's keys to it.
+fn discover<D, P, S>(
+    client: &mut Client,
+    user: &UsmUser,
+    salt: S,
+) -> Result<Session<'static, D, P, S>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S> + WithPasswd<'static, D>,
+    S: Step + Copy,
+{
+    let mut session = Session::<D, P, S>::new(client, user.user.as_bytes())?;
+    session.set_passwords(user.auth.as_deref(), user.privacy.as_deref(), salt)?;
+
+    Ok(session)
+}
+
+fn open<D, P, S>(
+    user: &UsmUser,
+    client: Client,
+    engine_id: &[u8],
+    engine_boots: u32,
+) -> Result<Box<dyn Notify + Send>>
+where
+    D: 'static + Digest + Send + Sync,
+    P: 'static + PrivKey<Salt = S> + WithPasswd<'static, D> + Send,
+    S: 'static + Step + Copy + Send,
+    Standard: Distribution<S>,
+{
+    let salt = rand::random();
+    let mut trap_session = Session::<D, P, S>::local(user.user.as_bytes(), engine_id, engine_boots);
+    trap_session.set_passwords(user.auth.as_deref(), user.privacy.as_deref(), salt)?;
+
+    Ok(Box::new(Handle {
+        client,
+        user: user.clone(),
+        salt,
+        trap_session,
+        inform_session: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::oids::OidMap;
+    use crate::receiver::{NotificationKind, NotificationReceiver};
+    use std::time::Duration;
+
+    #[test]
+    fn it_sends_traps_and_informs() {
+        let user = UsmUser {
+            user: "appliance".to_string(),
+            auth: Some("maplesyrup".to_string()),
+            auth_protocol: Some(AuthProtocol::Sha1),
+            privacy: Some("pancakes1".to_string()),
+            privacy_protocol: Some(PrivProtocol::Aes256),
+        };
+        let local_engine_id = b"appliance-engine";
+        let link_down = OID {
+            oid: "1.3.6.1.6.3.1.1.5.3".to_string(),
+            name: "linkDown".to_string(),
+            ..Default::default()
+        };
+        let if_index = OID {
+            oid: "1.3.6.1.2.1.2.2.1.1.3".to_string(),
+            name: "ifIndex.3".to_string(),
+            ..Default::default()
+        };
+
+        let mut receiver =
+            NotificationReceiver::bind("127.0.0.1:0", OidMap { oids: Vec::new() }).unwrap();
+        let receiver_engine_id = receiver.engine_id().to_vec();
+        receiver
+            .add_user(local_engine_id, &user)
+            .unwrap()
+            .add_user(&receiver_engine_id, &user)
+            .unwrap();
+        let host = receiver.local_addr().unwrap().to_string();
+        let notifications = receiver.spawn();
+
+        let mut originator = NotificationOriginator::new(&host, &user, local_engine_id, 3).unwrap();
+        originator
+            .send_trap(&link_down, vec![(if_index.clone(), SnmpValue::Int(3))])
+            .unwrap();
+        let trap = notifications
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(trap.kind, NotificationKind::Trap);
+        assert_eq!(trap.engine_id, local_engine_id);
+        assert_eq!(trap.trap_oid.unwrap().oid, link_down.oid);
+
+        originator
+            .send_inform(&link_down, vec![(if_index, SnmpValue::Int(3))])
+            .unwrap();
+        let inform = notifications
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(inform.kind, NotificationKind::Inform);
+        assert_eq!(inform.engine_id, receiver_engine_id);
+        assert_eq!(inform.var_binds[0].returned_oid, "1.3.6.1.2.1.2.2.1.1.3");
+    }
+}
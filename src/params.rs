@@ -1,8 +1,14 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::convert::From;
 use std::fmt;
+use std::str::FromStr;
 
+use crate::error::ConfigError;
 use crate::oids::OID;
+use crate::retry::RetryPolicy;
+
+// Shortest password accepted for authentication and privacy.
+const MIN_PASSWD_LEN: usize = 8;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct ObjectIdentifier {
@@ -14,35 +20,295 @@ pub struct Params {
     pub user: String,
     pub host: String,
     pub auth: Option<String>,
-    pub auth_protocol: Option<String>,
+    pub auth_protocol: Option<AuthProtocol>,
     pub privacy: Option<String>,
-    pub privacy_protocol: Option<String>,
+    pub privacy_protocol: Option<PrivProtocol>,
+    /// Local address to send requests from, e.g. `192.0.2.10` or `[2001:db8::10]:16100`.
+    #[serde(default)]
+    pub local_addr: Option<String>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
     pub cmd: Command,
 }
 
 impl Params {
-    pub const MD5_DIGEST: &'static str = "MD5";
-    pub const SHA1_DIGEST: &'static str = "SHA1";
-    pub const DES_ENCRYPTION: &'static str = "DES";
-    pub const AES128_ENCRYPTION: &'static str = "AES128";
+    /**
+     * Checks that the security settings are consistent.
+     *
+     * A password needs its protocol and the other way around, privacy can only be used with
+     * authentication, and passwords must be at least 8 octets long.
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_security(
+            &self.auth,
+            self.auth_protocol,
+            &self.privacy,
+            self.privacy_protocol,
+        )
+    }
+}
+
+/**
+ * Security settings of a USM user, as configured on a `NotificationReceiver` to accept the
+ * notifications the user sends.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UsmUser {
+    pub user: String,
+    pub auth: Option<String>,
+    pub auth_protocol: Option<AuthProtocol>,
+    pub privacy: Option<String>,
+    pub privacy_protocol: Option<PrivProtocol>,
+}
+
+impl UsmUser {
+    /**
+     * Checks that the security settings are consistent, like `Params::validate`.
+     */
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        validate_security(
+            &self.auth,
+            self.auth_protocol,
+            &self.privacy,
+            self.privacy_protocol,
+        )
+    }
+}
+
+impl From<&Params> for UsmUser {
+    fn from(params: &Params) -> Self {
+        Self {
+            user: params.user.clone(),
+            auth: params.auth.clone(),
+            auth_protocol: params.auth_protocol,
+            privacy: params.privacy.clone(),
+            privacy_protocol: params.privacy_protocol,
+        }
+    }
+}
+
+fn validate_security(
+    auth: &Option<String>,
+    auth_protocol: Option<AuthProtocol>,
+    privacy: &Option<String>,
+    privacy_protocol: Option<PrivProtocol>,
+) -> Result<(), ConfigError> {
+    match (auth, auth_protocol) {
+        (Some(_), None) => return Err(ConfigError::new("auth is set without auth_protocol")),
+        (None, Some(_)) => return Err(ConfigError::new("auth_protocol is set without auth")),
+        _ => (),
+    }
+
+    match (privacy, privacy_protocol) {
+        (Some(_), None) => return Err(ConfigError::new("privacy is set without privacy_protocol")),
+        (None, Some(_)) => return Err(ConfigError::new("privacy_protocol is set without privacy")),
+        _ => (),
+    }
+
+    if privacy.is_some() && auth.is_none() {
+        return Err(ConfigError::new("privacy requires authentication"));
+    }
+
+    // RFC 3414, section 11.2: passwords must be at least 8 octets long.
+    if auth.as_ref().is_some_and(|x| x.len() < MIN_PASSWD_LEN) {
+        return Err(ConfigError::new(format!(
+            "auth must be at least {} octets long",
+            MIN_PASSWD_LEN
+        )));
+    }
+    if privacy.as_ref().is_some_and(|x| x.len() < MIN_PASSWD_LEN) {
+        return Err(ConfigError::new(format!(
+            "privacy must be at least {} octets long",
+            MIN_PASSWD_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/**
+ * USM authentication protocol.
+ *
+ * Parsing is case-insensitive and ignores dashes and underscores, so both the names used by this
+ * crate ("SHA1", "SHA256") and the net-snmp ones ("SHA", "SHA-256") are accepted.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AuthProtocol {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl AuthProtocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthProtocol::Md5 => "MD5",
+            AuthProtocol::Sha1 => "SHA1",
+            AuthProtocol::Sha224 => "SHA224",
+            AuthProtocol::Sha256 => "SHA256",
+            AuthProtocol::Sha384 => "SHA384",
+            AuthProtocol::Sha512 => "SHA512",
+        }
+    }
+}
+
+impl FromStr for AuthProtocol {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match normalize_protocol_name(s).as_str() {
+            "MD5" => Ok(AuthProtocol::Md5),
+            "SHA" | "SHA1" => Ok(AuthProtocol::Sha1),
+            "SHA224" => Ok(AuthProtocol::Sha224),
+            "SHA256" => Ok(AuthProtocol::Sha256),
+            "SHA384" => Ok(AuthProtocol::Sha384),
+            "SHA512" => Ok(AuthProtocol::Sha512),
+            _ => Err(ConfigError::new(format!(
+                "unknown authentication protocol: {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AuthProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/**
+ * USM privacy protocol.
+ *
+ * The `C` variants use the Reeder key extension found on Cisco devices, `Aes192` and `Aes256`
+ * the Blumenthal one. Parsing accepts the net-snmp names, e.g. "AES" for AES-128 and "AES-256-C".
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PrivProtocol {
+    Des,
+    Aes128,
+    Aes192,
+    Aes256,
+    Aes192C,
+    Aes256C,
+}
+
+impl PrivProtocol {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrivProtocol::Des => "DES",
+            PrivProtocol::Aes128 => "AES128",
+            PrivProtocol::Aes192 => "AES192",
+            PrivProtocol::Aes256 => "AES256",
+            PrivProtocol::Aes192C => "AES192C",
+            PrivProtocol::Aes256C => "AES256C",
+        }
+    }
+}
+
+impl FromStr for PrivProtocol {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match normalize_protocol_name(s).as_str() {
+            "DES" => Ok(PrivProtocol::Des),
+            "AES" | "AES128" => Ok(PrivProtocol::Aes128),
+            "AES192" => Ok(PrivProtocol::Aes192),
+            "AES256" => Ok(PrivProtocol::Aes256),
+            "AES192C" => Ok(PrivProtocol::Aes192C),
+            "AES256C" => Ok(PrivProtocol::Aes256C),
+            _ => Err(ConfigError::new(format!(
+                "unknown privacy protocol: {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PrivProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+fn normalize_protocol_name(s: &str) -> String {
+    s.chars()
+        .filter(|c| *c != '-' && *c != '_')
+        .collect::<String>()
+        .to_uppercase()
+}
+
+macro_rules! serde_via_str {
+    ($protocol:ty) => {
+        impl Serialize for $protocol {
+            fn serialize<T: Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+                serializer.serialize_str(self.name())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $protocol {
+            fn deserialize<T: Deserializer<'de>>(deserializer: T) -> Result<Self, T::Error> {
+                let name = String::deserialize(deserializer)?;
+                name.parse().map_err(de::Error::custom)
+            }
+        }
+    };
 }
 
+serde_via_str!(AuthProtocol);
+serde_via_str!(PrivProtocol);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Command {
-    Get { oids: Vec<OID> },
-    GetNext { oids: Vec<OID> },
-    Walk { oid: OID },
+    Get {
+        oids: Vec<OID>,
+    },
+    GetNext {
+        oids: Vec<OID>,
+    },
+    /// The first `non_repeaters` OIDs are fetched once, the remaining ones up to
+    /// `max_repetitions` times each. `non_repeaters` can't exceed 18 unless it's larger than the
+    /// number of OIDs, as the encoder only accepts values that are also valid error statuses.
+    GetBulk {
+        oids: Vec<OID>,
+        non_repeaters: u32,
+        max_repetitions: u32,
+    },
+    Walk {
+        oid: OID,
+    },
+    /// Walks a subtree with `GetBulkRequest`s instead of one `GetNextRequest` per OID.
+    BulkWalk {
+        oid: OID,
+        max_repetitions: u32,
+    },
+    Set {
+        bindings: Vec<(OID, SnmpValue)>,
+    },
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+/**
+ * Value of a variable binding.
+ *
+ * OCTET STRINGs are kept as the raw bytes the agent sent, as many of them (MAC addresses,
+ * `DateAndTime`, engine IDs) aren't text. They serialize as byte strings in binary formats and as
+ * arrays of numbers in JSON; a text string is also accepted when deserializing, for data written
+ * before `OctetString` replaced the lossy `String` variant.
+*/
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
 pub enum SnmpValue {
     Int(i32),
-    String(String),
+    #[serde(alias = "String", with = "serde_bytes")]
+    OctetString(Vec<u8>),
     ObjectId(ObjectIdentifier),
     IpAddress([u8; 4]),
     Counter(u32),
     UnsignedInt(u32),
     TimeTicks(u32),
+    #[serde(with = "serde_bytes")]
     Opaque(Vec<u8>),
     BigCounter(u64),
     Unspecified,
@@ -55,9 +321,7 @@ impl From<snmp_mp::VarValue> for SnmpValue {
     fn from(item: snmp_mp::VarValue) -> SnmpValue {
         match item {
             snmp_mp::VarValue::Int(x) => SnmpValue::Int(x),
-            snmp_mp::VarValue::String(x) => {
-                SnmpValue::String(String::from_utf8_lossy(&x).into_owned())
-            }
+            snmp_mp::VarValue::String(x) => SnmpValue::OctetString(x),
             snmp_mp::VarValue::ObjectId(x) => SnmpValue::ObjectId(ObjectIdentifier {
                 components: x.components().to_vec(),
             }),
@@ -75,6 +339,86 @@ impl From<snmp_mp::VarValue> for SnmpValue {
     }
 }
 
+impl From<SnmpValue> for snmp_mp::VarValue {
+    fn from(item: SnmpValue) -> snmp_mp::VarValue {
+        match item {
+            SnmpValue::Int(x) => snmp_mp::VarValue::Int(x),
+            SnmpValue::OctetString(x) => snmp_mp::VarValue::String(x),
+            SnmpValue::ObjectId(x) => {
+                snmp_mp::VarValue::ObjectId(snmp_mp::ObjectIdent::new(x.components))
+            }
+            SnmpValue::IpAddress(x) => snmp_mp::VarValue::IpAddress(x),
+            SnmpValue::Counter(x) => snmp_mp::VarValue::Counter(x),
+            SnmpValue::UnsignedInt(x) => snmp_mp::VarValue::UnsignedInt(x),
+            SnmpValue::TimeTicks(x) => snmp_mp::VarValue::TimeTicks(x),
+            SnmpValue::Opaque(x) => snmp_mp::VarValue::Opaque(x),
+            SnmpValue::BigCounter(x) => snmp_mp::VarValue::BigCounter(x),
+            SnmpValue::Unspecified => snmp_mp::VarValue::Unspecified,
+            SnmpValue::NoSuchObject => snmp_mp::VarValue::NoSuchObject,
+            SnmpValue::NoSuchInstance => snmp_mp::VarValue::NoSuchInstance,
+            SnmpValue::EndOfMibView => snmp_mp::VarValue::EndOfMibView,
+        }
+    }
+}
+
+impl SnmpValue {
+    /**
+     * Returns the bytes of an OCTET STRING or Opaque value.
+     */
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            SnmpValue::OctetString(x) | SnmpValue::Opaque(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /**
+     * Returns an OCTET STRING as text, if it's valid UTF-8.
+     */
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            SnmpValue::OctetString(x) => std::str::from_utf8(x).ok(),
+            _ => None,
+        }
+    }
+
+    /**
+     * Formats an OCTET STRING or Opaque value as space separated hex octets, e.g. `00 1A 2B`.
+     */
+    pub fn to_hex(&self) -> Option<String> {
+        self.as_bytes().map(|x| hex(x, " "))
+    }
+
+    /**
+     * Formats a 6 octet OCTET STRING as a MAC address, e.g. `00:1a:2b:3c:4d:5e`.
+     */
+    pub fn to_mac(&self) -> Option<String> {
+        match self {
+            SnmpValue::OctetString(x) if x.len() == 6 => Some(
+                x.iter()
+                    .map(|octet| format!("{:02x}", octet))
+                    .collect::<Vec<String>>()
+                    .join(":"),
+            ),
+            _ => None,
+        }
+    }
+}
+
+fn hex(bytes: &[u8], separator: &str) -> String {
+    bytes
+        .iter()
+        .map(|octet| format!("{:02X}", octet))
+        .collect::<Vec<String>>()
+        .join(separator)
+}
+
+// Text is displayed as is unless it holds control characters other than line breaks and tabs.
+fn is_printable(text: &str) -> bool {
+    text.chars()
+        .all(|c| !c.is_control() || c == '\n' || c == '\r' || c == '\t')
+}
+
 const SECONDS_IN_MINUTE: u32 = 60;
 const SECONDS_IN_HOUR: u32 = 60 * SECONDS_IN_MINUTE;
 const SECONDS_IN_DAY: u32 = SECONDS_IN_HOUR * 24;
@@ -83,7 +427,10 @@ impl fmt::Display for SnmpValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             SnmpValue::Int(x) => write!(f, "{}", x),
-            SnmpValue::String(x) => write!(f, "{}", x),
+            SnmpValue::OctetString(x) => match std::str::from_utf8(x) {
+                Ok(text) if is_printable(text) => write!(f, "{}", text),
+                _ => write!(f, "{}", hex(x, " ")),
+            },
             SnmpValue::ObjectId(x) => {
                 let mut first = true;
                 let mut retval = Err(core::fmt::Error);
@@ -117,7 +464,7 @@ impl fmt::Display for SnmpValue {
                     x, days, hours, minutes, seconds, hundredth
                 )
             }
-            SnmpValue::Opaque(x) => write!(f, "{:X?}", x),
+            SnmpValue::Opaque(x) => write!(f, "{}", hex(x, " ")),
             SnmpValue::BigCounter(x) => write!(f, "{}", x),
             SnmpValue::Unspecified => write!(f, "Unspecified"),
             SnmpValue::NoSuchObject => write!(f, "No such object"),
@@ -131,15 +478,160 @@ impl fmt::Display for SnmpValue {
 pub struct SnmpResult {
     pub host: String,
     pub oid: String,
+    /// Dotted OID that was sent in the request.
+    #[serde(default)]
+    pub requested_oid: String,
+    /// Dotted OID returned by the agent. Differs from `requested_oid` for GetNext, GetBulk and
+    /// walks.
+    #[serde(default)]
+    pub returned_oid: String,
+    /// Whether the agent returned a value or an exception for this binding.
+    #[serde(default)]
+    pub outcome: Outcome,
     pub result: Option<SnmpValue>,
 }
 
+impl SnmpResult {
+    /**
+     * Returns the value, or `None` if the agent answered with an exception.
+     */
+    pub fn value(&self) -> Option<&SnmpValue> {
+        match self.outcome {
+            Outcome::Value => self.result.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Per-binding outcome of a request: a value, or one of the exceptions of RFC 3416.
+*/
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum Outcome {
+    #[default]
+    Value,
+    NoSuchObject,
+    NoSuchInstance,
+    EndOfMibView,
+}
+
+impl Outcome {
+    pub fn is_exception(&self) -> bool {
+        *self != Outcome::Value
+    }
+}
+
+impl From<&SnmpValue> for Outcome {
+    fn from(value: &SnmpValue) -> Self {
+        match value {
+            SnmpValue::NoSuchObject => Outcome::NoSuchObject,
+            SnmpValue::NoSuchInstance => Outcome::NoSuchInstance,
+            SnmpValue::EndOfMibView => Outcome::EndOfMibView,
+            _ => Outcome::Value,
+        }
+    }
+}
+
 impl fmt::Display for SnmpResult {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "OID: {}\n", self.oid)?;
+        writeln!(f, "OID: {}", self.oid)?;
         match &self.result {
-            Some(x) => write!(f, "Value: {}\n", x),
-            _ => write!(f, "Value <none>\n"),
+            Some(x) => writeln!(f, "Value: {}", x),
+            _ => writeln!(f, "Value <none>"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params_with(auth_protocol: &str, privacy_protocol: &str) -> serde_json::Result<Params> {
+        serde_json::from_str(&format!(
+            r#"{{"user": "u", "host": "h", "auth": "maplesyrup",
+                "auth_protocol": "{}", "privacy": "pancakes1", "privacy_protocol": "{}", "cmd": {{"Get": {{"oids": []}}}}}}"#,
+            auth_protocol, privacy_protocol
+        ))
+    }
+
+    #[test]
+    fn it_parses_protocol_names_case_insensitively() {
+        let params = params_with("sha-256", "AES").unwrap();
+        assert_eq!(params.auth_protocol, Some(AuthProtocol::Sha256));
+        assert_eq!(params.privacy_protocol, Some(PrivProtocol::Aes128));
+
+        let params = params_with("SHA", "aes-256-c").unwrap();
+        assert_eq!(params.auth_protocol, Some(AuthProtocol::Sha1));
+        assert_eq!(params.privacy_protocol, Some(PrivProtocol::Aes256C));
+
+        assert!(params_with("SHA-3", "AES").is_err());
+        assert!(params_with("MD5", "3DES").is_err());
+    }
+
+    #[test]
+    fn it_rejects_privacy_without_authentication() {
+        let mut params = params_with("MD5", "DES").unwrap();
+        assert!(params.validate().is_ok());
+
+        params.auth = None;
+        params.auth_protocol = None;
+        assert_eq!(
+            params.validate(),
+            Err(ConfigError::new("privacy requires authentication"))
+        );
+    }
+
+    #[test]
+    fn it_rejects_short_passwords() {
+        let mut params = params_with("SHA", "AES").unwrap();
+        params.privacy = Some("pancake".to_string());
+        assert_eq!(
+            params.validate(),
+            Err(ConfigError::new("privacy must be at least 8 octets long"))
+        );
+
+        let mut user = UsmUser::from(&params_with("SHA", "AES").unwrap());
+        user.auth = Some(String::new());
+        assert_eq!(
+            user.validate(),
+            Err(ConfigError::new("auth must be at least 8 octets long"))
+        );
+    }
+
+    #[test]
+    fn it_converts_values_for_set_requests() {
+        let value: snmp_mp::VarValue = SnmpValue::OctetString(b"noc@example.com".to_vec()).into();
+        assert_eq!(
+            value,
+            snmp_mp::VarValue::String(b"noc@example.com".to_vec())
+        );
+
+        let oid = SnmpValue::ObjectId(ObjectIdentifier {
+            components: vec![1, 3, 6, 1, 4, 1, 9],
+        });
+        let value: snmp_mp::VarValue = oid.into();
+        assert_eq!(
+            value,
+            snmp_mp::VarValue::ObjectId(snmp_mp::ObjectIdent::from_slice(&[1, 3, 6, 1, 4, 1, 9]))
+        );
+    }
+
+    #[test]
+    fn it_keeps_octet_strings_intact() {
+        let mac = SnmpValue::from(snmp_mp::VarValue::String(vec![
+            0x00, 0x1a, 0x2b, 0xff, 0x0, 0x5e,
+        ]));
+        assert_eq!(mac.to_mac().as_deref(), Some("00:1a:2b:ff:00:5e"));
+        assert_eq!(mac.to_hex().as_deref(), Some("00 1A 2B FF 00 5E"));
+        assert_eq!(mac.to_string(), "00 1A 2B FF 00 5E");
+
+        let json = serde_json::to_string(&mac).unwrap();
+        assert_eq!(serde_json::from_str::<SnmpValue>(&json).unwrap(), mac);
+        let cbor = serde_cbor::to_vec(&mac).unwrap();
+        assert_eq!(serde_cbor::from_slice::<SnmpValue>(&cbor).unwrap(), mac);
+
+        let text: SnmpValue = serde_json::from_str(r#"{"String": "router1"}"#).unwrap();
+        assert_eq!(text.as_text(), Some("router1"));
+        assert_eq!(text.to_string(), "router1");
+    }
+}
@@ -0,0 +1,308 @@
+use crate::auth::{self, LocalizedKey};
+use crate::error;
+use aes::cipher::{AsyncStreamCipher, KeyIvInit};
+use aes::{Aes192, Aes256};
+use digest::{FixedOutput, Reset, Update};
+use snmp_usm::{
+    Aes128PrivKey, DesPrivKey, PrivKey, SecurityError, SecurityParams, WithLocalizedKey,
+};
+use std::marker::PhantomData;
+
+/**
+ * Trait implemented by privacy keys that can be derived from a user password and an
+ * authoritative engine ID.
+ *
+ * It plays the role of `snmp_usm::WithLocalizedKey` for keys that need more key material than a
+ * single localized key provides.
+*/
+pub trait WithPasswd<'a, D>: Sized {
+    fn with_passwd(passwd: &[u8], engine_id: &[u8]) -> error::Result<Self>;
+}
+
+impl<'a, D> WithPasswd<'a, D> for DesPrivKey<'a, D>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+{
+    fn with_passwd(passwd: &[u8], engine_id: &[u8]) -> error::Result<Self> {
+        // snmp_usm panics on an empty password.
+        auth::check_passwd(passwd)?;
+        Ok(Self::with_localized_key(snmp_usm::LocalizedKey::new(
+            passwd, engine_id,
+        )))
+    }
+}
+
+impl<'a, D> WithPasswd<'a, D> for Aes128PrivKey<'a, D>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+{
+    fn with_passwd(passwd: &[u8], engine_id: &[u8]) -> error::Result<Self> {
+        // snmp_usm panics on an empty password.
+        auth::check_passwd(passwd)?;
+        Ok(Self::with_localized_key(snmp_usm::LocalizedKey::new(
+            passwd, engine_id,
+        )))
+    }
+}
+
+/**
+ * Algorithm used to lengthen a localized key when the digest doesn't produce enough key
+ * material for the cipher, e.g. a SHA-1 key (20 octets) used for AES-256 (32 octets).
+*/
+pub trait KeyExtension {
+    fn extend<D>(localized_key: &[u8], engine_id: &[u8], len: usize) -> Vec<u8>
+    where
+        D: Update + FixedOutput + Reset + Default + Clone;
+}
+
+/**
+ * Key extension from draft-blumenthal-aes-usm-04: each additional block is the hash of all the
+ * key material produced so far. Used by net-snmp for `AES192` and `AES256`.
+*/
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Blumenthal;
+
+impl KeyExtension for Blumenthal {
+    fn extend<D>(localized_key: &[u8], _engine_id: &[u8], len: usize) -> Vec<u8>
+    where
+        D: Update + FixedOutput + Reset + Default + Clone,
+    {
+        let mut key = localized_key.to_vec();
+        while key.len() < len {
+            let mut hashing_fn = D::default();
+            hashing_fn.update(&key);
+            key.extend_from_slice(&hashing_fn.finalize_fixed());
+        }
+
+        key.truncate(len);
+        key
+    }
+}
+
+/**
+ * Key extension from draft-reeder-snmpv3-usm-3desede-00: each additional block is the previous
+ * block used as a password and localized again. Used by Cisco, and by net-snmp for `AES192C` and
+ * `AES256C`.
+*/
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct Reeder;
+
+impl KeyExtension for Reeder {
+    fn extend<D>(localized_key: &[u8], engine_id: &[u8], len: usize) -> Vec<u8>
+    where
+        D: Update + FixedOutput + Reset + Default + Clone,
+    {
+        let mut key = localized_key.to_vec();
+        let mut block = localized_key.to_vec();
+        while key.len() < len {
+            // The block is a digest, so never empty.
+            let digest1 = auth::hash_passwd::<D>(&block);
+            block = auth::localize(&digest1, engine_id, D::default());
+            key.extend_from_slice(&block);
+        }
+
+        key.truncate(len);
+        key
+    }
+}
+
+/**
+ * AES block cipher usable in CFB mode for USM privacy.
+*/
+pub trait AesCipher {
+    const KEY_LEN: usize;
+
+    fn encrypt(key: &[u8], iv: &[u8], buf: &mut [u8]);
+    fn decrypt(key: &[u8], iv: &[u8], buf: &mut [u8]) -> Result<(), SecurityError>;
+}
+
+impl AesCipher for Aes192 {
+    const KEY_LEN: usize = 24;
+
+    fn encrypt(key: &[u8], iv: &[u8], buf: &mut [u8]) {
+        cfb_mode::Encryptor::<Aes192>::new_from_slices(key, iv)
+            .unwrap()
+            .encrypt(buf);
+    }
+
+    fn decrypt(key: &[u8], iv: &[u8], buf: &mut [u8]) -> Result<(), SecurityError> {
+        cfb_mode::Decryptor::<Aes192>::new_from_slices(key, iv)
+            .map_err(|_| SecurityError::DecryptError)?
+            .decrypt(buf);
+        Ok(())
+    }
+}
+
+impl AesCipher for Aes256 {
+    const KEY_LEN: usize = 32;
+
+    fn encrypt(key: &[u8], iv: &[u8], buf: &mut [u8]) {
+        cfb_mode::Encryptor::<Aes256>::new_from_slices(key, iv)
+            .unwrap()
+            .encrypt(buf);
+    }
+
+    fn decrypt(key: &[u8], iv: &[u8], buf: &mut [u8]) -> Result<(), SecurityError> {
+        cfb_mode::Decryptor::<Aes256>::new_from_slices(key, iv)
+            .map_err(|_| SecurityError::DecryptError)?
+            .decrypt(buf);
+        Ok(())
+    }
+}
+
+/**
+ * Privacy key used for AES-192 and AES-256 encryption in CFB mode.
+ *
+ * The IV is built like for AES-128 (RFC 3826): engine boots, engine time and a 64-bit salt.
+ * When the localized key is too short for the cipher, it's lengthened with `E`.
+*/
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct AesPrivKey<'a, D, C, E> {
+    key: Vec<u8>,
+    _types: PhantomData<(&'a D, C, E)>,
+}
+
+/// AES-192 with the Blumenthal key extension.
+pub type Aes192PrivKey<'a, D> = AesPrivKey<'a, D, Aes192, Blumenthal>;
+/// AES-192 with the Reeder key extension.
+pub type Aes192CPrivKey<'a, D> = AesPrivKey<'a, D, Aes192, Reeder>;
+/// AES-256 with the Blumenthal key extension.
+pub type Aes256PrivKey<'a, D> = AesPrivKey<'a, D, Aes256, Blumenthal>;
+/// AES-256 with the Reeder key extension.
+pub type Aes256CPrivKey<'a, D> = AesPrivKey<'a, D, Aes256, Reeder>;
+
+impl<'a, D, C, E> AesPrivKey<'a, D, C, E> {
+    fn iv(engine_boots: u32, engine_time: u32, salt: &[u8]) -> Vec<u8> {
+        let mut iv = Vec::with_capacity(16);
+        iv.extend_from_slice(&engine_boots.to_be_bytes());
+        iv.extend_from_slice(&engine_time.to_be_bytes());
+        iv.extend_from_slice(salt);
+
+        iv
+    }
+
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+}
+
+impl<'a, D, C, E> AesPrivKey<'a, D, C, E>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+    C: AesCipher,
+    E: KeyExtension,
+{
+    /**
+     * Constructs a key from a localized key, extending it if needed.
+     */
+    pub fn with_localized_key(localized_key: LocalizedKey<'a, D>, engine_id: &[u8]) -> Self {
+        Self {
+            key: E::extend::<D>(localized_key.bytes(), engine_id, C::KEY_LEN),
+            _types: PhantomData,
+        }
+    }
+}
+
+impl<'a, D, C, E> WithPasswd<'a, D> for AesPrivKey<'a, D, C, E>
+where
+    D: Update + FixedOutput + Reset + Default + Clone,
+    C: AesCipher,
+    E: KeyExtension,
+{
+    fn with_passwd(passwd: &[u8], engine_id: &[u8]) -> error::Result<Self> {
+        Ok(Self::with_localized_key(
+            LocalizedKey::new(passwd, engine_id)?,
+            engine_id,
+        ))
+    }
+}
+
+impl<'a, D, C, E> PrivKey for AesPrivKey<'a, D, C, E>
+where
+    C: AesCipher,
+{
+    type Salt = u64;
+
+    fn encrypt(
+        &self,
+        mut scoped_pdu: Vec<u8>,
+        security_params: &SecurityParams,
+        salt: Self::Salt,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let salt = salt.to_be_bytes();
+        let iv = Self::iv(
+            security_params.engine_boots(),
+            security_params.engine_time(),
+            &salt,
+        );
+        C::encrypt(&self.key, &iv, &mut scoped_pdu);
+
+        (scoped_pdu, salt.to_vec())
+    }
+
+    fn decrypt(
+        &self,
+        mut encrypted_scoped_pdu: Vec<u8>,
+        security_params: &SecurityParams,
+    ) -> Result<Vec<u8>, SecurityError> {
+        let iv = Self::iv(
+            security_params.engine_boots(),
+            security_params.engine_time(),
+            security_params.priv_params(),
+        );
+        C::decrypt(&self.key, &iv, &mut encrypted_scoped_pdu)?;
+
+        Ok(encrypted_scoped_pdu)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use snmp_usm::{Md5, Sha1};
+
+    const ENGINE_ID: [u8; 12] = [0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x02];
+
+    #[test]
+    fn it_extends_keys_with_blumenthal() {
+        let localized_key = LocalizedKey::<Sha1>::new(b"maplesyrup", &ENGINE_ID).unwrap();
+        let key = Aes256PrivKey::<Sha1>::with_localized_key(localized_key.clone(), &ENGINE_ID);
+
+        let mut hashing_fn = Sha1::default();
+        hashing_fn.update(localized_key.bytes());
+        let second_block = hashing_fn.finalize_fixed();
+
+        assert_eq!(key.key().len(), 32);
+        assert_eq!(&key.key()[..20], localized_key.bytes());
+        assert_eq!(&key.key()[20..], &second_block[..12]);
+    }
+
+    #[test]
+    fn it_extends_keys_with_reeder() {
+        let localized_key = LocalizedKey::<Md5>::new(b"maplesyrup", &ENGINE_ID).unwrap();
+        let key = Aes192CPrivKey::<Md5>::with_localized_key(localized_key.clone(), &ENGINE_ID);
+
+        let second_block = LocalizedKey::<Md5>::new(localized_key.bytes(), &ENGINE_ID).unwrap();
+
+        assert_eq!(key.key().len(), 24);
+        assert_eq!(&key.key()[..16], localized_key.bytes());
+        assert_eq!(&key.key()[16..], &second_block.bytes()[..8]);
+    }
+
+    #[test]
+    fn it_decrypts_what_it_encrypts() {
+        let key = Aes256CPrivKey::<Sha1>::with_passwd(b"maplesyrup", &ENGINE_ID).unwrap();
+        let mut security_params = SecurityParams::new();
+        security_params.set_engine_boots(3).set_engine_time(1200);
+
+        let scoped_pdu = b"scoped PDU of an odd length".to_vec();
+        let (encrypted, salt) = key.encrypt(scoped_pdu.clone(), &security_params, 42);
+        assert_ne!(encrypted, scoped_pdu);
+
+        security_params.set_priv_params(&salt);
+        assert_eq!(
+            key.decrypt(encrypted, &security_params).unwrap(),
+            scoped_pdu
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use crate::error::{ReportKind, Result};
+use crate::msg_factory::{SNMP_TRAP_OID_OID, SYS_UP_TIME_OID};
+use crate::oids::{FindOidName, OidMap, OID};
+use crate::params::{SnmpResult, UsmUser};
+use crate::request;
+use crate::usm::{Incoming, LocalEngine};
+use serde::{Deserialize, Serialize};
+use snmp_mp::{ObjectIdent, PduType, SnmpMsg, VarBind, VarValue};
+use std::io::ErrorKind;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Standard port notification receivers listen on.
+pub const SNMP_TRAP_PORT_NUM: u16 = 162;
+
+// How often the thread started by `spawn` checks whether the channel is still open.
+const SPAWN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/**
+ * PDU type of a received notification.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NotificationKind {
+    /// SNMPv2-Trap PDU, which isn't acknowledged.
+    Trap,
+    /// InformRequest PDU, acknowledged with a Response PDU before it's delivered.
+    Inform,
+}
+
+/**
+ * Notification received by a `NotificationReceiver`.
+ *
+ * `uptime` and `trap_oid` come from the `sysUpTime.0` and `snmpTrapOID.0` variable bindings that
+ * start every notification, and `var_binds` holds the ones that follow, named with the receiver's
+ * `OidMap`.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notification {
+    pub source: SocketAddr,
+    pub kind: NotificationKind,
+    /// Authoritative engine ID: the sender's for traps, the receiver's for informs.
+    #[serde(with = "serde_bytes")]
+    pub engine_id: Vec<u8>,
+    pub user: String,
+    /// Value of `sysUpTime.0`, in hundredths of a second.
+    pub uptime: Option<u32>,
+    /// Value of `snmpTrapOID.0`.
+    pub trap_oid: Option<OID>,
+    pub var_binds: Vec<SnmpResult>,
+}
+
+/**
+ * Receiver for SNMPv3 traps and informs.
+ *
+ * Notifications are authenticated and decrypted with the keys of the USM users added with
+ * `add_user`. Traps are sent by the agent's own engine, so their users are localized to the
+ * agent's engine ID. Informs are sent to the receiver's engine, which answers the sender's
+ * discovery requests, so their users are localized to `engine_id()`.
+ *
+ * Messages that can't be authenticated, decrypted or decoded are dropped and counted, and
+ * reported to the sender when it asked for reports. Informs are acknowledged with a Response PDU
+ * before being delivered.
+*/
+pub struct NotificationReceiver {
+    socket: UdpSocket,
+    oid_map: OidMap,
+    engine: LocalEngine,
+    buf: [u8; SnmpMsg::MAX_UDP_PACKET_SIZE],
+}
+
+impl NotificationReceiver {
+    /**
+     * Binds a receiver to `local_addr`, e.g. `0.0.0.0:162`, with a random engine ID.
+     *
+     * The engine ID uses the administratively assigned octets format of RFC 3411. Informs
+     * senders that cache engine IDs should be given a stable one with `with_engine_id`.
+     */
+    pub fn bind<A: ToSocketAddrs>(local_addr: A, oid_map: OidMap) -> Result<Self> {
+        Self::with_engine_id(local_addr, oid_map, &LocalEngine::random_engine_id(), 1)
+    }
+
+    /**
+     * Binds a receiver to `local_addr` with the given engine ID and boots.
+     *
+     * `engine_boots` should be incremented, and persisted, every time the receiver restarts, as
+     * inform senders reject responses from an engine that went back in time.
+     */
+    pub fn with_engine_id<A: ToSocketAddrs>(
+        local_addr: A,
+        oid_map: OidMap,
+        engine_id: &[u8],
+        engine_boots: u32,
+    ) -> Result<Self> {
+        let engine = LocalEngine::new(engine_id, engine_boots)?;
+        let socket = UdpSocket::bind(local_addr)?;
+        let buf = [0; SnmpMsg::MAX_UDP_PACKET_SIZE];
+
+        Ok(Self {
+            socket,
+            oid_map,
+            engine,
+            buf,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn engine_id(&self) -> &[u8] {
+        self.engine.engine_id()
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.engine.engine_boots()
+    }
+
+    pub fn engine_time(&self) -> u32 {
+        self.engine.engine_time()
+    }
+
+    /**
+     * Accepts the notifications `user` sends from the authoritative engine `engine_id`.
+     *
+     * Notifications must be sent at the security level of the user: a user with privacy only
+     * accepts encrypted notifications, and a user with authentication only authenticated ones.
+     */
+    pub fn add_user(&mut self, engine_id: &[u8], user: &UsmUser) -> Result<&mut Self> {
+        self.engine.add_user(engine_id, user)?;
+        Ok(self)
+    }
+
+    /**
+     * Returns how many messages were dropped for the reason named by the USM statistics
+     * counter `kind`. `ReportKind::Other` counts messages that couldn't be decoded.
+     */
+    pub fn dropped(&self, kind: ReportKind) -> u32 {
+        self.engine.dropped(kind)
+    }
+
+    /**
+     * Waits for the next notification.
+     */
+    pub fn recv(&mut self) -> Result<Notification> {
+        loop {
+            if let Some(notification) = self.recv_until(None)? {
+                return Ok(notification);
+            }
+        }
+    }
+
+    /**
+     * Waits up to `timeout` for the next notification. Returns `None` on timeout.
+     */
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<Notification>> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    /**
+     * Passes every notification to `handler`. Only returns on socket errors.
+     */
+    pub fn run<F>(&mut self, mut handler: F) -> Result<()>
+    where
+        F: FnMut(Notification),
+    {
+        loop {
+            handler(self.recv()?);
+        }
+    }
+
+    /**
+     * Moves the receiver to a new thread and returns a channel delivering its notifications.
+     *
+     * The thread stops when the channel is dropped, or after sending the socket error that
+     * stopped it.
+     */
+    pub fn spawn(mut self) -> mpsc::Receiver<Result<Notification>> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            let sent = match self.recv_timeout(SPAWN_POLL_INTERVAL) {
+                Ok(Some(notification)) => sender.send(Ok(notification)),
+                Ok(None) => continue,
+                Err(error) => {
+                    let _ = sender.send(Err(error));
+                    return;
+                }
+            };
+
+            if sent.is_err() {
+                return;
+            }
+        });
+
+        receiver
+    }
+
+    // Handles messages until a notification arrives or `deadline` passes.
+    fn recv_until(&mut self, deadline: Option<Instant>) -> Result<Option<Notification>> {
+        loop {
+            let timeout = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(Instant::now());
+                    if remaining.is_zero() {
+                        return Ok(None);
+                    }
+
+                    Some(remaining)
+                }
+                None => None,
+            };
+
+            self.socket.set_read_timeout(timeout)?;
+            let (len, source) = match self.socket.recv_from(&mut self.buf) {
+                Ok(received) => received,
+                Err(error)
+                    if error.kind() == ErrorKind::WouldBlock
+                        || error.kind() == ErrorKind::TimedOut =>
+                {
+                    return Ok(None);
+                }
+                Err(error) => return Err(error.into()),
+            };
+
+            let mut encoded_msg = self.buf[..len].to_vec();
+            if let Some(notification) = self.handle_msg(&mut encoded_msg, source)? {
+                return Ok(Some(notification));
+            }
+        }
+    }
+
+    // Only errors sending responses and reports are returned, anything wrong with the message
+    // itself gets it dropped.
+    fn handle_msg(
+        &mut self,
+        encoded_msg: &mut [u8],
+        source: SocketAddr,
+    ) -> Result<Option<Notification>> {
+        let request = match self.engine.process(encoded_msg)? {
+            Incoming::Accepted(request) => request,
+            Incoming::Dropped { report } => {
+                if let Some(report) = report {
+                    self.socket.send_to(&report, source)?;
+                }
+                return Ok(None);
+            }
+        };
+
+        let msg = &request.msg;
+        let scoped_pdu = match msg.scoped_pdu_data.plaintext() {
+            Some(scoped_pdu) => scoped_pdu,
+            None => return Ok(None),
+        };
+
+        let kind = match scoped_pdu.pdu_type() {
+            PduType::SnmpTrap => NotificationKind::Trap,
+            PduType::InformRequest => NotificationKind::Inform,
+            // Requests meant for an agent, or responses meant for a manager.
+            _ => return Ok(None),
+        };
+
+        if kind == NotificationKind::Inform {
+            let mut response = SnmpMsg::new(msg.id());
+            if let Some(response_pdu) = response.scoped_pdu_data.plaintext_mut() {
+                response_pdu
+                    .set_pdu_type(PduType::Response)
+                    .set_request_id(scoped_pdu.request_id())
+                    .set_engine_id(scoped_pdu.engine_id())
+                    .set_context_name(scoped_pdu.context_name())
+                    .set_var_binds(scoped_pdu.var_binds().iter().cloned());
+            }
+
+            let encoded_response = self.engine.encode_response(&request, &mut response)?;
+            self.socket.send_to(&encoded_response, source)?;
+        }
+
+        let notification = Notification {
+            source,
+            kind,
+            engine_id: request.security_params.engine_id().to_vec(),
+            user: String::from_utf8_lossy(request.security_params.username()).into_owned(),
+            uptime: None,
+            trap_oid: None,
+            var_binds: Vec::new(),
+        };
+
+        Ok(Some(self.resolve(notification, scoped_pdu.var_binds())?))
+    }
+
+    // Fills in the notification from its variable bindings.
+    fn resolve(
+        &self,
+        mut notification: Notification,
+        var_binds: &[VarBind],
+    ) -> Result<Notification> {
+        for var_bind in var_binds {
+            match (var_bind.name().components(), var_bind.value()) {
+                (name, VarValue::TimeTicks(uptime)) if name == SYS_UP_TIME_OID => {
+                    notification.uptime = Some(*uptime);
+                }
+                (name, VarValue::ObjectId(trap_oid)) if name == SNMP_TRAP_OID_OID => {
+                    notification.trap_oid = Some(OID {
+                        oid: trap_oid.to_string(),
+                        name: self.name(trap_oid),
+                        ..Default::default()
+                    });
+                }
+                _ => notification
+                    .var_binds
+                    .push(request::var_bind_to_snmp_result(
+                        notification.source.to_string(),
+                        self.name(var_bind.name()),
+                        var_bind.name(),
+                        var_bind.clone(),
+                    )?),
+            }
+        }
+
+        Ok(notification)
+    }
+
+    fn name(&self, oid: &ObjectIdent) -> String {
+        self.oid_map
+            .find_oid_name(&oid.to_string())
+            .unwrap_or_else(|| oid.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::Sha256;
+    use crate::client::{self, Client};
+    use crate::msg_factory;
+    use crate::params::{AuthProtocol, PrivProtocol};
+    use crate::session::Session;
+    use snmp_usm::Aes128PrivKey;
+
+    type TestSession = Session<'static, Sha256, Aes128PrivKey<'static, Sha256>, u64>;
+
+    fn user() -> UsmUser {
+        UsmUser {
+            user: "trapper".to_string(),
+            auth: Some("maplesyrup".to_string()),
+            auth_protocol: Some(AuthProtocol::Sha256),
+            privacy: Some("pancakes1".to_string()),
+            privacy_protocol: Some(PrivProtocol::Aes128),
+        }
+    }
+
+    fn notification_var_binds() -> Vec<VarBind> {
+        vec![
+            VarBind::with_value(
+                ObjectIdent::from_slice(&SYS_UP_TIME_OID),
+                VarValue::TimeTicks(4200),
+            ),
+            VarBind::with_value(
+                ObjectIdent::from_slice(&SNMP_TRAP_OID_OID),
+                // linkDown
+                VarValue::ObjectId(ObjectIdent::from_slice(&[1, 3, 6, 1, 6, 3, 1, 1, 5, 3])),
+            ),
+            VarBind::with_value(
+                ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 1, 3]),
+                VarValue::Int(3),
+            ),
+        ]
+    }
+
+    fn oid_map() -> OidMap {
+        OidMap {
+            oids: vec![
+                OID {
+                    oid: "1.3.6.1.6.3.1.1.5.3".to_string(),
+                    name: "linkDown".to_string(),
+                    ..Default::default()
+                },
+                OID {
+                    oid: "1.3.6.1.2.1.2.2.1.1".to_string(),
+                    name: "ifIndex".to_string(),
+                    ..Default::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn it_acknowledges_informs_after_discovery() {
+        let mut receiver = NotificationReceiver::bind("127.0.0.1:0", oid_map()).unwrap();
+        let engine_id = receiver.engine_id().to_vec();
+        receiver.add_user(&engine_id, &user()).unwrap();
+        let receiver_addr = receiver.local_addr().unwrap();
+        let notifications = receiver.spawn();
+
+        let mut client = Client::new(receiver_addr).unwrap();
+        let mut session = TestSession::new(&mut client, b"trapper").unwrap();
+        assert_eq!(session.engine_id(), engine_id.as_slice());
+
+        session
+            .set_passwords(Some("maplesyrup"), Some("pancakes1"), 0)
+            .unwrap();
+        let mut inform = msg_factory::create_request_msg(
+            PduType::InformRequest,
+            notification_var_binds(),
+            &mut session,
+        );
+        let response = client.send_request(&mut inform, &mut session).unwrap();
+        let response_pdu = response.scoped_pdu_data.plaintext().unwrap();
+        assert_eq!(response_pdu.pdu_type(), PduType::Response);
+
+        let notification = notifications.recv().unwrap().unwrap();
+        assert_eq!(notification.kind, NotificationKind::Inform);
+        assert_eq!(notification.user, "trapper");
+        assert_eq!(notification.uptime, Some(4200));
+        assert_eq!(notification.trap_oid.unwrap().name, "linkDown");
+        assert_eq!(notification.var_binds.len(), 1);
+        assert_eq!(notification.var_binds[0].oid, "ifIndex.3");
+    }
+
+    #[test]
+    fn it_drops_traps_from_unknown_users() {
+        let agent_engine_id = b"agent-engine";
+        let mut receiver = NotificationReceiver::bind("127.0.0.1:0", oid_map()).unwrap();
+        receiver.add_user(agent_engine_id, &user()).unwrap();
+        let agent = Client::new(receiver.local_addr().unwrap()).unwrap();
+
+        let mut session = TestSession::undiscovered();
+        session
+            .set_username(b"intruder")
+            .set_engine_id(agent_engine_id);
+        let mut trap = SnmpMsg::new(session.msg_id());
+        if let Some(scoped_pdu) = trap.scoped_pdu_data.plaintext_mut() {
+            scoped_pdu
+                .set_pdu_type(PduType::SnmpTrap)
+                .set_var_binds(notification_var_binds());
+        }
+        let encoded_trap = client::encode_msg(&mut trap, &mut session).unwrap();
+        agent.socket.send(&encoded_trap).unwrap();
+
+        let notification = receiver.recv_timeout(Duration::from_millis(200)).unwrap();
+        assert!(notification.is_none());
+        assert_eq!(receiver.dropped(ReportKind::UnknownUserName), 1);
+    }
+}
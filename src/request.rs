@@ -1,15 +1,23 @@
 use std::collections::HashMap;
 use std::str::FromStr;
 
+#[cfg(feature = "async")]
+use crate::async_client::AsyncClient;
+use crate::auth::Digest;
+use crate::error::PduError;
 use crate::oids;
 use crate::oids::{FindOidName, OID};
+#[cfg(feature = "async")]
+use crate::walker::AsyncWalker;
+use crate::walker::Walker;
 use crate::{msg_factory, params, Client, Session, Step};
 
-use anyhow::{format_err, Result};
-use snmp_mp::{ObjectIdent, PduType, SnmpMsg, VarBind, VarValue};
-use snmp_usm::{Digest, PrivKey};
+use crate::error::{Error, Result};
+use snmp_mp::{ObjectIdent, PduErrorStatus, PduType, SnmpMsg, VarBind, VarValue};
+use snmp_usm::PrivKey;
 
-const MIB2_BASE_OID: [u64; 6] = [1, 3, 6, 1, 2, 1];
+// Room left in a response for the message header and security parameters.
+const MSG_OVERHEAD: usize = 512;
 
 /**
  * This function retrieves an SNMP response using `get` and prints the result.
@@ -25,28 +33,120 @@ where
     P: PrivKey<Salt = S>,
     S: Step + Copy,
 {
-    let oid_map: HashMap<String, &OID> = oids.iter().map(|x| (x.oid.clone(), x)).collect();
+    let mut get_request = create_get_msg(pdu_type, &oids, session)?;
+    let response = client.send_request(&mut get_request, session)?;
+    get_results(&oids, client.socket.peer_addr()?.to_string(), &response)
+}
+
+/**
+ * Asynchronous version of `snmp_get`.
+*/
+#[cfg(feature = "async")]
+pub async fn snmp_get_async<D, P, S>(
+    pdu_type: PduType,
+    oids: Vec<OID>,
+    client: &mut AsyncClient,
+    session: &mut Session<'_, D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let mut get_request = create_get_msg(pdu_type, &oids, session)?;
+    let response = client.send_request(&mut get_request, session).await?;
+    get_results(&oids, client.socket.peer_addr()?.to_string(), &response)
+}
+
+fn create_get_msg<D, P, S>(
+    pdu_type: PduType,
+    oids: &[OID],
+    session: &mut Session<D, P, S>,
+) -> Result<SnmpMsg> {
     let oid_list: Vec<String> = oids.iter().map(|x| x.oid.to_string()).collect();
-    let var_binds = strings_to_var_binds(oid_list.iter());
-    if var_binds.is_empty() {
-        return Err(format_err!("invalid OID(s) supplied"));
-    }
+    let var_binds = strings_to_var_binds(oid_list.iter())?;
+
+    Ok(msg_factory::create_request_msg(
+        pdu_type, var_binds, session,
+    ))
+}
 
-    let mut get_request = msg_factory::create_request_msg(pdu_type, var_binds, session);
+fn get_results(oids: &[OID], host: String, response: &SnmpMsg) -> Result<Vec<params::SnmpResult>> {
+    check_error_status(response, &var_bind_names(oids))?;
+    let oid_map: HashMap<String, &OID> = oids.iter().map(|x| (x.oid.clone(), x)).collect();
 
     let mut retval: Vec<params::SnmpResult> = Vec::new();
-    let response = client.send_request(&mut get_request, session)?;
-    if let Some(var_binds) = get_var_binds(&response) {
+    if let Some(var_binds) = get_var_binds(response) {
         for var_bind in var_binds {
             let vb_string = var_bind.name().to_string();
             let this_oid = vb_string.as_str();
             let oid_obj: OID = match oid_map.get(this_oid) {
-                Some(&&ref x) => x.clone(),
-                _ => return Err(format_err!("OID not in oid_map: {:#?}", oid_map)),
+                Some(&x) => x.clone(),
+                _ => return Err(Error::protocol(format!("unrequested OID {}", this_oid))),
             };
             let this_result: params::SnmpResult = var_bind_to_snmp_result(
-                client.socket.peer_addr()?.to_string(),
+                host.clone(),
                 oid_obj.name,
+                var_bind.name(),
+                var_bind.clone(),
+            )?;
+            retval.push(this_result);
+        }
+    }
+
+    Ok(retval)
+}
+
+/**
+ * Sends a `GetNextRequest` and returns the lexicographic successor of each requested OID.
+ *
+ * The returned OIDs are resolved through `oid_map`; each result also carries the OID that was
+ * requested so callers can match the two up.
+*/
+pub fn snmp_get_next<D, P, S>(
+    oid_map: &oids::OidMap,
+    oids: Vec<OID>,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let oid_list: Vec<String> = oids.iter().map(|x| x.oid.to_string()).collect();
+    let var_binds = strings_to_var_binds(oid_list.iter())?;
+
+    let mut get_next_request =
+        msg_factory::create_request_msg(PduType::GetNextRequest, var_binds.clone(), session);
+
+    let mut retval: Vec<params::SnmpResult> = Vec::new();
+    let response = client.send_request(&mut get_next_request, session)?;
+    check_error_status(&response, &var_bind_names(&oids))?;
+    if let Some(response_var_binds) = get_var_binds(&response) {
+        if response_var_binds.len() != var_binds.len() {
+            return Err(Error::protocol(format!(
+                "expected {} variable bindings, got {}",
+                var_binds.len(),
+                response_var_binds.len()
+            )));
+        }
+
+        for (requested, var_bind) in var_binds.iter().zip(response_var_binds) {
+            if var_bind.value() != &VarValue::EndOfMibView && var_bind.name() <= requested.name() {
+                return Err(Error::protocol(format!(
+                    "OID not increasing: {} follows {}",
+                    var_bind.name(),
+                    requested.name()
+                )));
+            }
+
+            let this_result: params::SnmpResult = var_bind_to_snmp_result(
+                client.socket.peer_addr()?.to_string(),
+                oid_map
+                    .find_oid_name(&var_bind.name().to_string())
+                    .unwrap_or_else(|| var_bind.name().to_string()),
+                requested.name(),
                 var_bind.clone(),
             )?;
             retval.push(this_result);
@@ -57,7 +157,7 @@ where
 }
 
 pub fn snmp_walk<D, P, S>(
-    oid_map: oids::OidMap,
+    oid_map: &oids::OidMap,
     oid: OID,
     client: &mut Client,
     session: &mut Session<D, P, S>,
@@ -67,81 +167,392 @@ where
     P: PrivKey<Salt = S>,
     S: Step + Copy,
 {
-    //println!("== entered snmp_walk ==");
+    Walker::new(oid_map, oid, client, session)?.collect()
+}
+
+/**
+ * Asynchronous version of `snmp_walk`.
+*/
+#[cfg(feature = "async")]
+pub async fn snmp_walk_async<D, P, S>(
+    oid_map: &oids::OidMap,
+    oid: OID,
+    client: &mut AsyncClient,
+    session: &mut Session<'_, D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let mut walker = AsyncWalker::new(oid_map, oid, client, session)?;
+
+    let mut retval: Vec<params::SnmpResult> = Vec::new();
+    while let Some(result) = walker.next().await {
+        retval.push(result?);
+    }
+
+    Ok(retval)
+}
+
+pub(crate) fn walk_start(oid: &OID) -> Result<Vec<VarBind>> {
     let oid_list: Option<String> = Some(oid.oid.to_string());
-    let mut var_bind = strings_to_var_binds(oid_list.iter());
+    strings_to_var_binds(oid_list.iter())
+}
+
+/**
+ * Sends a single `GetBulkRequest` and returns the results, resolving the returned OIDs through
+ * `oid_map`.
+*/
+pub fn snmp_get_bulk<D, P, S>(
+    oid_map: &oids::OidMap,
+    oids: Vec<OID>,
+    non_repeaters: u32,
+    max_repetitions: u32,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let oid_list: Vec<String> = oids.iter().map(|x| x.oid.to_string()).collect();
+    let var_binds = strings_to_var_binds(oid_list.iter())?;
+
+    let mut max_repetitions = max_repetitions;
+    let response = send_bulk_request(
+        &var_binds,
+        non_repeaters,
+        &mut max_repetitions,
+        client,
+        session,
+    )?;
+    check_error_status(&response, &var_bind_names(&oids))?;
+
+    let non_repeaters = (non_repeaters as usize).min(var_binds.len());
+    let repeaters = var_binds.len() - non_repeaters;
+
+    let mut retval: Vec<params::SnmpResult> = Vec::new();
+    if let Some(response_var_binds) = get_var_binds(&response) {
+        for (i, var_bind) in response_var_binds.iter().enumerate() {
+            if var_bind.value() == &VarValue::EndOfMibView {
+                continue;
+            }
+
+            // Each repetition of a repeater asks for the successor of the previous repetition.
+            let requested = if i < non_repeaters + repeaters {
+                var_binds[i].name()
+            } else {
+                response_var_binds[i - repeaters].name()
+            };
+
+            let this_result: params::SnmpResult = var_bind_to_snmp_result(
+                client.socket.peer_addr()?.to_string(),
+                oid_map
+                    .find_oid_name(&var_bind.name().to_string())
+                    .unwrap_or_else(|| var_bind.name().to_string()),
+                requested,
+                var_bind.clone(),
+            )?;
+            retval.push(this_result);
+        }
+    }
+
+    Ok(retval)
+}
+
+/**
+ * Walks the subtree rooted at `oid` using `GetBulkRequest`s.
+ *
+ * `max_repetitions` is reduced automatically when the agent answers with `tooBig`, or when the
+ * previous response suggests the next one would not fit in a UDP datagram.
+*/
+pub fn snmp_bulk_walk<D, P, S>(
+    oid_map: &oids::OidMap,
+    oid: OID,
+    max_repetitions: u32,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    Walker::new(oid_map, oid, client, session)?
+        .bulk(max_repetitions)
+        .collect()
+}
 
-    if var_bind.is_empty() {
-        eprintln!("invalid OID supplied, using default OID\n");
+/**
+ * Sends a `SetRequest` assigning each value to its OID and returns the bindings echoed by the
+ * agent.
+ *
+ * A non-zero error-status in the response is returned as a `PduError` naming the failing binding.
+*/
+pub fn snmp_set<D, P, S>(
+    bindings: Vec<(OID, params::SnmpValue)>,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<Vec<params::SnmpResult>>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    if bindings.is_empty() {
+        return Err(Error::config("no bindings supplied"));
     }
 
-    if var_bind.is_empty() {
-        let base_oid = ObjectIdent::from_slice(&MIB2_BASE_OID);
-        var_bind = vec![VarBind::new(base_oid)];
+    let mut names: Vec<String> = Vec::new();
+    let mut var_binds: Vec<VarBind> = Vec::new();
+    for (oid, value) in bindings {
+        let name =
+            ObjectIdent::from_str(&oid.oid).map_err(|_| Error::InvalidOid(oid.oid.clone()))?;
+        var_binds.push(VarBind::with_value(name, value.into()));
+        names.push(oid.name);
     }
 
+    let mut set_request = msg_factory::create_request_msg(PduType::SetRequest, var_binds, session);
+    let response = client.send_request(&mut set_request, session)?;
+
+    check_error_status(&response, &names)?;
+
     let mut retval: Vec<params::SnmpResult> = Vec::new();
-    let end_oid = &next_sibling(var_bind[0].name());
-    loop {
-        let mut get_next_request =
-            msg_factory::create_request_msg(PduType::GetNextRequest, var_bind, session);
+    if let Some(var_binds) = get_var_binds(&response) {
+        for (var_bind, name) in var_binds.iter().zip(names) {
+            let this_result: params::SnmpResult = var_bind_to_snmp_result(
+                client.socket.peer_addr()?.to_string(),
+                name,
+                var_bind.name(),
+                var_bind.clone(),
+            )?;
+            retval.push(this_result);
+        }
+    }
 
-        let get_next_response = client.send_request(&mut get_next_request, session)?;
+    Ok(retval)
+}
 
-        match get_first_var_bind(&get_next_response) {
-            Some(var) => {
-                //println!("Found Some(var)");
-                if var.name() >= end_oid || var.value() == &VarValue::EndOfMibView {
-                    //println!("== exited snmp_walk (EndOfMibView) ==");
-                    return Ok(retval);
-                }
+/**
+ * Sends an `SNMPv2-Trap` with the trap OID `trap_oid` and the variable bindings `bindings`.
+ *
+ * Traps are sent by the local engine, so `session` should come from `Session::local` with keys
+ * localized to the local engine ID. `uptime` is the value of `sysUpTime.0`, in hundredths of a
+ * second. Nothing acknowledges a trap, so success only means it was sent.
+ */
+pub fn snmp_trap<D, P, S>(
+    trap_oid: &OID,
+    uptime: u32,
+    bindings: Vec<(OID, params::SnmpValue)>,
+    client: &Client,
+    session: &mut Session<D, P, S>,
+) -> Result<()>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let (trap_oid, var_binds, _) = notification_var_binds(trap_oid, bindings)?;
+    let mut trap = msg_factory::create_notification_msg(
+        PduType::SnmpTrap,
+        uptime,
+        trap_oid,
+        var_binds,
+        session,
+    );
+    client.send_msg(&mut trap, session)?;
 
-                let oid_string: &String = &var.name().to_string();
-                let oid_obj: OID = OID {
-                    name: var.name().to_string(),
-                    oid: (*oid_string.clone()).to_string(),
-                };
-                let this_result: params::SnmpResult = var_bind_to_snmp_result(
-                    client.socket.peer_addr()?.to_string(),
-                    oid_map
-                        .clone()
-                        .find_oid_name(oid_obj.oid.clone())
-                        .unwrap_or_else(|| oid_obj.oid.to_string()),
-                    var.clone(),
-                )?;
-                //println!("request.rs: {:?}", this_result);
-                retval.push(this_result);
-                var_bind = vec![VarBind::new(var.name().clone())];
-            }
+    Ok(())
+}
+
+/**
+ * Sends an `InformRequest` and waits for the receiver to acknowledge it.
+ *
+ * Informs are sent to the receiver's engine, so `session` should come from `Session::new`, which
+ * discovers it. The inform is retransmitted as allowed by the client's retry policy until a
+ * Response PDU arrives.
+ */
+pub fn snmp_inform<D, P, S>(
+    trap_oid: &OID,
+    uptime: u32,
+    bindings: Vec<(OID, params::SnmpValue)>,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<()>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    let (trap_oid, var_binds, names) = notification_var_binds(trap_oid, bindings)?;
+    let mut inform = msg_factory::create_notification_msg(
+        PduType::InformRequest,
+        uptime,
+        trap_oid,
+        var_binds,
+        session,
+    );
+    let response = client.send_request(&mut inform, session)?;
 
-            None => {
-                //println!("== exited snmp_walk (None) ==");
-                return Ok(retval);
+    match response.scoped_pdu_data.plaintext() {
+        Some(scoped_pdu) if scoped_pdu.pdu_type() == PduType::Response => {
+            check_error_status(&response, &names)
+        }
+        _ => Err(Error::protocol(
+            "inform wasn't acknowledged with a Response PDU",
+        )),
+    }
+}
+
+// Parses the trap OID and the bindings of a notification. The names returned match the variable
+// bindings of the message, which start with `sysUpTime.0` and `snmpTrapOID.0`.
+fn notification_var_binds(
+    trap_oid: &OID,
+    bindings: Vec<(OID, params::SnmpValue)>,
+) -> Result<(ObjectIdent, Vec<VarBind>, Vec<String>)> {
+    let trap_oid = ObjectIdent::from_str(&trap_oid.oid)
+        .map_err(|_| Error::InvalidOid(trap_oid.oid.clone()))?;
+
+    let mut names = vec!["sysUpTime.0".to_string(), "snmpTrapOID.0".to_string()];
+    let mut var_binds: Vec<VarBind> = Vec::new();
+    for (oid, value) in bindings {
+        let name =
+            ObjectIdent::from_str(&oid.oid).map_err(|_| Error::InvalidOid(oid.oid.clone()))?;
+        var_binds.push(VarBind::with_value(name, value.into()));
+        names.push(oid.name);
+    }
+
+    Ok((trap_oid, var_binds, names))
+}
+
+// Sends a `GetBulkRequest`, halving `max_repetitions` and resending while the agent answers with
+// `tooBig`. On success `max_repetitions` is adjusted to what the next response can hold.
+fn send_bulk_request<D, P, S>(
+    var_binds: &[VarBind],
+    non_repeaters: u32,
+    max_repetitions: &mut u32,
+    client: &mut Client,
+    session: &mut Session<D, P, S>,
+) -> Result<SnmpMsg>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    loop {
+        let mut bulk_request = msg_factory::create_bulk_request_msg(
+            var_binds.to_vec(),
+            non_repeaters,
+            *max_repetitions,
+            session,
+        )?;
+        let response = client.send_request(&mut bulk_request, session)?;
+
+        let error_status = response
+            .scoped_pdu_data
+            .plaintext()
+            .map(|scoped_pdu| scoped_pdu.error_status());
+        if error_status == Some(PduErrorStatus::TooBig) {
+            if *max_repetitions <= 1 {
+                return Err(PduError {
+                    status: PduErrorStatus::TooBig,
+                    index: 0,
+                    oid: None,
+                }
+                .into());
             }
+
+            *max_repetitions /= 2;
+            continue;
         }
+
+        let repeaters = var_binds.len().saturating_sub(non_repeaters as usize);
+        *max_repetitions = fit_max_repetitions(&response, *max_repetitions, repeaters);
+
+        return Ok(response);
     }
 }
 
-fn strings_to_var_binds<'a, I>(strings: I) -> Vec<VarBind>
+/**
+ * Returns the error-status of `response` as a `PduError`.
+ *
+ * `names` holds the name of each variable binding of the request, and is used to name the one the
+ * error-index points at.
+*/
+pub(crate) fn check_error_status(response: &SnmpMsg, names: &[String]) -> Result<()> {
+    let scoped_pdu = match response.scoped_pdu_data.plaintext() {
+        Some(scoped_pdu) => scoped_pdu,
+        None => return Ok(()),
+    };
+
+    if scoped_pdu.error_status() == PduErrorStatus::NoError {
+        return Ok(());
+    }
+
+    let index = scoped_pdu.error_index();
+    let oid = (index as usize)
+        .checked_sub(1)
+        .and_then(|i| names.get(i))
+        .cloned();
+    Err(PduError {
+        status: scoped_pdu.error_status(),
+        index,
+        oid,
+    }
+    .into())
+}
+
+fn var_bind_names(oids: &[OID]) -> Vec<String> {
+    oids.iter().map(|x| x.name.clone()).collect()
+}
+
+// Estimates how many repetitions fit in a UDP datagram from the average size of the variable
+// bindings in `response`, and returns the smaller of that and `max_repetitions`.
+pub(crate) fn fit_max_repetitions(
+    response: &SnmpMsg,
+    max_repetitions: u32,
+    repeaters: usize,
+) -> u32 {
+    let var_binds = match get_var_binds(response) {
+        Some(var_binds) if !var_binds.is_empty() && repeaters > 0 => var_binds,
+        _ => return max_repetitions,
+    };
+
+    let encoded_len = response.encode().len().saturating_sub(MSG_OVERHEAD);
+    let var_bind_len = (encoded_len / var_binds.len()).max(1);
+    let budget = SnmpMsg::MAX_UDP_PACKET_SIZE - MSG_OVERHEAD;
+    let fitting = (budget / (var_bind_len * repeaters)).max(1);
+
+    max_repetitions.min(fitting as u32)
+}
+
+// Fails on the first invalid OID, so the results line up with the requested OIDs.
+fn strings_to_var_binds<'a, I>(strings: I) -> Result<Vec<VarBind>>
 where
     I: for<'b> Iterator<Item = &'a String>,
 {
-    strings
-        .map(|oid_str| ObjectIdent::from_str(&oid_str))
-        .filter_map(Result::ok)
-        .map(VarBind::new)
-        .collect()
-}
+    let var_binds = strings
+        .map(|oid_str| {
+            ObjectIdent::from_str(oid_str)
+                .map(VarBind::new)
+                .map_err(|_| Error::InvalidOid(oid_str.clone()))
+        })
+        .collect::<Result<Vec<VarBind>>>()?;
 
-fn get_var_binds(msg: &SnmpMsg) -> Option<&[VarBind]> {
-    Some(msg.scoped_pdu_data.plaintext()?.var_binds())
+    if var_binds.is_empty() {
+        return Err(Error::config("no OIDs supplied"));
+    }
+
+    Ok(var_binds)
 }
 
-fn get_first_var_bind(msg: &SnmpMsg) -> Option<&VarBind> {
-    get_var_binds(msg)?.first()
+pub(crate) fn get_var_binds(msg: &SnmpMsg) -> Option<&[VarBind]> {
+    Some(msg.scoped_pdu_data.plaintext()?.var_binds())
 }
 
-fn next_sibling(oid: &ObjectIdent) -> ObjectIdent {
+pub(crate) fn next_sibling(oid: &ObjectIdent) -> ObjectIdent {
     let mut components = oid.components().to_vec();
     let len = components.len();
     components[len - 1] = components[len - 1].wrapping_add(1);
@@ -149,16 +560,114 @@ fn next_sibling(oid: &ObjectIdent) -> ObjectIdent {
     ObjectIdent::new(components)
 }
 
-fn var_bind_to_snmp_result(
+pub(crate) fn var_bind_to_snmp_result(
     req_host: String,
     req_oid: String,
+    requested_oid: &ObjectIdent,
     req_var_bind: VarBind,
 ) -> Result<params::SnmpResult> {
     let bound_value: params::SnmpValue = params::SnmpValue::from(req_var_bind.value().to_owned());
     let retval: params::SnmpResult = params::SnmpResult {
         host: req_host,
         oid: req_oid.to_string(),
+        requested_oid: requested_oid.to_string(),
+        returned_oid: req_var_bind.name().to_string(),
+        outcome: params::Outcome::from(&bound_value),
         result: Some(bound_value),
     };
     Ok(retval)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use snmp_usm::{DesPrivKey, Md5};
+
+    pub(crate) fn response_with(var_binds: Vec<VarBind>) -> SnmpMsg {
+        let mut response = SnmpMsg::new(1);
+        if let Some(scoped_pdu) = response.scoped_pdu_data.plaintext_mut() {
+            scoped_pdu
+                .set_pdu_type(PduType::Response)
+                .set_var_binds(var_binds);
+        }
+
+        response
+    }
+
+    #[test]
+    fn it_shrinks_max_repetitions_for_large_var_binds() {
+        let name = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 2, 1]);
+        let value = VarValue::String(vec![b'x'; 8_000]);
+        let response = response_with(vec![VarBind::with_value(name, value); 2]);
+
+        assert_eq!(fit_max_repetitions(&response, 50, 1), 8);
+    }
+
+    #[test]
+    fn it_keeps_max_repetitions_for_small_var_binds() {
+        let name = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 1, 1]);
+        let response = response_with(vec![VarBind::with_value(name, VarValue::Int(1)); 10]);
+
+        assert_eq!(fit_max_repetitions(&response, 50, 1), 50);
+    }
+
+    #[test]
+    fn it_limits_non_repeaters_to_the_number_of_oids() {
+        let mut session = Session::<Md5, DesPrivKey<Md5>, u32>::undiscovered();
+        let var_bind = VarBind::new(ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 1]));
+
+        let msg =
+            msg_factory::create_bulk_request_msg(vec![var_bind.clone(); 2], 100, 10, &mut session)
+                .unwrap();
+        let scoped_pdu = msg.scoped_pdu_data.plaintext().unwrap();
+        assert_eq!(scoped_pdu.error_status() as u32, 2);
+
+        let result = msg_factory::create_bulk_request_msg(vec![var_bind; 20], 19, 10, &mut session);
+        assert!(matches!(result, Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn it_computes_next_sibling() {
+        let oid = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2]);
+        assert_eq!(
+            next_sibling(&oid),
+            ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 3])
+        );
+    }
+
+    #[test]
+    fn it_maps_error_status_to_the_failing_oid() {
+        let name = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 1, 5, 0]);
+        let mut response = response_with(vec![VarBind::new(name.clone()), VarBind::new(name)]);
+        if let Some(scoped_pdu) = response.scoped_pdu_data.plaintext_mut() {
+            scoped_pdu
+                .set_error_status(PduErrorStatus::GenErr)
+                .set_error_index(2);
+        }
+
+        let names = ["sysName.0".to_string(), "sysLocation.0".to_string()];
+        match check_error_status(&response, &names) {
+            Err(Error::ErrorStatus(error)) => {
+                assert_eq!(error.status_name(), "genErr");
+                assert_eq!(error.oid.as_deref(), Some("sysLocation.0"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_reports_exceptions_as_outcomes() {
+        let name = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 1, 5, 0]);
+        let var_bind = VarBind::with_value(name.clone(), VarValue::NoSuchInstance);
+        let result = var_bind_to_snmp_result(
+            "agent".to_string(),
+            "sysName.0".to_string(),
+            &name,
+            var_bind,
+        )
+        .unwrap();
+
+        assert_eq!(result.outcome, params::Outcome::NoSuchInstance);
+        assert!(result.value().is_none());
+    }
+}
@@ -0,0 +1,127 @@
+use crate::error::{Error, Result};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/**
+ * How long to wait for a response and how often to retransmit a request that timed out.
+ *
+ * A request is sent at most `retries + 1` times. Each retransmission is encrypted again, so it
+ * carries a fresh salt and the current engine time.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Time to wait for the response to the first attempt, in milliseconds.
+    pub timeout_ms: u64,
+    pub retries: u32,
+    pub backoff: Backoff,
+}
+
+impl RetryPolicy {
+    pub const DEFAULT_TIMEOUT_MS: u64 = 3000;
+    pub const DEFAULT_RETRIES: u32 = 2;
+
+    /**
+     * Checks that every attempt gets a non-zero timeout.
+     */
+    pub(crate) fn validate(&self) -> Result<()> {
+        let max_timeout_ms = match self.backoff {
+            Backoff::Fixed => self.timeout_ms,
+            Backoff::Exponential { max_timeout_ms, .. } => max_timeout_ms,
+        };
+        if self.timeout_ms == 0 || max_timeout_ms == 0 {
+            return Err(Error::config("the retry timeout can't be zero"));
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Returns how long to wait for the response to `attempt`, counted from 0.
+     */
+    pub fn timeout(&self, attempt: u32) -> Duration {
+        let timeout_ms = match self.backoff {
+            Backoff::Fixed => self.timeout_ms,
+            Backoff::Exponential {
+                max_timeout_ms,
+                jitter,
+            } => {
+                let factor = 1u64.checked_shl(attempt).unwrap_or(u64::MAX);
+                let timeout_ms = self.timeout_ms.saturating_mul(factor).min(max_timeout_ms);
+                if jitter && timeout_ms > 1 {
+                    // "Equal jitter": keep at least half of the computed timeout.
+                    rand::thread_rng().gen_range(timeout_ms / 2, timeout_ms + 1)
+                } else {
+                    timeout_ms
+                }
+            }
+        };
+
+        // A zero read timeout means blocking forever to `UdpSocket`.
+        Duration::from_millis(timeout_ms.max(1))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_ms: Self::DEFAULT_TIMEOUT_MS,
+            retries: Self::DEFAULT_RETRIES,
+            backoff: Backoff::Fixed,
+        }
+    }
+}
+
+/**
+ * How the timeout grows between attempts.
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub enum Backoff {
+    /// Every attempt waits `RetryPolicy.timeout_ms`.
+    #[default]
+    Fixed,
+    /// The timeout doubles after each attempt, up to `max_timeout_ms`. With `jitter`, each
+    /// timeout is picked at random between half and all of its value, so pollers that time out
+    /// together don't retransmit in lockstep.
+    Exponential { max_timeout_ms: u64, jitter: bool },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_computes_backoff_timeouts() {
+        let fixed = RetryPolicy::default();
+        assert_eq!(fixed.timeout(0), Duration::from_secs(3));
+        assert_eq!(fixed.timeout(2), Duration::from_secs(3));
+
+        let exponential = RetryPolicy {
+            timeout_ms: 500,
+            retries: 5,
+            backoff: Backoff::Exponential {
+                max_timeout_ms: 3000,
+                jitter: false,
+            },
+        };
+        assert_eq!(exponential.timeout(0), Duration::from_millis(500));
+        assert_eq!(exponential.timeout(2), Duration::from_millis(2000));
+        assert_eq!(exponential.timeout(3), Duration::from_millis(3000));
+        assert_eq!(exponential.timeout(100), Duration::from_millis(3000));
+
+        let jittered = RetryPolicy {
+            backoff: Backoff::Exponential {
+                max_timeout_ms: 3000,
+                jitter: true,
+            },
+            ..exponential
+        };
+        for _ in 0..32 {
+            let timeout = jittered.timeout(1);
+            assert!(
+                timeout >= Duration::from_millis(500) && timeout <= Duration::from_millis(1000)
+            );
+        }
+    }
+}
@@ -1,9 +1,13 @@
+#[cfg(feature = "async")]
+use crate::async_client::AsyncClient;
+use crate::auth::{AuthKey, Digest, LocalizedKey};
 use crate::client::Client;
+use crate::error::Result;
 use crate::msg_factory;
-use anyhow::Result;
+use crate::priv_key::WithPasswd;
 use rand::prelude::*;
 use snmp_mp::{ScopedPdu, SnmpMsg};
-use snmp_usm::{AuthKey, Digest, PrivKey, SecurityParams};
+use snmp_usm::{PrivKey, SecurityParams};
 use std::time::Instant;
 
 /**
@@ -110,7 +114,7 @@ impl<'a, D, P, S> Session<'a, D, P, S> {
         request_id
     }
 
-    pub fn auth_key(&self) -> &Option<AuthKey<D>> {
+    pub fn auth_key(&self) -> &Option<AuthKey<'a, D>> {
         &self.auth_key
     }
 
@@ -135,9 +139,40 @@ where
     S: Step + Copy,
 {
     pub fn new(client: &mut Client, username: &[u8]) -> Result<Self> {
+        let mut session = Self::undiscovered();
+        let mut discovery_msg = msg_factory::create_reportable_msg(&mut session);
+        let discovery_response = client.send_discovery(&mut discovery_msg, &mut session)?;
+        session.complete_discovery(username, &discovery_response)?;
+
+        Ok(session)
+    }
+
+    /**
+     * Asynchronous version of `Session::new`.
+     */
+    #[cfg(feature = "async")]
+    pub async fn new_async(client: &mut AsyncClient, username: &[u8]) -> Result<Self> {
+        let mut session = Self::undiscovered();
+        let mut discovery_msg = msg_factory::create_reportable_msg(&mut session);
+        let discovery_response = client
+            .send_discovery(&mut discovery_msg, &mut session)
+            .await?;
+        session.complete_discovery(username, &discovery_response)?;
+
+        Ok(session)
+    }
+
+    /**
+     * Constructs a session that hasn't been through discovery yet.
+     *
+     * Only useful with transports that can't call `Session::new`, like `MultiClient`: send the
+     * message built by `msg_factory::create_reportable_msg` and pass the response to
+     * `complete_discovery`.
+     */
+    pub fn undiscovered() -> Self {
         let mut rng = thread_rng();
 
-        let mut session = Self {
+        Self {
             username: Default::default(),
             engine_id: Default::default(),
             engine_boots: Default::default(),
@@ -147,19 +182,40 @@ where
             sync_time: Instant::now(),
             auth_key: None,
             priv_key: None,
-        };
-
-        let mut discovery_msg = msg_factory::create_reportable_msg(&mut session);
-        let discovery_response = client.send_request(&mut discovery_msg, &mut session)?;
+        }
+    }
 
-        let security_params = SecurityParams::decode(discovery_response.security_params())?;
+    /**
+     * Constructs a session in which the local engine is authoritative, as when sending traps.
+     *
+     * No discovery takes place: the engine time starts at zero and grows from now on.
+     */
+    pub fn local(username: &[u8], engine_id: &[u8], engine_boots: u32) -> Self {
+        let mut session = Self::undiscovered();
         session
             .set_username(username)
+            .set_engine_id(engine_id)
+            .set_engine_boots(engine_boots)
+            .set_engine_time(0);
+
+        session
+    }
+
+    /**
+     * Stores the engine ID, boots and time the agent reported in answer to discovery.
+     */
+    pub fn complete_discovery(
+        &mut self,
+        username: &[u8],
+        discovery_response: &SnmpMsg,
+    ) -> Result<()> {
+        let security_params = SecurityParams::decode(discovery_response.security_params())?;
+        self.set_username(username)
             .set_engine_id(security_params.engine_id())
             .set_engine_boots(security_params.engine_boots())
             .set_engine_time(security_params.engine_time());
 
-        Ok(session)
+        Ok(())
     }
 
     pub fn priv_key_and_salt(&mut self) -> Option<(&P, P::Salt)> {
@@ -177,4 +233,33 @@ where
         self.priv_key = Some((priv_key, salt));
         self
     }
+
+    /**
+     * Derives the authentication key, and the privacy key if `priv_passwd` is given, from the
+     * passwords and the session's engine ID. Privacy is ignored without authentication.
+     *
+     * Fails with a `ConfigError` if a password is empty.
+     */
+    pub fn set_passwords(
+        &mut self,
+        auth_passwd: Option<&str>,
+        priv_passwd: Option<&str>,
+        salt: P::Salt,
+    ) -> Result<&mut Self>
+    where
+        P: WithPasswd<'a, D>,
+    {
+        if let Some(auth_passwd) = auth_passwd {
+            let localized_key = LocalizedKey::<D>::new(auth_passwd.as_bytes(), self.engine_id())?;
+            self.set_auth_key(AuthKey::new(localized_key));
+
+            if let Some(priv_passwd) = priv_passwd {
+                // The privacy key is derived with the authentication digest (RFC 3826, RFC 7860).
+                let priv_key = P::with_passwd(priv_passwd.as_bytes(), self.engine_id())?;
+                self.set_priv_key_and_salt(priv_key, salt);
+            }
+        }
+
+        Ok(self)
+    }
 }
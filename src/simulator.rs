@@ -0,0 +1,501 @@
+use crate::agent::{Agent, MibTable};
+use crate::error::{ParseError, Result};
+use crate::params::{ObjectIdentifier, SnmpResult, SnmpValue};
+use snmp_mp::ObjectIdent;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::str::FromStr;
+
+/**
+ * Formats of recorded device data understood by `load`.
+*/
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecordFormat {
+    /// snmpsim `.snmprec` files, with one `OID|TAG|VALUE` line per variable.
+    Snmprec,
+    /// Output of net-snmp's `snmpwalk -On`.
+    Snmpwalk,
+    /// JSON written by the `get-snmp` example, holding the results of one or more hosts.
+    Json,
+}
+
+impl RecordFormat {
+    /**
+     * Guesses the format from the extension of `path`: `.snmprec`, `.json`, and snmpwalk output
+     * for anything else.
+     */
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("snmprec") => RecordFormat::Snmprec,
+            Some("json") => RecordFormat::Json,
+            _ => RecordFormat::Snmpwalk,
+        }
+    }
+}
+
+/**
+ * Variables recorded from one device, to be served by an `Agent`.
+*/
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// File stem of the recording, or the host the results were polled from for JSON.
+    pub name: String,
+    pub table: MibTable,
+}
+
+impl Recording {
+    /**
+     * Registers the recorded variables with `agent`, with one handler per top-level subtree
+     * (`1.3`, `2.16`, ...) as the agent can't register a single-arc subtree.
+     */
+    pub fn register(self, agent: &mut Agent) -> Result<()> {
+        let mut subtrees: Vec<(ObjectIdent, MibTable)> = Vec::new();
+        for (oid, value) in self.table.values() {
+            let subtree = ObjectIdent::from_slice(&oid.components()[..2]);
+            match subtrees.last_mut() {
+                Some((last, table)) if *last == subtree => {
+                    table.insert(oid.clone(), value.clone());
+                }
+                _ => {
+                    let mut table = MibTable::new();
+                    table.insert(oid.clone(), value.clone());
+                    subtrees.push((subtree, table));
+                }
+            }
+        }
+
+        for (subtree, table) in subtrees {
+            agent.register(&subtree.to_string(), table)?;
+        }
+
+        Ok(())
+    }
+}
+
+/**
+ * Loads the recordings in the file at `path`, in the format given by its extension.
+ *
+ * snmprec and snmpwalk files hold one device, named after the file. JSON files hold one device
+ * per polled host.
+*/
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Recording>> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    let name = path
+        .file_stem()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let table = match RecordFormat::from_path(path) {
+        RecordFormat::Snmprec => parse_snmprec(&text)?,
+        RecordFormat::Snmpwalk => parse_snmpwalk(&text)?,
+        RecordFormat::Json => return parse_json(&text),
+    };
+
+    Ok(vec![Recording { name, table }])
+}
+
+/**
+ * Parses an snmpsim `.snmprec` file.
+ *
+ * Tags with an `x` suffix carry hex-encoded values. Exception tags (128 to 130) are skipped, and
+ * variation modules aren't supported.
+*/
+pub fn parse_snmprec(text: &str) -> Result<MibTable> {
+    let mut table = MibTable::new();
+    for (index, line) in text.lines().enumerate() {
+        let line_num = index + 1;
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = line.splitn(3, '|');
+        let (oid, tag, value) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(oid), Some(tag), Some(value)) => (oid, tag, value),
+            _ => return Err(ParseError::new(line_num, "expected OID|TAG|VALUE").into()),
+        };
+
+        let oid = parse_oid(oid)
+            .ok_or_else(|| ParseError::new(line_num, format!("invalid OID {:?}", oid)))?;
+        if let Some(value) =
+            snmprec_value(tag, value).map_err(|message| ParseError::new(line_num, message))?
+        {
+            table.insert(oid, value);
+        }
+    }
+
+    Ok(table)
+}
+
+/**
+ * Parses the output of `snmpwalk -On`, where each line reads `.1.3.6.1.2.1.1.5.0 = STRING: "x"`.
+ *
+ * Values spanning several lines, like long strings and hex strings, are joined back together.
+ * Exceptions such as `No Such Instance` are skipped.
+*/
+pub fn parse_snmpwalk(text: &str) -> Result<MibTable> {
+    let mut table = MibTable::new();
+    for (line_num, record) in walk_records(text) {
+        if record.trim().is_empty() {
+            continue;
+        }
+
+        let (oid, value) = record
+            .split_once(" = ")
+            .ok_or_else(|| ParseError::new(line_num, "expected OID = TYPE: VALUE"))?;
+        let oid = parse_oid(oid)
+            .ok_or_else(|| ParseError::new(line_num, format!("invalid OID {:?}", oid)))?;
+        if let Some(value) =
+            walk_value(value).map_err(|message| ParseError::new(line_num, message))?
+        {
+            table.insert(oid, value);
+        }
+    }
+
+    Ok(table)
+}
+
+/**
+ * Parses the JSON written by the `get-snmp` example, a list of `[host, [SnmpResult, ...]]` pairs.
+ *
+ * Exceptions are skipped, as are results without a numeric OID, written before `returned_oid`
+ * was recorded.
+*/
+pub fn parse_json(text: &str) -> Result<Vec<Recording>> {
+    let hosts: Vec<(String, Vec<SnmpResult>)> = serde_json::from_str(text)
+        .map_err(|error| ParseError::new(error.line(), error.to_string()))?;
+
+    let mut recordings: Vec<Recording> = Vec::new();
+    for (host, results) in hosts {
+        let index = match recordings.iter().position(|x| x.name == host) {
+            Some(index) => index,
+            None => {
+                recordings.push(Recording {
+                    name: host,
+                    table: MibTable::new(),
+                });
+                recordings.len() - 1
+            }
+        };
+
+        for result in results {
+            let oid = match result.returned_oid.as_str() {
+                "" => result.oid.as_str(),
+                returned_oid => returned_oid,
+            };
+            if let (Some(oid), Some(value)) = (parse_oid(oid), result.value()) {
+                recordings[index].table.insert(oid, value.clone());
+            }
+        }
+    }
+
+    Ok(recordings)
+}
+
+// Accepts dotted OIDs with or without the leading dot, and `iso` for the first arc, as printed by
+// net-snmp.
+fn parse_oid(oid: &str) -> Option<ObjectIdent> {
+    let oid = oid.trim();
+    let oid = oid.strip_prefix('.').unwrap_or(oid);
+    let oid = match oid.strip_prefix("iso") {
+        Some(rest) => format!("1{}", rest),
+        None => oid.to_string(),
+    };
+
+    let components = oid
+        .split('.')
+        .map(|x| x.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    match components.len() {
+        0 | 1 => None,
+        _ => Some(ObjectIdent::new(components)),
+    }
+}
+
+fn snmprec_value(tag: &str, value: &str) -> std::result::Result<Option<SnmpValue>, String> {
+    let (tag_num, hex) = match tag.strip_suffix('x') {
+        Some(tag_num) => (tag_num, true),
+        None => (tag, false),
+    };
+    let tag_num: u8 = tag_num.parse().map_err(|_| match tag.contains(':') {
+        true => format!("variation modules aren't supported: {:?}", tag),
+        false => format!("invalid tag {:?}", tag),
+    })?;
+
+    let bytes = match hex {
+        true => decode_hex(value)?,
+        false => value.as_bytes().to_vec(),
+    };
+    let text = || String::from_utf8(bytes.clone()).map_err(|_| "invalid text value".to_string());
+
+    let value = match tag_num {
+        2 => SnmpValue::Int(number(&text()?)?),
+        4 => SnmpValue::OctetString(bytes),
+        5 => SnmpValue::Unspecified,
+        6 => SnmpValue::ObjectId(object_identifier(&text()?)?),
+        64 if hex => SnmpValue::IpAddress(
+            bytes
+                .as_slice()
+                .try_into()
+                .map_err(|_| format!("invalid IpAddress {:?}", value))?,
+        ),
+        64 => SnmpValue::IpAddress(ip_address(value)?),
+        65 => SnmpValue::Counter(number(&text()?)?),
+        66 => SnmpValue::UnsignedInt(number(&text()?)?),
+        67 => SnmpValue::TimeTicks(number(&text()?)?),
+        68 => SnmpValue::Opaque(bytes),
+        70 => SnmpValue::BigCounter(number(&text()?)?),
+        128..=130 => return Ok(None),
+        _ => return Err(format!("unsupported tag {:?}", tag)),
+    };
+
+    Ok(Some(value))
+}
+
+// Returns the bindings of a walk with the line each starts on. Lines that don't start with
+// `OID = ` continue the value of the previous binding.
+fn walk_records(text: &str) -> Vec<(usize, String)> {
+    let mut records: Vec<(usize, String)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let starts_binding = line
+            .split_once(" = ")
+            .is_some_and(|(oid, _)| parse_oid(oid).is_some());
+
+        match records.last_mut() {
+            Some((_, record)) if !starts_binding => {
+                record.push('\n');
+                record.push_str(line);
+            }
+            _ => records.push((index + 1, line.to_string())),
+        }
+    }
+
+    records
+}
+
+fn walk_value(value: &str) -> std::result::Result<Option<SnmpValue>, String> {
+    let value = value.trim();
+    if value.starts_with("No Such") || value.starts_with("No more variables") {
+        return Ok(None);
+    }
+
+    match value {
+        "\"\"" => return Ok(Some(SnmpValue::OctetString(Vec::new()))),
+        "NULL" => return Ok(Some(SnmpValue::Unspecified)),
+        _ => (),
+    }
+
+    let (kind, data) = value
+        .split_once(':')
+        .ok_or_else(|| format!("missing type in {:?}", value))?;
+    let data = data.trim();
+
+    let value = match kind {
+        "STRING" => SnmpValue::OctetString(unquote(data).into_bytes()),
+        "Hex-STRING" => SnmpValue::OctetString(decode_hex(data)?),
+        // The octets come first, followed by the names of the bits that are set.
+        "BITS" => SnmpValue::OctetString(decode_hex(
+            &data
+                .split_whitespace()
+                .take_while(|x| x.len() == 2 && x.chars().all(|c| c.is_ascii_hexdigit()))
+                .collect::<Vec<_>>()
+                .join(" "),
+        )?),
+        "INTEGER" => SnmpValue::Int(number(data)?),
+        "Counter32" => SnmpValue::Counter(number(data)?),
+        "Gauge32" | "Unsigned32" | "UInteger32" => SnmpValue::UnsignedInt(number(data)?),
+        "Counter64" => SnmpValue::BigCounter(number(data)?),
+        "Timeticks" => SnmpValue::TimeTicks(number(data)?),
+        "OID" => SnmpValue::ObjectId(object_identifier(data)?),
+        "IpAddress" => SnmpValue::IpAddress(ip_address(data)?),
+        "Network Address" => SnmpValue::IpAddress(
+            decode_hex(data)?
+                .as_slice()
+                .try_into()
+                .map_err(|_| format!("invalid Network Address {:?}", data))?,
+        ),
+        "Opaque" => SnmpValue::Opaque(decode_hex(data)?),
+        _ => return Err(format!("unsupported type {:?}", kind)),
+    };
+
+    Ok(Some(value))
+}
+
+// Strips the quotes net-snmp puts around strings, and the backslashes it puts before embedded
+// quotes and backslashes.
+fn unquote(data: &str) -> String {
+    let inner = match data.strip_prefix('"').and_then(|x| x.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return data.to_string(),
+    };
+
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+// Accepts `00 1A 2B`, `00:1a:2b` and `001a2b`, over one or more lines.
+fn decode_hex(data: &str) -> std::result::Result<Vec<u8>, String> {
+    let digits: Vec<u8> = data
+        .bytes()
+        .filter(|x| !x.is_ascii_whitespace() && *x != b':')
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits in {:?}", data));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .ok_or_else(|| format!("invalid hex string {:?}", data))
+        })
+        .collect()
+}
+
+// Reads `42`, `up(1)`, `42 seconds` and `(12345) 0:02:03.45`.
+fn number<T: FromStr>(data: &str) -> std::result::Result<T, String> {
+    let digits = match (data.find('('), data.find(')')) {
+        (Some(start), Some(end)) if start < end => &data[start + 1..end],
+        _ => data.split_whitespace().next().unwrap_or_default(),
+    };
+
+    digits
+        .parse()
+        .map_err(|_| format!("invalid number {:?}", data))
+}
+
+fn object_identifier(data: &str) -> std::result::Result<ObjectIdentifier, String> {
+    let oid = parse_oid(data).ok_or_else(|| format!("invalid OID {:?}", data))?;
+    Ok(ObjectIdentifier {
+        components: oid.components().to_vec(),
+    })
+}
+
+fn ip_address(data: &str) -> std::result::Result<[u8; 4], String> {
+    Ipv4Addr::from_str(data.trim())
+        .map(|x| x.octets())
+        .map_err(|_| format!("invalid IpAddress {:?}", data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(table: &MibTable, oid: &str) -> Option<SnmpValue> {
+        table.values().get(&parse_oid(oid).unwrap()).cloned()
+    }
+
+    #[test]
+    fn it_parses_snmprec_files() {
+        let text = "# recorded from a lab switch\n\
+                    1.3.6.1.2.1.1.1.0|4|Linux sw1 5.10\n\
+                    1.3.6.1.2.1.1.2.0|6|1.3.6.1.4.1.8072.3.2.10\n\
+                    1.3.6.1.2.1.1.3.0|67|123456\n\
+                    1.3.6.1.2.1.2.2.1.6.1|4x|0050569a0b01\n\
+                    1.3.6.1.2.1.4.20.1.1.10.0.0.1|64x|0a000001\n\
+                    1.3.6.1.2.1.31.1.1.1.6.1|70|18446744073709551615\n\
+                    1.3.6.1.2.1.99.1.0|129|\n";
+
+        let table = parse_snmprec(text).unwrap();
+        assert_eq!(table.values().len(), 6);
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.1.1.0"),
+            Some(SnmpValue::OctetString(b"Linux sw1 5.10".to_vec()))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.2.2.1.6.1"),
+            Some(SnmpValue::OctetString(vec![
+                0x00, 0x50, 0x56, 0x9a, 0x0b, 0x01
+            ]))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.4.20.1.1.10.0.0.1"),
+            Some(SnmpValue::IpAddress([10, 0, 0, 1]))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.31.1.1.1.6.1"),
+            Some(SnmpValue::BigCounter(u64::MAX))
+        );
+
+        let error = parse_snmprec("1.3.6.1.2.1.1.1.0|4:numeric|x\n").unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Parse(ParseError { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_snmpwalk_output() {
+        let text = ".1.3.6.1.2.1.1.1.0 = STRING: \"Cisco IOS Software,\n\
+                    Version 15.2\"\n\
+                    .1.3.6.1.2.1.1.2.0 = OID: .1.3.6.1.4.1.9.1.1208\n\
+                    .1.3.6.1.2.1.1.3.0 = Timeticks: (8639622) 23:59:56.22\n\
+                    .1.3.6.1.2.1.1.4.0 = \"\"\n\
+                    .1.3.6.1.2.1.2.2.1.6.1 = Hex-STRING: 00 50 56 9A 0B 01 \n\
+                    .1.3.6.1.2.1.2.2.1.8.1 = INTEGER: up(1)\n\
+                    .1.3.6.1.2.1.2.2.1.10.1 = Counter32: 2882400001\n\
+                    .1.3.6.1.2.1.4.20.1.1.10.0.0.1 = IpAddress: 10.0.0.1\n\
+                    .1.3.6.1.2.1.99.1.0 = No Such Instance currently exists at this OID\n";
+
+        let table = parse_snmpwalk(text).unwrap();
+        assert_eq!(table.values().len(), 8);
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.1.1.0"),
+            Some(SnmpValue::OctetString(
+                b"Cisco IOS Software,\nVersion 15.2".to_vec()
+            ))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.1.3.0"),
+            Some(SnmpValue::TimeTicks(8639622))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.1.4.0"),
+            Some(SnmpValue::OctetString(Vec::new()))
+        );
+        assert_eq!(
+            value(&table, "1.3.6.1.2.1.2.2.1.8.1"),
+            Some(SnmpValue::Int(1))
+        );
+
+        let error = parse_snmpwalk(".1.3.6.1.2.1.1.3.0 = Timeticks: soon\n").unwrap_err();
+        assert!(matches!(
+            error,
+            crate::Error::Parse(ParseError { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn it_parses_get_snmp_json() {
+        let results = vec![(
+            "192.0.2.1:161".to_string(),
+            vec![SnmpResult {
+                host: "192.0.2.1:161".to_string(),
+                oid: "sysName.0".to_string(),
+                requested_oid: "1.3.6.1.2.1.1.5.0".to_string(),
+                returned_oid: "1.3.6.1.2.1.1.5.0".to_string(),
+                outcome: Default::default(),
+                result: Some(SnmpValue::OctetString(b"edge1".to_vec())),
+            }],
+        )];
+
+        let recordings = parse_json(&serde_json::to_string(&results).unwrap()).unwrap();
+        assert_eq!(recordings.len(), 1);
+        assert_eq!(recordings[0].name, "192.0.2.1:161");
+        assert_eq!(
+            value(&recordings[0].table, "1.3.6.1.2.1.1.5.0"),
+            Some(SnmpValue::OctetString(b"edge1".to_vec()))
+        );
+    }
+}
@@ -0,0 +1,323 @@
+use crate::addr::{self, SNMP_PORT_NUM};
+use crate::auth::{Digest, Sha224, Sha256, Sha384, Sha512};
+use crate::client::Client;
+use crate::oids::{OidMap, OID};
+use crate::params::{AuthProtocol, Command, Params, PrivProtocol, SnmpResult, SnmpValue};
+use crate::priv_key::{Aes192CPrivKey, Aes192PrivKey, Aes256CPrivKey, Aes256PrivKey, WithPasswd};
+use crate::request;
+use crate::retry::RetryPolicy;
+use crate::session::{Session, Step};
+use crate::walker::Walker;
+
+use crate::error::Result;
+use snmp_mp::PduType;
+use snmp_usm::{Aes128PrivKey, DesPrivKey, Md5, PrivKey, Sha1};
+
+macro_rules! open_session {
+    ($digest:ty, $params:expr) => {{
+        match $params.privacy_protocol {
+            Some(PrivProtocol::Aes128) => open_with!($digest, Aes128PrivKey<$digest>, $params),
+            Some(PrivProtocol::Aes192) => open_with!($digest, Aes192PrivKey<$digest>, $params),
+            Some(PrivProtocol::Aes256) => open_with!($digest, Aes256PrivKey<$digest>, $params),
+            Some(PrivProtocol::Aes192C) => open_with!($digest, Aes192CPrivKey<$digest>, $params),
+            Some(PrivProtocol::Aes256C) => open_with!($digest, Aes256CPrivKey<$digest>, $params),
+            Some(PrivProtocol::Des) | None => open_with!($digest, DesPrivKey<$digest>, $params),
+        }
+    }};
+}
+
+macro_rules! open_with {
+    ($digest:ty, $priv_key:ty, $params:expr) => {{
+        let salt = rand::random();
+        open::<$digest, $priv_key, <$priv_key as PrivKey>::Salt>($params, salt)
+    }};
+}
+
+/**
+ * Long-lived handle to an SNMPv3 agent.
+ *
+ * `SnmpSession` owns the UDP client and the USM session, so engine discovery and key
+ * localization only happen once, in `SnmpSession::new`. Engine boots and time are updated from
+ * every response, which keeps the handle usable for as long as the agent does not reboot.
+*/
+pub struct SnmpSession {
+    host: String,
+    inner: Box<dyn Requests + Send>,
+}
+
+impl SnmpSession {
+    /**
+     * Connects to `params.host` and performs engine discovery. `params.cmd` is not used.
+     */
+    pub fn new(params: &Params) -> Result<Self> {
+        params.validate()?;
+
+        // Without authentication the digest type is never used.
+        let inner = match params.auth_protocol {
+            Some(AuthProtocol::Md5) | None => open_session!(Md5, params)?,
+            Some(AuthProtocol::Sha1) => open_session!(Sha1, params)?,
+            Some(AuthProtocol::Sha224) => open_session!(Sha224, params)?,
+            Some(AuthProtocol::Sha256) => open_session!(Sha256, params)?,
+            Some(AuthProtocol::Sha384) => open_session!(Sha384, params)?,
+            Some(AuthProtocol::Sha512) => open_session!(Sha512, params)?,
+        };
+
+        Ok(Self {
+            host: params.host.clone(),
+            inner,
+        })
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn engine_id(&self) -> &[u8] {
+        self.inner.engine_id()
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.inner.engine_boots()
+    }
+
+    pub fn engine_time(&self) -> u32 {
+        self.inner.engine_time()
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        self.inner.retry_policy()
+    }
+
+    /**
+     * Changes the timeout and retransmission settings used by later requests.
+     */
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()> {
+        self.inner.set_retry_policy(retry_policy)
+    }
+
+    pub fn get(&mut self, oids: Vec<OID>) -> Result<Vec<SnmpResult>> {
+        self.inner.get(oids)
+    }
+
+    pub fn get_next(&mut self, oid_map: &OidMap, oids: Vec<OID>) -> Result<Vec<SnmpResult>> {
+        self.inner.get_next(oid_map, oids)
+    }
+
+    pub fn get_bulk(
+        &mut self,
+        oid_map: &OidMap,
+        oids: Vec<OID>,
+        non_repeaters: u32,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>> {
+        self.inner
+            .get_bulk(oid_map, oids, non_repeaters, max_repetitions)
+    }
+
+    pub fn walk(&mut self, oid_map: &OidMap, oid: OID) -> Result<Vec<SnmpResult>> {
+        self.inner.walk(oid_map, oid)
+    }
+
+    pub fn bulk_walk(
+        &mut self,
+        oid_map: &OidMap,
+        oid: OID,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>> {
+        self.inner.bulk_walk(oid_map, oid, max_repetitions)
+    }
+
+    /**
+     * Returns a `Walker` over the subtree rooted at `oid`, using GetBulk requests when
+     * `max_repetitions` is given and GetNext ones otherwise.
+     */
+    pub fn walker<'s>(
+        &'s mut self,
+        oid_map: &'s OidMap,
+        oid: OID,
+        max_repetitions: Option<u32>,
+    ) -> Result<Box<dyn Iterator<Item = Result<SnmpResult>> + 's>> {
+        self.inner.walker(oid_map, oid, max_repetitions)
+    }
+
+    pub fn set(&mut self, bindings: Vec<(OID, SnmpValue)>) -> Result<Vec<SnmpResult>> {
+        self.inner.set(bindings)
+    }
+
+    /**
+     * Runs `cmd` against the agent.
+     */
+    pub fn execute(&mut self, oid_map: &OidMap, cmd: Command) -> Result<Vec<SnmpResult>> {
+        match cmd {
+            Command::Get { oids } => self.get(oids),
+            Command::GetNext { oids } => self.get_next(oid_map, oids),
+            Command::GetBulk {
+                oids,
+                non_repeaters,
+                max_repetitions,
+            } => self.get_bulk(oid_map, oids, non_repeaters, max_repetitions),
+            Command::Walk { oid } => self.walk(oid_map, oid),
+            Command::BulkWalk {
+                oid,
+                max_repetitions,
+            } => self.bulk_walk(oid_map, oid, max_repetitions),
+            Command::Set { bindings } => self.set(bindings),
+        }
+    }
+}
+
+// Object safe view of a `Client` and `Session` pair, so the digest and privacy types chosen at
+// runtime don't leak into `SnmpSession`.
+trait Requests {
+    fn engine_id(&self) -> &[u8];
+    fn engine_boots(&self) -> u32;
+    fn engine_time(&self) -> u32;
+    fn retry_policy(&self) -> &RetryPolicy;
+    fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()>;
+    fn get(&mut self, oids: Vec<OID>) -> Result<Vec<SnmpResult>>;
+    fn get_next(&mut self, oid_map: &OidMap, oids: Vec<OID>) -> Result<Vec<SnmpResult>>;
+    fn get_bulk(
+        &mut self,
+        oid_map: &OidMap,
+        oids: Vec<OID>,
+        non_repeaters: u32,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>>;
+    fn walk(&mut self, oid_map: &OidMap, oid: OID) -> Result<Vec<SnmpResult>>;
+    fn bulk_walk(
+        &mut self,
+        oid_map: &OidMap,
+        oid: OID,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>>;
+    fn walker<'s>(
+        &'s mut self,
+        oid_map: &'s OidMap,
+        oid: OID,
+        max_repetitions: Option<u32>,
+    ) -> Result<Box<dyn Iterator<Item = Result<SnmpResult>> + 's>>;
+    fn set(&mut self, bindings: Vec<(OID, SnmpValue)>) -> Result<Vec<SnmpResult>>;
+}
+
+struct Handle<D: 'static, P, S> {
+    client: Client,
+    session: Session<'static, D, P, S>,
+}
+
+impl<D, P, S> Requests for Handle<D, P, S>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    fn engine_id(&self) -> &[u8] {
+        self.session.engine_id()
+    }
+
+    fn engine_boots(&self) -> u32 {
+        self.session.engine_boots()
+    }
+
+    fn engine_time(&self) -> u32 {
+        self.session.engine_time()
+    }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        self.client.retry_policy()
+    }
+
+    fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> Result<()> {
+        self.client.set_retry_policy(retry_policy)?;
+        Ok(())
+    }
+
+    fn get(&mut self, oids: Vec<OID>) -> Result<Vec<SnmpResult>> {
+        request::snmp_get(
+            PduType::GetRequest,
+            oids,
+            &mut self.client,
+            &mut self.session,
+        )
+    }
+
+    fn get_next(&mut self, oid_map: &OidMap, oids: Vec<OID>) -> Result<Vec<SnmpResult>> {
+        request::snmp_get_next(oid_map, oids, &mut self.client, &mut self.session)
+    }
+
+    fn get_bulk(
+        &mut self,
+        oid_map: &OidMap,
+        oids: Vec<OID>,
+        non_repeaters: u32,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>> {
+        request::snmp_get_bulk(
+            oid_map,
+            oids,
+            non_repeaters,
+            max_repetitions,
+            &mut self.client,
+            &mut self.session,
+        )
+    }
+
+    fn walk(&mut self, oid_map: &OidMap, oid: OID) -> Result<Vec<SnmpResult>> {
+        request::snmp_walk(oid_map, oid, &mut self.client, &mut self.session)
+    }
+
+    fn bulk_walk(
+        &mut self,
+        oid_map: &OidMap,
+        oid: OID,
+        max_repetitions: u32,
+    ) -> Result<Vec<SnmpResult>> {
+        request::snmp_bulk_walk(
+            oid_map,
+            oid,
+            max_repetitions,
+            &mut self.client,
+            &mut self.session,
+        )
+    }
+
+    fn walker<'s>(
+        &'s mut self,
+        oid_map: &'s OidMap,
+        oid: OID,
+        max_repetitions: Option<u32>,
+    ) -> Result<Box<dyn Iterator<Item = Result<SnmpResult>> + 's>> {
+        let walker = Walker::new(oid_map, oid, &mut self.client, &mut self.session)?;
+        Ok(match max_repetitions {
+            Some(max_repetitions) => Box::new(walker.bulk(max_repetitions)),
+            None => Box::new(walker),
+        })
+    }
+
+    fn set(&mut self, bindings: Vec<(OID, SnmpValue)>) -> Result<Vec<SnmpResult>> {
+        request::snmp_set(bindings, &mut self.client, &mut self.session)
+    }
+}
+
+fn open<D, P, S>(params: &Params, salt: P::Salt) -> Result<Box<dyn Requests + Send>>
+where
+    D: 'static + Digest + Send + Sync,
+    P: 'static + PrivKey<Salt = S> + WithPasswd<'static, D> + Send,
+    S: 'static + Step + Copy + Send,
+{
+    let local_addr = match &params.local_addr {
+        Some(local_addr) => Some(addr::resolve(local_addr, 0)?),
+        None => None,
+    };
+    let remote_addr = match &local_addr {
+        Some(local_addr) => addr::resolve_for(&params.host, SNMP_PORT_NUM, local_addr)?,
+        None => addr::resolve(&params.host, SNMP_PORT_NUM)?,
+    };
+
+    let mut client = Client::with_local_addr(remote_addr, local_addr)?;
+    client.set_retry_policy(params.retry)?;
+    let mut session = Session::<D, P, S>::new(&mut client, params.user.as_bytes())?;
+
+    session.set_passwords(params.auth.as_deref(), params.privacy.as_deref(), salt)?;
+
+    Ok(Box::new(Handle { client, session }))
+}
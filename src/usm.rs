@@ -0,0 +1,418 @@
+use crate::auth::{Digest, Sha224, Sha256, Sha384, Sha512};
+use crate::client;
+use crate::error::{Error, ReportKind, Result};
+use crate::params::{AuthProtocol, PrivProtocol, UsmUser};
+use crate::priv_key::{Aes192CPrivKey, Aes192PrivKey, Aes256CPrivKey, Aes256PrivKey, WithPasswd};
+use crate::session::{Session, Step};
+use rand::prelude::*;
+use snmp_mp::{ObjectIdent, PduType, SnmpMsg, VarBind, VarValue};
+use snmp_usm::{Aes128PrivKey, DesPrivKey, Md5, PrivKey, SecurityError, SecurityParams, Sha1};
+use std::collections::HashMap;
+use std::time::Instant;
+
+macro_rules! localize_user {
+    ($digest:ty, $user:expr, $engine_id:expr) => {{
+        match $user.privacy_protocol {
+            Some(PrivProtocol::Aes128) => {
+                localize_with!($digest, Aes128PrivKey<$digest>, $user, $engine_id)
+            }
+            Some(PrivProtocol::Aes192) => {
+                localize_with!($digest, Aes192PrivKey<$digest>, $user, $engine_id)
+            }
+            Some(PrivProtocol::Aes256) => {
+                localize_with!($digest, Aes256PrivKey<$digest>, $user, $engine_id)
+            }
+            Some(PrivProtocol::Aes192C) => {
+                localize_with!($digest, Aes192CPrivKey<$digest>, $user, $engine_id)
+            }
+            Some(PrivProtocol::Aes256C) => {
+                localize_with!($digest, Aes256CPrivKey<$digest>, $user, $engine_id)
+            }
+            Some(PrivProtocol::Des) | None => {
+                localize_with!($digest, DesPrivKey<$digest>, $user, $engine_id)
+            }
+        }
+    }};
+}
+
+macro_rules! localize_with {
+    ($digest:ty, $priv_key:ty, $user:expr, $engine_id:expr) => {{
+        let salt = rand::random();
+        localize::<$digest, $priv_key, <$priv_key as PrivKey>::Salt>($user, $engine_id, salt)
+    }};
+}
+
+/**
+ * Outcome of processing a received message.
+*/
+pub(crate) enum Incoming {
+    /// The message was authenticated and decrypted.
+    Accepted(Box<Accepted>),
+    /// The message was dropped, and `report` is the encoded Report PDU to send back if the sender
+    /// asked for one.
+    Dropped { report: Option<Vec<u8>> },
+}
+
+pub(crate) struct Accepted {
+    pub msg: SnmpMsg,
+    pub security_params: SecurityParams,
+}
+
+/**
+ * Authoritative SNMP engine and its USM user table, shared by the command responder (`Agent`) and
+ * the notification receiver.
+ *
+ * Users can be added for other engines too, so that notifications sent by those engines can be
+ * authenticated; the latest boots and time received from them are kept per user.
+*/
+pub(crate) struct LocalEngine {
+    engine_id: Vec<u8>,
+    engine_boots: u32,
+    start_time: Instant,
+    users: HashMap<(Vec<u8>, Vec<u8>), Box<dyn UserKeys>>,
+    stats: HashMap<ReportKind, u32>,
+}
+
+impl LocalEngine {
+    pub fn new(engine_id: &[u8], engine_boots: u32) -> Result<Self> {
+        if engine_id.is_empty() {
+            return Err(Error::config("the engine ID can't be empty"));
+        }
+
+        Ok(Self {
+            engine_id: engine_id.to_vec(),
+            engine_boots,
+            start_time: Instant::now(),
+            users: HashMap::new(),
+            stats: HashMap::new(),
+        })
+    }
+
+    /**
+     * Returns a random engine ID in the administratively assigned octets format of RFC 3411.
+     */
+    pub fn random_engine_id() -> Vec<u8> {
+        let mut engine_id = vec![0x80, 0x00, 0x00, 0x00, 0x05];
+        engine_id.extend_from_slice(&thread_rng().gen::<[u8; 8]>());
+        engine_id
+    }
+
+    pub fn engine_id(&self) -> &[u8] {
+        &self.engine_id
+    }
+
+    pub fn engine_boots(&self) -> u32 {
+        self.engine_boots
+    }
+
+    pub fn engine_time(&self) -> u32 {
+        self.start_time.elapsed().as_secs() as u32
+    }
+
+    pub fn add_user(&mut self, engine_id: &[u8], user: &UsmUser) -> Result<()> {
+        user.validate()?;
+
+        // Without authentication the digest type is never used.
+        let keys = match user.auth_protocol {
+            Some(AuthProtocol::Md5) | None => localize_user!(Md5, user, engine_id),
+            Some(AuthProtocol::Sha1) => localize_user!(Sha1, user, engine_id),
+            Some(AuthProtocol::Sha224) => localize_user!(Sha224, user, engine_id),
+            Some(AuthProtocol::Sha256) => localize_user!(Sha256, user, engine_id),
+            Some(AuthProtocol::Sha384) => localize_user!(Sha384, user, engine_id),
+            Some(AuthProtocol::Sha512) => localize_user!(Sha512, user, engine_id),
+        }?;
+
+        self.users
+            .insert((engine_id.to_vec(), user.user.as_bytes().to_vec()), keys);
+        Ok(())
+    }
+
+    pub fn dropped(&self, kind: ReportKind) -> u32 {
+        self.stats.get(&kind).copied().unwrap_or(0)
+    }
+
+    /**
+     * Processes a received message as described in RFC 3414, section 3.2.
+     *
+     * Messages must be sent at the security level of their user. Anything wrong with the message
+     * gets it dropped and counted; errors are only returned when encoding the report fails.
+     */
+    pub fn process(&mut self, encoded_msg: &mut [u8]) -> Result<Incoming> {
+        let decoded = SnmpMsg::decode(encoded_msg).ok().and_then(|msg| {
+            let security_params = SecurityParams::decode(msg.security_params()).ok()?;
+            Some((msg, security_params))
+        });
+        let (mut msg, security_params) = match decoded {
+            Some(decoded) => decoded,
+            None => return Ok(self.drop_msg(ReportKind::Other)),
+        };
+
+        let engine_id = security_params.engine_id();
+        let is_local = engine_id == self.engine_id.as_slice();
+        if msg.is_reportable() && !is_local {
+            // Discovery requests, and messages for another engine, are answered with the local
+            // engine ID, boots and time.
+            return self.reject(&msg, &security_params, ReportKind::UnknownEngineId);
+        }
+
+        let key = (engine_id.to_vec(), security_params.username().to_vec());
+        let (engine_boots, engine_time, is_auth, is_priv) = match self.users.get(&key) {
+            Some(keys) if is_local => (
+                self.engine_boots,
+                self.engine_time(),
+                keys.is_auth(),
+                keys.is_priv(),
+            ),
+            Some(keys) => (
+                keys.engine_boots(),
+                keys.engine_time(),
+                keys.is_auth(),
+                keys.is_priv(),
+            ),
+            None => return self.reject(&msg, &security_params, ReportKind::UnknownUserName),
+        };
+
+        if msg.is_auth() != is_auth || msg.is_private() != is_priv {
+            return self.reject(&msg, &security_params, ReportKind::UnsupportedSecLevel);
+        }
+
+        if is_auth {
+            let authenticated = self.users[&key].authenticate(
+                encoded_msg,
+                &self.engine_id,
+                engine_boots,
+                engine_time,
+            );
+            let kind = match authenticated {
+                Ok(()) => None,
+                Err(SecurityError::WrongAuthParams) => Some(ReportKind::WrongDigest),
+                Err(SecurityError::NotInTimeWindow) => Some(ReportKind::NotInTimeWindow),
+                Err(_) => return Ok(self.drop_msg(ReportKind::Other)),
+            };
+            if let Some(kind) = kind {
+                return self.reject(&msg, &security_params, kind);
+            }
+
+            // A non-authoritative engine keeps the most recent boots and time it authenticated
+            // (RFC 3414, section 3.2, step 7b).
+            let msg_boots = security_params.engine_boots();
+            let msg_time = security_params.engine_time();
+            if !is_local
+                && (msg_boots > engine_boots
+                    || (msg_boots == engine_boots && msg_time > engine_time))
+            {
+                if let Some(keys) = self.users.get_mut(&key) {
+                    keys.set_engine_time(msg_boots, msg_time);
+                }
+            }
+        }
+
+        if self.users[&key]
+            .decrypt(&mut msg, &security_params)
+            .is_err()
+        {
+            return self.reject(&msg, &security_params, ReportKind::DecryptionError);
+        }
+
+        Ok(Incoming::Accepted(Box::new(Accepted {
+            msg,
+            security_params,
+        })))
+    }
+
+    /**
+     * Encodes the response to an accepted message sent to the local engine, at the security level
+     * of the request.
+     */
+    pub fn encode_response(
+        &mut self,
+        request: &Accepted,
+        response: &mut SnmpMsg,
+    ) -> Result<Vec<u8>> {
+        let (engine_boots, engine_time) = (self.engine_boots, self.engine_time());
+        let key = (
+            request.security_params.engine_id().to_vec(),
+            request.security_params.username().to_vec(),
+        );
+
+        match self.users.get_mut(&key) {
+            Some(keys) => keys.encode(response, engine_boots, engine_time, true),
+            None => Err(Error::config("no keys for the user of the request")),
+        }
+    }
+
+    fn drop_msg(&mut self, kind: ReportKind) -> Incoming {
+        *self.stats.entry(kind).or_insert(0) += 1;
+        Incoming::Dropped { report: None }
+    }
+
+    // Counts the dropped message and builds a Report PDU with the counter of `kind` if the sender
+    // asked for reports. Only `usmStatsNotInTimeWindows` reports are authenticated, as the sender
+    // needs to trust the boots and time they carry (RFC 3414, section 3.2, step 7a).
+    fn reject(
+        &mut self,
+        msg: &SnmpMsg,
+        security_params: &SecurityParams,
+        kind: ReportKind,
+    ) -> Result<Incoming> {
+        self.drop_msg(kind);
+        let counter_oid = match kind.oid() {
+            Some(counter_oid) if msg.is_reportable() => counter_oid,
+            _ => return Ok(Incoming::Dropped { report: None }),
+        };
+
+        let request_id = msg
+            .scoped_pdu_data
+            .plaintext()
+            .map(|scoped_pdu| scoped_pdu.request_id())
+            .unwrap_or(0);
+
+        let mut report = SnmpMsg::new(msg.id());
+        if let Some(scoped_pdu) = report.scoped_pdu_data.plaintext_mut() {
+            scoped_pdu
+                .set_pdu_type(PduType::Report)
+                .set_request_id(request_id)
+                .set_engine_id(&self.engine_id)
+                .push_var_bind(VarBind::with_value(
+                    ObjectIdent::new(counter_oid),
+                    VarValue::Counter(self.dropped(kind)),
+                ));
+        }
+
+        let (engine_boots, engine_time) = (self.engine_boots, self.engine_time());
+        let key = (
+            security_params.engine_id().to_vec(),
+            security_params.username().to_vec(),
+        );
+        let encoded_report = match self.users.get_mut(&key) {
+            Some(keys) if kind == ReportKind::NotInTimeWindow => {
+                keys.encode(&mut report, engine_boots, engine_time, false)?
+            }
+            _ => {
+                let mut report_params = SecurityParams::new();
+                report_params
+                    .set_engine_id(&self.engine_id)
+                    .set_engine_boots(engine_boots)
+                    .set_engine_time(engine_time)
+                    .set_username(security_params.username());
+                report.set_security_params(&report_params.encode());
+                report.encode()
+            }
+        };
+
+        Ok(Incoming::Dropped {
+            report: Some(encoded_report),
+        })
+    }
+}
+
+// Object safe view of a user's keys localized to one engine, so the digest and privacy types
+// chosen at runtime don't leak into `LocalEngine`. The session also holds the latest boots and
+// time received from the engine.
+trait UserKeys: Send {
+    fn is_auth(&self) -> bool;
+    fn is_priv(&self) -> bool;
+    fn engine_boots(&self) -> u32;
+    fn engine_time(&self) -> u32;
+    fn set_engine_time(&mut self, engine_boots: u32, engine_time: u32);
+    fn authenticate(
+        &self,
+        encoded_msg: &mut [u8],
+        local_engine_id: &[u8],
+        engine_boots: u32,
+        engine_time: u32,
+    ) -> std::result::Result<(), SecurityError>;
+    fn decrypt(&self, msg: &mut SnmpMsg, security_params: &SecurityParams) -> Result<()>;
+    fn encode(
+        &mut self,
+        msg: &mut SnmpMsg,
+        engine_boots: u32,
+        engine_time: u32,
+        encrypt: bool,
+    ) -> Result<Vec<u8>>;
+}
+
+impl<D, P, S> UserKeys for Session<'static, D, P, S>
+where
+    D: Digest + Send + Sync,
+    P: PrivKey<Salt = S> + Send,
+    S: Step + Copy + Send,
+{
+    fn is_auth(&self) -> bool {
+        self.auth_key().is_some()
+    }
+
+    fn is_priv(&self) -> bool {
+        self.priv_key().is_some()
+    }
+
+    fn engine_boots(&self) -> u32 {
+        Session::engine_boots(self)
+    }
+
+    fn engine_time(&self) -> u32 {
+        Session::engine_time(self)
+    }
+
+    fn set_engine_time(&mut self, engine_boots: u32, engine_time: u32) {
+        self.set_engine_boots(engine_boots)
+            .set_engine_time(engine_time);
+    }
+
+    fn authenticate(
+        &self,
+        encoded_msg: &mut [u8],
+        local_engine_id: &[u8],
+        engine_boots: u32,
+        engine_time: u32,
+    ) -> std::result::Result<(), SecurityError> {
+        match self.auth_key() {
+            Some(auth_key) => {
+                auth_key.auth_in_msg(encoded_msg, local_engine_id, engine_boots, engine_time)
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn decrypt(&self, msg: &mut SnmpMsg, security_params: &SecurityParams) -> Result<()> {
+        if let Some(priv_key) = self.priv_key() {
+            msg.decrypt_scoped_pdu(|encrypted_scoped_pdu| {
+                priv_key.decrypt(encrypted_scoped_pdu, security_params).ok()
+            })?;
+        }
+
+        Ok(())
+    }
+
+    fn encode(
+        &mut self,
+        msg: &mut SnmpMsg,
+        engine_boots: u32,
+        engine_time: u32,
+        encrypt: bool,
+    ) -> Result<Vec<u8>> {
+        // Responses and reports carry the receiver's boots and time, as it's the authoritative
+        // engine.
+        let (saved_boots, saved_time) = (Session::engine_boots(self), Session::engine_time(self));
+        UserKeys::set_engine_time(self, engine_boots, engine_time);
+        let encoded_msg = client::encode_msg_with_privacy(msg, self, encrypt);
+        UserKeys::set_engine_time(self, saved_boots, saved_time);
+
+        encoded_msg
+    }
+}
+
+fn localize<D, P, S>(user: &UsmUser, engine_id: &[u8], salt: P::Salt) -> Result<Box<dyn UserKeys>>
+where
+    D: 'static + Digest + Send + Sync,
+    P: 'static + PrivKey<Salt = S> + WithPasswd<'static, D> + Send,
+    S: 'static + Step + Copy + Send,
+{
+    let mut session = Session::<D, P, S>::undiscovered();
+    session
+        .set_username(user.user.as_bytes())
+        .set_engine_id(engine_id)
+        .set_passwords(user.auth.as_deref(), user.privacy.as_deref(), salt)?;
+
+    Ok(Box::new(session))
+}
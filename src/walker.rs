@@ -0,0 +1,432 @@
+use crate::auth::Digest;
+use crate::client::Client;
+use crate::error::{Error, PduError, Result};
+use crate::msg_factory;
+use crate::oids::{FindOidName, OidMap, OID};
+use crate::params::SnmpResult;
+use crate::request::{
+    check_error_status, fit_max_repetitions, get_var_binds, next_sibling, var_bind_to_snmp_result,
+    walk_start,
+};
+use crate::session::{Session, Step};
+use snmp_mp::{ObjectIdent, PduErrorStatus, PduType, SnmpMsg, VarBind, VarValue};
+use snmp_usm::PrivKey;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[cfg(feature = "async")]
+use crate::async_client::AsyncClient;
+#[cfg(feature = "async")]
+use futures_core::Stream;
+#[cfg(feature = "async")]
+use std::pin::Pin;
+
+/**
+ * Iterator over the subtree rooted at an OID.
+ *
+ * Requests are only sent when the results of the previous one have been consumed, so memory use
+ * doesn't grow with the size of the subtree. The walk uses GetNext requests, or GetBulk ones
+ * after `bulk` was called. It stops at the end of the subtree, after `max_results` results, when
+ * it's cancelled, or after yielding an error.
+*/
+pub struct Walker<'w, 'a, D, P, S> {
+    client: &'w mut Client,
+    session: &'w mut Session<'a, D, P, S>,
+    state: WalkState<'w>,
+}
+
+impl<'w, 'a, D, P, S> Walker<'w, 'a, D, P, S>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    pub fn new(
+        oid_map: &'w OidMap,
+        oid: OID,
+        client: &'w mut Client,
+        session: &'w mut Session<'a, D, P, S>,
+    ) -> Result<Self> {
+        let host = client.socket.peer_addr()?.to_string();
+
+        Ok(Self {
+            client,
+            session,
+            state: WalkState::new(oid_map, &oid, host)?,
+        })
+    }
+
+    /**
+     * Walks with GetBulk requests asking for `max_repetitions` OIDs at a time.
+     */
+    pub fn bulk(mut self, max_repetitions: u32) -> Self {
+        self.state.max_repetitions = Some(max_repetitions.max(1));
+        self
+    }
+
+    /**
+     * Stops the walk after `max_results` results.
+     */
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.state.max_results = Some(max_results);
+        self
+    }
+
+    /**
+     * Returns a handle that stops the walk from another thread.
+     */
+    pub fn cancel_handle(&self) -> WalkCancel {
+        self.state.cancel.clone()
+    }
+
+    pub fn cancel(&mut self) {
+        self.state.cancel.cancel();
+    }
+}
+
+impl<'w, 'a, D, P, S> Iterator for Walker<'w, 'a, D, P, S>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    type Item = Result<SnmpResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.state.pop() {
+                return Some(item);
+            }
+
+            if self.state.done {
+                return None;
+            }
+
+            let response = self
+                .state
+                .request_msg(self.session)
+                .and_then(|mut msg| self.client.send_request(&mut msg, self.session));
+            if let Err(error) = response.and_then(|response| self.state.push_response(&response)) {
+                self.state.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+/**
+ * Asynchronous version of `Walker`, as a `Stream`.
+*/
+#[cfg(feature = "async")]
+pub struct AsyncWalker<'w, 'a, D, P, S> {
+    client: &'w mut AsyncClient,
+    session: &'w mut Session<'a, D, P, S>,
+    state: WalkState<'w>,
+}
+
+#[cfg(feature = "async")]
+impl<'w, 'a, D, P, S> AsyncWalker<'w, 'a, D, P, S>
+where
+    D: Digest,
+    P: PrivKey<Salt = S>,
+    S: Step + Copy,
+{
+    pub fn new(
+        oid_map: &'w OidMap,
+        oid: OID,
+        client: &'w mut AsyncClient,
+        session: &'w mut Session<'a, D, P, S>,
+    ) -> Result<Self> {
+        let host = client.socket.peer_addr()?.to_string();
+
+        Ok(Self {
+            client,
+            session,
+            state: WalkState::new(oid_map, &oid, host)?,
+        })
+    }
+
+    pub fn bulk(mut self, max_repetitions: u32) -> Self {
+        self.state.max_repetitions = Some(max_repetitions.max(1));
+        self
+    }
+
+    pub fn max_results(mut self, max_results: usize) -> Self {
+        self.state.max_results = Some(max_results);
+        self
+    }
+
+    pub fn cancel_handle(&self) -> WalkCancel {
+        self.state.cancel.clone()
+    }
+
+    /**
+     * Returns the next result, or `None` once the walk is over.
+     */
+    pub async fn next(&mut self) -> Option<Result<SnmpResult>> {
+        loop {
+            if let Some(item) = self.state.pop() {
+                return Some(item);
+            }
+
+            if self.state.done {
+                return None;
+            }
+
+            let response = match self.state.request_msg(self.session) {
+                Ok(mut msg) => self.client.send_request(&mut msg, self.session).await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = response.and_then(|response| self.state.push_response(&response)) {
+                self.state.done = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'w, 'a, D, P, S> AsyncWalker<'w, 'a, D, P, S>
+where
+    D: 'w + Digest + Send + Sync,
+    P: 'w + PrivKey<Salt = S> + Send,
+    S: 'w + Step + Copy + Send,
+    'a: 'w,
+{
+    /**
+     * Turns the walker into a `Stream` of results.
+     */
+    pub fn into_stream(self) -> Pin<Box<dyn Stream<Item = Result<SnmpResult>> + Send + 'w>> {
+        Box::pin(futures_util::stream::unfold(
+            self,
+            |mut walker| async move {
+                let item = walker.next().await?;
+                Some((item, walker))
+            },
+        ))
+    }
+}
+
+/**
+ * Handle to cancel a walk, possibly from another thread. The walk ends before its next request.
+*/
+#[derive(Debug, Clone, Default)]
+pub struct WalkCancel {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl WalkCancel {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// Progress of a walk, independent of how requests are sent.
+struct WalkState<'m> {
+    oid_map: &'m OidMap,
+    host: String,
+    end_oid: ObjectIdent,
+    last_oid: ObjectIdent,
+    max_repetitions: Option<u32>,
+    max_results: Option<usize>,
+    returned: usize,
+    buffered: VecDeque<SnmpResult>,
+    done: bool,
+    cancel: WalkCancel,
+}
+
+impl<'m> WalkState<'m> {
+    fn new(oid_map: &'m OidMap, oid: &OID, host: String) -> Result<Self> {
+        let root = walk_start(oid)?.remove(0).name().clone();
+
+        Ok(Self {
+            oid_map,
+            host,
+            end_oid: next_sibling(&root),
+            last_oid: root,
+            max_repetitions: None,
+            max_results: None,
+            returned: 0,
+            buffered: VecDeque::new(),
+            done: false,
+            cancel: WalkCancel::default(),
+        })
+    }
+
+    fn pop(&mut self) -> Option<Result<SnmpResult>> {
+        let capped = self
+            .max_results
+            .is_some_and(|max_results| self.returned >= max_results);
+        if capped || self.cancel.is_cancelled() {
+            self.done = true;
+            self.buffered.clear();
+            return None;
+        }
+
+        let result = self.buffered.pop_front()?;
+        self.returned += 1;
+        Some(Ok(result))
+    }
+
+    fn request_msg<D, P, S>(&self, session: &mut Session<D, P, S>) -> Result<SnmpMsg> {
+        let var_binds = vec![VarBind::new(self.last_oid.clone())];
+        match self.max_repetitions {
+            Some(max_repetitions) => {
+                msg_factory::create_bulk_request_msg(var_binds, 0, max_repetitions, session)
+            }
+            None => Ok(msg_factory::create_request_msg(
+                PduType::GetNextRequest,
+                var_binds,
+                session,
+            )),
+        }
+    }
+
+    fn resolve(&self, oid: &ObjectIdent) -> String {
+        self.oid_map
+            .find_oid_name(&oid.to_string())
+            .unwrap_or_else(|| oid.to_string())
+    }
+
+    fn push_response(&mut self, response: &SnmpMsg) -> Result<()> {
+        if let Some(max_repetitions) = self.max_repetitions {
+            let error_status = response
+                .scoped_pdu_data
+                .plaintext()
+                .map(|scoped_pdu| scoped_pdu.error_status());
+            if error_status == Some(PduErrorStatus::TooBig) {
+                if max_repetitions <= 1 {
+                    return Err(PduError {
+                        status: PduErrorStatus::TooBig,
+                        index: 0,
+                        oid: None,
+                    }
+                    .into());
+                }
+
+                // Nothing is buffered, so the next call asks again with fewer repetitions.
+                self.max_repetitions = Some(max_repetitions / 2);
+                return Ok(());
+            }
+
+            self.max_repetitions = Some(fit_max_repetitions(response, max_repetitions, 1));
+        }
+
+        let name = self.resolve(&self.last_oid);
+        check_error_status(response, &[name])?;
+
+        let var_binds = match get_var_binds(response) {
+            Some(var_binds) if !var_binds.is_empty() => var_binds,
+            _ => {
+                self.done = true;
+                return Ok(());
+            }
+        };
+
+        for var in var_binds {
+            if var.name() >= &self.end_oid || var.value() == &VarValue::EndOfMibView {
+                self.done = true;
+                return Ok(());
+            }
+
+            if var.name() <= &self.last_oid {
+                return Err(Error::protocol(format!(
+                    "OID not increasing: {}",
+                    var.name()
+                )));
+            }
+
+            let name = self.resolve(var.name());
+            self.buffered.push_back(var_bind_to_snmp_result(
+                self.host.clone(),
+                name,
+                &self.last_oid,
+                var.clone(),
+            )?);
+            self.last_oid = var.name().clone();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::tests::response_with;
+
+    fn if_descr(index: u64) -> VarBind {
+        let name = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 2, index]);
+        VarBind::with_value(name, VarValue::String(format!("eth{}", index).into_bytes()))
+    }
+
+    fn if_descr_map() -> OidMap {
+        OidMap {
+            oids: vec![OID {
+                oid: "1.3.6.1.2.1.2.2.1.2".to_string(),
+                name: "ifDescr".to_string(),
+                ..Default::default()
+            }],
+        }
+    }
+
+    fn if_descr_walk(oid_map: &OidMap) -> WalkState<'_> {
+        WalkState::new(oid_map, &oid_map.oids[0], "agent".to_string()).unwrap()
+    }
+
+    #[test]
+    fn it_stops_at_the_end_of_the_subtree() {
+        let oid_map = if_descr_map();
+        let mut state = if_descr_walk(&oid_map);
+        let if_type = ObjectIdent::from_slice(&[1, 3, 6, 1, 2, 1, 2, 2, 1, 3, 1]);
+        let response = response_with(vec![
+            if_descr(1),
+            if_descr(2),
+            VarBind::with_value(if_type, VarValue::Int(6)),
+        ]);
+        state.push_response(&response).unwrap();
+
+        let results: Vec<SnmpResult> = std::iter::from_fn(|| state.pop())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].oid, "ifDescr.1");
+        assert_eq!(results[1].requested_oid, "1.3.6.1.2.1.2.2.1.2.1");
+        assert!(state.done);
+    }
+
+    #[test]
+    fn it_honours_max_results_and_cancellation() {
+        let oid_map = if_descr_map();
+        let mut state = if_descr_walk(&oid_map);
+        state.max_results = Some(1);
+        state
+            .push_response(&response_with(vec![if_descr(1), if_descr(2)]))
+            .unwrap();
+        assert!(state.pop().is_some());
+        assert!(state.pop().is_none());
+        assert!(state.done);
+
+        let oid_map = if_descr_map();
+        let mut state = if_descr_walk(&oid_map);
+        state
+            .push_response(&response_with(vec![if_descr(1)]))
+            .unwrap();
+        state.cancel.cancel();
+        assert!(state.pop().is_none());
+        assert!(state.done);
+    }
+
+    #[test]
+    fn it_rejects_non_increasing_oids() {
+        let oid_map = if_descr_map();
+        let mut state = if_descr_walk(&oid_map);
+        let response = response_with(vec![if_descr(2), if_descr(1)]);
+        assert!(state.push_response(&response).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// Mock SNMPv3 agent serving a fixture MIB over loopback UDP, shared by the integration tests.
+
+use k0hax_snmpv3::oids::{OidMap, OID};
+use k0hax_snmpv3::params::{ObjectIdentifier, SnmpValue};
+use k0hax_snmpv3::{
+    Agent, AuthProtocol, Command, MibTable, Params, PrivProtocol, RetryPolicy, UsmUser,
+};
+use snmp_mp::ObjectIdent;
+use std::str::FromStr;
+use std::thread;
+
+pub const SYS_DESCR: &str = "1.3.6.1.2.1.1.1.0";
+pub const SYS_OBJECT_ID: &str = "1.3.6.1.2.1.1.2.0";
+pub const SYS_UP_TIME: &str = "1.3.6.1.2.1.1.3.0";
+pub const SYS_CONTACT: &str = "1.3.6.1.2.1.1.4.0";
+pub const SYS_NAME: &str = "1.3.6.1.2.1.1.5.0";
+pub const IF_TABLE: &str = "1.3.6.1.2.1.2.2";
+pub const IF_COUNT: usize = 3;
+// ifIndex, ifDescr, ifType and ifPhysAddress.
+pub const IF_COLUMNS: usize = 4;
+
+pub const AUTH_PASSWD: &str = "maplesyrup";
+pub const PRIV_PASSWD: &str = "pancakes1";
+
+/**
+ * Agent answering on a loopback port from a background thread, for as long as the test runs.
+*/
+pub struct MockAgent {
+    pub host: String,
+    pub engine_id: Vec<u8>,
+    pub engine_boots: u32,
+}
+
+impl MockAgent {
+    /**
+     * Starts an agent serving the fixture MIB to every user returned by `users`.
+     */
+    pub fn start() -> Self {
+        let mut agent = fixture_agent("127.0.0.1:0", &Agent::random_engine_id(), 1);
+
+        let mock_agent = Self {
+            host: agent.local_addr().unwrap().to_string(),
+            engine_id: agent.engine_id().to_vec(),
+            engine_boots: agent.engine_boots(),
+        };
+        thread::spawn(move || agent.run());
+
+        mock_agent
+    }
+
+    /**
+     * Returns the parameters for running `cmd` against the agent as `user`.
+     */
+    pub fn params(&self, user: &UsmUser, cmd: Command) -> Params {
+        Params {
+            user: user.user.clone(),
+            host: self.host.clone(),
+            auth: user.auth.clone(),
+            auth_protocol: user.auth_protocol,
+            privacy: user.privacy.clone(),
+            privacy_protocol: user.privacy_protocol,
+            local_addr: None,
+            retry: RetryPolicy::default(),
+            cmd,
+        }
+    }
+}
+
+/**
+ * Returns an agent bound to `addr`, serving the fixture MIB to every user returned by `users`.
+*/
+pub fn fixture_agent(addr: &str, engine_id: &[u8], engine_boots: u32) -> Agent {
+    let mut agent = Agent::with_engine_id(addr, engine_id, engine_boots).unwrap();
+    for user in users() {
+        agent.add_user(&user).unwrap();
+    }
+    agent
+        .register("1.3.6.1.2.1.1", system())
+        .unwrap()
+        .register("1.3.6.1.2.1.2", interfaces())
+        .unwrap();
+
+    agent
+}
+
+/**
+ * Returns the users known to the mock agent: one without security, then one per combination of
+ * the MD5 and SHA digests with no privacy, DES and AES.
+*/
+pub fn users() -> Vec<UsmUser> {
+    let mut users = vec![UsmUser {
+        user: "noauth".to_string(),
+        auth: None,
+        auth_protocol: None,
+        privacy: None,
+        privacy_protocol: None,
+    }];
+
+    for auth_protocol in [AuthProtocol::Md5, AuthProtocol::Sha1, AuthProtocol::Sha256] {
+        for privacy_protocol in [None, Some(PrivProtocol::Des), Some(PrivProtocol::Aes128)] {
+            users.push(UsmUser {
+                user: match privacy_protocol {
+                    Some(privacy_protocol) => format!("{:?}-{:?}", auth_protocol, privacy_protocol),
+                    None => format!("{:?}-nopriv", auth_protocol),
+                }
+                .to_lowercase(),
+                auth: Some(AUTH_PASSWD.to_string()),
+                auth_protocol: Some(auth_protocol),
+                privacy: privacy_protocol.map(|_| PRIV_PASSWD.to_string()),
+                privacy_protocol,
+            });
+        }
+    }
+
+    users
+}
+
+/**
+ * Returns the user authenticating with `auth_protocol` and encrypting with `privacy_protocol`.
+*/
+pub fn user(auth_protocol: AuthProtocol, privacy_protocol: Option<PrivProtocol>) -> UsmUser {
+    users()
+        .into_iter()
+        .find(|x| x.auth_protocol == Some(auth_protocol) && x.privacy_protocol == privacy_protocol)
+        .unwrap()
+}
+
+pub fn oid(oid: &str) -> OID {
+    OID {
+        oid: oid.to_string(),
+        name: oid.to_string(),
+        ..Default::default()
+    }
+}
+
+pub fn oid_map() -> OidMap {
+    OidMap {
+        oids: vec![
+            OID {
+                oid: "1.3.6.1.2.1.1".to_string(),
+                name: "system".to_string(),
+                ..Default::default()
+            },
+            OID {
+                oid: IF_TABLE.to_string(),
+                name: "ifTable".to_string(),
+                ..Default::default()
+            },
+        ],
+    }
+}
+
+fn system() -> MibTable {
+    let mut system = MibTable::new().writable(true);
+    system
+        .insert(
+            ident(SYS_DESCR),
+            SnmpValue::OctetString(b"k0hax-snmpv3 mock agent".to_vec()),
+        )
+        .insert(
+            ident(SYS_OBJECT_ID),
+            SnmpValue::ObjectId(ObjectIdentifier {
+                components: vec![1, 3, 6, 1, 4, 1, 8072, 3, 2, 10],
+            }),
+        )
+        .insert(ident(SYS_UP_TIME), SnmpValue::TimeTicks(123_456))
+        .insert(
+            ident(SYS_CONTACT),
+            SnmpValue::OctetString(b"noc@example.com".to_vec()),
+        )
+        .insert(ident(SYS_NAME), SnmpValue::OctetString(b"mock".to_vec()));
+
+    system
+}
+
+fn interfaces() -> MibTable {
+    let mut interfaces = MibTable::new();
+    for index in 1..=IF_COUNT as u8 {
+        let column = |column: u8| ident(&format!("{}.1.{}.{}", IF_TABLE, column, index));
+        interfaces
+            .insert(column(1), SnmpValue::Int(index.into()))
+            .insert(
+                column(2),
+                SnmpValue::OctetString(format!("eth{}", index - 1).into_bytes()),
+            )
+            .insert(column(3), SnmpValue::Int(6))
+            .insert(
+                column(6),
+                SnmpValue::OctetString(vec![0x02, 0, 0, 0, 0, index]),
+            );
+    }
+
+    interfaces
+}
+
+fn ident(oid: &str) -> ObjectIdent {
+    ObjectIdent::from_str(oid).unwrap()
+}
@@ -0,0 +1,278 @@
+mod common;
+
+use common::{
+    fixture_agent, oid, oid_map, user, users, MockAgent, AUTH_PASSWD, IF_COLUMNS, IF_COUNT,
+    IF_TABLE, PRIV_PASSWD, SYS_CONTACT, SYS_DESCR, SYS_NAME, SYS_UP_TIME,
+};
+use k0hax_snmpv3::client::Client;
+use k0hax_snmpv3::error::ReportKind;
+use k0hax_snmpv3::params::SnmpValue;
+use k0hax_snmpv3::request;
+use k0hax_snmpv3::session::Session;
+use k0hax_snmpv3::{
+    run, Agent, AuthProtocol, Command, Error, Outcome, PrivProtocol, RetryPolicy, SnmpSession,
+};
+use snmp_mp::{PduErrorStatus, PduType};
+use snmp_usm::{Aes128PrivKey, DesPrivKey, Md5, Sha1};
+use std::net::UdpSocket;
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn it_gets_with_every_security_level() {
+    let agent = MockAgent::start();
+
+    for user in users() {
+        let cmd = Command::Get {
+            oids: vec![oid(SYS_DESCR), oid(SYS_UP_TIME)],
+        };
+        let results = run(oid_map(), agent.params(&user, cmd))
+            .unwrap_or_else(|error| panic!("{} failed: {:?}", user.user, error));
+
+        assert_eq!(results.len(), 2, "{}", user.user);
+        assert_eq!(
+            results[0].value(),
+            Some(&SnmpValue::OctetString(b"k0hax-snmpv3 mock agent".to_vec()))
+        );
+        assert_eq!(results[1].value(), Some(&SnmpValue::TimeTicks(123_456)));
+    }
+}
+
+#[test]
+fn it_walks_the_fixture_tree() {
+    let agent = MockAgent::start();
+    let user = user(AuthProtocol::Sha1, Some(PrivProtocol::Aes128));
+
+    let walk = Command::Walk { oid: oid(IF_TABLE) };
+    let walked = run(oid_map(), agent.params(&user, walk)).unwrap();
+    assert_eq!(walked.len(), IF_COUNT * IF_COLUMNS);
+    assert_eq!(walked[0].returned_oid, "1.3.6.1.2.1.2.2.1.1.1");
+    assert_eq!(walked[IF_COUNT].returned_oid, "1.3.6.1.2.1.2.2.1.2.1");
+    assert_eq!(
+        walked.last().unwrap().value(),
+        Some(&SnmpValue::OctetString(vec![0x02, 0, 0, 0, 0, 3]))
+    );
+
+    let bulk_walk = Command::BulkWalk {
+        oid: oid(IF_TABLE),
+        max_repetitions: 5,
+    };
+    let bulk_walked = run(oid_map(), agent.params(&user, bulk_walk)).unwrap();
+    let returned_oids = |results: &[k0hax_snmpv3::SnmpResult]| -> Vec<String> {
+        results.iter().map(|x| x.returned_oid.clone()).collect()
+    };
+    assert_eq!(returned_oids(&bulk_walked), returned_oids(&walked));
+}
+
+#[test]
+fn it_discovers_the_engine_and_sends_requests() {
+    let agent = MockAgent::start();
+    let user = user(AuthProtocol::Md5, Some(PrivProtocol::Des));
+
+    let mut client = Client::new(agent.host.as_str()).unwrap();
+    let mut session =
+        Session::<Md5, DesPrivKey<Md5>, u32>::new(&mut client, user.user.as_bytes()).unwrap();
+    assert_eq!(session.engine_id(), agent.engine_id.as_slice());
+    assert_eq!(session.engine_boots(), agent.engine_boots);
+    session
+        .set_passwords(Some(AUTH_PASSWD), Some(PRIV_PASSWD), 0)
+        .unwrap();
+
+    let results = request::snmp_get(
+        PduType::GetRequest,
+        vec![oid(SYS_NAME)],
+        &mut client,
+        &mut session,
+    )
+    .unwrap();
+    assert_eq!(
+        results[0].value(),
+        Some(&SnmpValue::OctetString(b"mock".to_vec()))
+    );
+
+    let walked =
+        request::snmp_walk(&oid_map(), oid("1.3.6.1.2.1.1"), &mut client, &mut session).unwrap();
+    assert_eq!(walked.len(), 5);
+    assert_eq!(walked[4].returned_oid, SYS_NAME);
+}
+
+#[test]
+fn it_reports_missing_variables_as_exceptions() {
+    let agent = MockAgent::start();
+    let user = user(AuthProtocol::Sha256, None);
+
+    let cmd = Command::Get {
+        oids: vec![oid("1.3.6.1.2.1.1.9.0"), oid("1.3.6.1.4.1.8072.1.0")],
+    };
+    let results = run(oid_map(), agent.params(&user, cmd)).unwrap();
+    assert_eq!(results[0].outcome, Outcome::NoSuchInstance);
+    assert_eq!(results[1].outcome, Outcome::NoSuchObject);
+
+    let cmd = Command::GetNext {
+        oids: vec![oid("1.3.6.1.2.1.2.2.1.6.3")],
+    };
+    let results = run(oid_map(), agent.params(&user, cmd)).unwrap();
+    assert_eq!(results[0].outcome, Outcome::EndOfMibView);
+}
+
+#[test]
+fn it_sets_writable_variables_only() {
+    let agent = MockAgent::start();
+    let user = user(AuthProtocol::Sha1, Some(PrivProtocol::Des));
+    let contact = SnmpValue::OctetString(b"ops@example.com".to_vec());
+
+    let set = Command::Set {
+        bindings: vec![(oid(SYS_CONTACT), contact.clone())],
+    };
+    run(oid_map(), agent.params(&user, set)).unwrap();
+    let get = Command::Get {
+        oids: vec![oid(SYS_CONTACT)],
+    };
+    let results = run(oid_map(), agent.params(&user, get)).unwrap();
+    assert_eq!(results[0].value(), Some(&contact));
+
+    let set = Command::Set {
+        bindings: vec![
+            (oid(SYS_CONTACT), contact),
+            (oid("1.3.6.1.2.1.2.2.1.1.1"), SnmpValue::Int(7)),
+        ],
+    };
+    match run(oid_map(), agent.params(&user, set)) {
+        Err(Error::ErrorStatus(error)) => {
+            assert_eq!(error.status, PduErrorStatus::NotWritable);
+            assert_eq!(error.index, 2);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn it_returns_reports_for_rejected_credentials() {
+    let agent = MockAgent::start();
+    let get = || Command::Get {
+        oids: vec![oid(SYS_DESCR)],
+    };
+
+    let mut wrong_auth = user(AuthProtocol::Md5, None);
+    wrong_auth.auth = Some("not the password".to_string());
+    match run(oid_map(), agent.params(&wrong_auth, get())) {
+        Err(Error::ReportPdu(report)) => assert_eq!(report.kind, ReportKind::WrongDigest),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let mut unknown = user(AuthProtocol::Sha1, Some(PrivProtocol::Aes128));
+    unknown.user = "intruder".to_string();
+    match run(oid_map(), agent.params(&unknown, get())) {
+        Err(Error::ReportPdu(report)) => assert_eq!(report.kind, ReportKind::UnknownUserName),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    // The user exists, but not with privacy.
+    let mut downgraded = user(AuthProtocol::Sha1, Some(PrivProtocol::Aes128));
+    downgraded.privacy = None;
+    downgraded.privacy_protocol = None;
+    match run(oid_map(), agent.params(&downgraded, get())) {
+        Err(Error::ReportPdu(report)) => {
+            assert_eq!(report.kind, ReportKind::UnsupportedSecLevel)
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn it_times_out_without_an_agent() {
+    // Bound but never read, so requests go unanswered.
+    let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let agent = MockAgent {
+        host: silent.local_addr().unwrap().to_string(),
+        engine_id: Vec::new(),
+        engine_boots: 0,
+    };
+    let mut params = agent.params(
+        &user(AuthProtocol::Sha1, None),
+        Command::Get {
+            oids: vec![oid(SYS_DESCR)],
+        },
+    );
+    params.retry = RetryPolicy {
+        timeout_ms: 100,
+        retries: 1,
+        ..Default::default()
+    };
+
+    assert!(matches!(run(oid_map(), params), Err(Error::Timeout(_))));
+}
+
+#[test]
+fn it_rejects_invalid_oids() {
+    let agent = MockAgent::start();
+    let cmd = Command::Get {
+        oids: vec![oid("1.3.six.1")],
+    };
+
+    let result = run(oid_map(), agent.params(&user(AuthProtocol::Md5, None), cmd));
+    assert!(matches!(result, Err(Error::InvalidOid(_))));
+
+    // A single bad OID fails the request, rather than shifting the other results.
+    let cmd = Command::Get {
+        oids: vec![oid(SYS_DESCR), oid("1.3.six.1"), oid(SYS_NAME)],
+    };
+    let result = run(oid_map(), agent.params(&user(AuthProtocol::Md5, None), cmd));
+    assert!(matches!(result, Err(Error::InvalidOid(oid)) if oid == "1.3.six.1"));
+
+    let cmd = Command::Walk { oid: oid("system") };
+    let result = run(oid_map(), agent.params(&user(AuthProtocol::Md5, None), cmd));
+    assert!(matches!(result, Err(Error::InvalidOid(oid)) if oid == "system"));
+}
+
+#[test]
+fn it_encrypts_with_aes_after_discovery() {
+    let agent = MockAgent::start();
+    let user = user(AuthProtocol::Sha1, Some(PrivProtocol::Aes128));
+
+    let mut client = Client::new(agent.host.as_str()).unwrap();
+    let mut session =
+        Session::<Sha1, Aes128PrivKey<Sha1>, u64>::new(&mut client, user.user.as_bytes()).unwrap();
+    session
+        .set_passwords(Some(AUTH_PASSWD), Some(PRIV_PASSWD), 0)
+        .unwrap();
+
+    let results =
+        request::snmp_get_next(&oid_map(), vec![oid(IF_TABLE)], &mut client, &mut session).unwrap();
+    assert_eq!(results[0].returned_oid, "1.3.6.1.2.1.2.2.1.1.1");
+    assert_eq!(results[0].value(), Some(&SnmpValue::Int(1)));
+}
+
+#[test]
+fn it_resyncs_after_the_agent_reboots() {
+    let engine_id = Agent::random_engine_id();
+    let mut agent = fixture_agent("127.0.0.1:0", &engine_id, 1);
+    let addr = agent.local_addr().unwrap().to_string();
+    let before_reboot = thread::spawn(move || agent.poll(Duration::from_millis(500)));
+
+    let user = user(AuthProtocol::Sha1, Some(PrivProtocol::Aes128));
+    let mock_agent = MockAgent {
+        host: addr.clone(),
+        engine_id: engine_id.clone(),
+        engine_boots: 1,
+    };
+    let get = Command::Get {
+        oids: vec![oid(SYS_NAME)],
+    };
+    let mut session = SnmpSession::new(&mock_agent.params(&user, get)).unwrap();
+    session.get(vec![oid(SYS_NAME)]).unwrap();
+    assert_eq!(session.engine_boots(), 1);
+    before_reboot.join().unwrap().unwrap();
+
+    // Same engine on the same port, one boot later: the request sent with the old boots gets an
+    // authenticated notInTimeWindow report, and is sent again with the new ones.
+    let mut agent = fixture_agent(&addr, &engine_id, 2);
+    thread::spawn(move || agent.run());
+
+    let results = session.get(vec![oid(SYS_NAME)]).unwrap();
+    assert_eq!(
+        results[0].value(),
+        Some(&SnmpValue::OctetString(b"mock".to_vec()))
+    );
+    assert_eq!(session.engine_boots(), 2);
+}